@@ -8,9 +8,71 @@ export const commands = {
 async errorPopup(msg: string) : Promise<void> {
     await TAURI_INVOKE("error_popup", { msg });
 },
-async getMessage(msg: string, chatId: string, providerName: string, modelName: string) : Promise<Result<string, string>> {
+async getMessage(msg: string, chatId: string, providerName: string, modelName: string, attachmentIds: string[] | null) : Promise<Result<string, string>> {
     try {
-    return { status: "ok", data: await TAURI_INVOKE("get_message", { msg, chatId, providerName, modelName }) };
+    return { status: "ok", data: await TAURI_INVOKE("get_message", { msg, chatId, providerName, modelName, attachmentIds }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+/**
+ * Cancel the generation currently running for a chat.
+ * Returns false if no generation was running.
+ */
+async cancelGeneration(chatId: string) : Promise<Result<boolean, string>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("cancel_generation", { chatId }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+/**
+ * Re-send the turn of a failed or interrupted answer, optionally to a different model.
+ * The answer is replaced by the new one.
+ */
+async retryMessage(messageId: string, providerName: string | null, modelName: string | null) : Promise<Result<string, string>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("retry_message", { messageId, providerName, modelName }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+/**
+ * Generate a new version of an assistant reply, optionally with a different model.
+ * The previous versions are kept as alternative branches, the new one becomes active.
+ */
+async regenerateMessage(messageId: string, providerName: string | null, modelName: string | null) : Promise<Result<string, string>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("regenerate_message", { messageId, providerName, modelName }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+/**
+ * Edit a message.
+ * An edited prompt is saved as a new version next to the original one and answered again, optionally with a different model.
+ * An edited answer is changed in place and rendered again. Returns the new answer or the edited content.
+ */
+async editMessage(messageId: string, content: string, providerName: string | null, modelName: string | null) : Promise<Result<string, string>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("edit_message", { messageId, content, providerName, modelName }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+/**
+ * Send a prompt to several models at once.
+ * Every answer is stored as a version of the reply to the same prompt and the first successful one becomes active,
+ * `switch_message_version` continues the chat with another one. Returns the answers in the order of `targets`.
+ */
+async compareModels(msg: string, chatId: string, targets: ModelTarget[]) : Promise<Result<Message[], string>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("compare_models", { msg, chatId, targets }) };
 } catch (e) {
     if(e instanceof Error) throw e;
     else return { status: "error", error: e  as any };
@@ -28,7 +90,221 @@ async getChats() : Promise<Result<Chats, string>> {
 }
 },
 /**
- * Load all messages for a chat, including their rendered blocks
+ * Search all chats for messages and chat names containing the words of a query.
+ * The last word also matches as prefix so results show up while typing.
+ */
+async searchChats(query: string, filters: SearchFilters | null) : Promise<Result<SearchHit[], string>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("search_chats", { query, filters }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+/**
+ * Find the chats whose messages are closest in meaning to a query, even if they use other words
+ */
+async searchSimilarChats(query: string, limit: number | null) : Promise<Result<SimilarChat[], string>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("search_similar_chats", { query, limit }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+/**
+ * Find the chats closest in meaning to a chat, compared by the average of its message vectors
+ */
+async getSimilarChats(chatId: string, limit: number | null) : Promise<Result<SimilarChat[], string>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("get_similar_chats", { chatId, limit }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+/**
+ * Number of messages embedded with the configured embedding model, out of all messages that can be embedded
+ */
+async getEmbeddingStatus() : Promise<Result<EmbeddingStatus, string>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("get_embedding_status") };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+async getCollections() : Promise<Result<Collection[], string>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("get_collections") };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+/**
+ * Create an empty collection, its documents are embedded with the embedding model configured in the settings
+ */
+async createCollection(name: string) : Promise<Result<Collection, string>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("create_collection", { name }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+async renameCollection(collectionId: string, name: string) : Promise<Result<null, string>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("rename_collection", { collectionId, name }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+/**
+ * Delete a collection with its documents and detach it from all chats
+ */
+async deleteCollection(collectionId: string) : Promise<Result<null, string>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("delete_collection", { collectionId }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+async getCollectionDocuments(collectionId: string) : Promise<Result<CollectionDocument[], string>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("get_collection_documents", { collectionId }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+/**
+ * Read, chunk and embed markdown, code, text and PDF files and add them to a collection.
+ * Files that are already part of the collection are read again and replace their previous chunks.
+ */
+async addCollectionDocuments(collectionId: string, filePaths: string[]) : Promise<Result<CollectionDocument[], string>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("add_collection_documents", { collectionId, filePaths }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+async removeCollectionDocument(documentId: string) : Promise<Result<null, string>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("remove_collection_document", { documentId }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+/**
+ * Ids of the collections attached to a chat
+ */
+async getChatCollections(chatId: string) : Promise<Result<string[], string>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("get_chat_collections", { chatId }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+/**
+ * Replace the collections attached to a chat, they can be set before the first message is sent
+ */
+async setChatCollections(chatId: string, collectionIds: string[]) : Promise<Result<null, string>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("set_chat_collections", { chatId, collectionIds }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+/**
+ * Copy the conversation up to a message into a new chat, including the rendered blocks, attachments, system prompt, parameters and collections.
+ * Only the branch leading to the message is copied. Returns the id of the new chat.
+ */
+async forkChat(messageId: string) : Promise<Result<string, string>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("fork_chat", { messageId }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+/**
+ * Export a chat to a file chosen by the user. Returns the path of the file, `None` if the user cancelled.
+ */
+async exportChat(chatId: string, format: ExportFormat) : Promise<Result<string | null, string>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("export_chat", { chatId, format }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+/**
+ * Export several chats, all chats if no ids are given.
+ * JSON exports are written to one file, Markdown and HTML exports to one file per chat in a folder chosen by the user.
+ * Returns the path of the file or folder, `None` if the user cancelled.
+ */
+async exportChats(chatIds: string[] | null, format: ExportFormat) : Promise<Result<string | null, string>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("export_chats", { chatIds, format }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+/**
+ * Import the chats of an export file, the user picks the file if no path is given.
+ * An `importProgress` event is emitted after every chat. Returns the final counts, `None` if the user cancelled.
+ */
+async importChats(filePath: string | null) : Promise<Result<ImportProgress | null, string>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("import_chats", { filePath }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+/**
+ * Get the token usage and cost of a chat
+ */
+async getChatUsage(chatId: string) : Promise<Result<UsageSummary, string>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("get_chat_usage", { chatId }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+/**
+ * Get the token usage and cost per provider, optionally limited to a date range
+ */
+async getUsageByProvider(since: string | null, until: string | null) : Promise<Result<UsageSummary[], string>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("get_usage_by_provider", { since, until }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+/**
+ * Get the token usage and cost per day, optionally limited to a date range
+ */
+async getUsageByDay(since: string | null, until: string | null) : Promise<Result<UsageSummary[], string>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("get_usage_by_day", { since, until }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+/**
+ * Load the messages of the active branch of a chat, including their rendered blocks and attachments
  */
 async loadChat(chatId: string) : Promise<Result<Message[], string>> {
     try {
@@ -38,6 +314,52 @@ async loadChat(chatId: string) : Promise<Result<Message[], string>> {
     else return { status: "error", error: e  as any };
 }
 },
+/**
+ * List all versions of a message: the message itself and the alternatives that share its parent, oldest first
+ */
+async getMessageVersions(messageId: string) : Promise<Result<Message[], string>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("get_message_versions", { messageId }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+/**
+ * Show another version of a message.
+ * The chat continues with the most recent messages that follow the selected version.
+ */
+async switchMessageVersion(messageId: string) : Promise<Result<null, string>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("switch_message_version", { messageId }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+/**
+ * Copy a file into the attachments folder, the text of text, code and PDF files is extracted and stored with it.
+ * The attachment is linked to a message once the message is sent.
+ */
+async addAttachment(filePath: string) : Promise<Result<Attachment, string>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("add_attachment", { filePath }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+/**
+ * Remove an attachment that has not been sent yet
+ */
+async removeAttachment(attachmentId: string) : Promise<Result<null, string>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("remove_attachment", { attachmentId }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
 /**
  * Load all provider configurations
  */
@@ -137,6 +459,43 @@ async updateModel(update: ModelUpdate) : Promise<Result<null, string>> {
     else return { status: "error", error: e  as any };
 }
 },
+/**
+ * Sync the models installed on the Ollama server into the models table.
+ * New models are added, known ones get their context size updated and are shown again if they were reinstalled,
+ * models that are no longer installed are hidden.
+ * Returns the installed models.
+ */
+async refreshOllamaModels() : Promise<Result<Models, string>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("refresh_ollama_models") };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+/**
+ * List the models offered by a provider's model endpoint, marking the ones that are already imported.
+ * Imported models the provider no longer lists are flagged as deprecated.
+ */
+async listProviderModels(providerName: string) : Promise<Result<AvailableModel[], string>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("list_provider_models", { providerName }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+/**
+ * Add models listed by `list_provider_models` to the models table, models that already exist are skipped
+ */
+async importModels(providerName: string, models: AvailableModel[]) : Promise<Result<null, string>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("import_models", { providerName, models }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
 /**
  * Delete a model
  */
@@ -182,7 +541,7 @@ async archiveChat(chatId: string) : Promise<Result<null, string>> {
 }
 },
 /**
- * Permanently delete a chat
+ * Permanently delete a chat, including its attached files
  */
 async deleteChat(chatId: string) : Promise<Result<null, string>> {
     try {
@@ -192,6 +551,108 @@ async deleteChat(chatId: string) : Promise<Result<null, string>> {
     else return { status: "error", error: e  as any };
 }
 },
+/**
+ * Get the generation parameter overrides of a chat
+ */
+async getChatParameters(chatId: string) : Promise<Result<ChatParameters, string>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("get_chat_parameters", { chatId }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+/**
+ * Set the generation parameter overrides of a chat.
+ * Stop sequences, seed and penalties are rejected if the provider the chat uses can't send them.
+ */
+async updateChatParameters(chatId: string, providerName: string, parameters: ChatParameters) : Promise<Result<null, string>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("update_chat_parameters", { chatId, providerName, parameters }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+/**
+ * Get the system prompt of a chat, `None` if the chat uses the global one
+ */
+async getChatSystemPrompt(chatId: string) : Promise<Result<string | null, string>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("get_chat_system_prompt", { chatId }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+/**
+ * Set the system prompt of a chat, `None` or an empty prompt falls back to the global one.
+ * Creates the chat with a placeholder name if it has no messages yet.
+ */
+async setChatSystemPrompt(chatId: string, systemPrompt: string | null) : Promise<Result<null, string>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("set_chat_system_prompt", { chatId, systemPrompt }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+/**
+ * Load all MCP server definitions
+ */
+async getMcpServers() : Promise<Result<McpServer[], string>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("get_mcp_servers") };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+/**
+ * Add an MCP server or update the one with the same name.
+ * A running server is stopped so the next chat starts it with the new definition.
+ */
+async saveMcpServer(server: McpServer) : Promise<Result<null, string>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("save_mcp_server", { server }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+/**
+ * Delete an MCP server definition and stop the server
+ */
+async deleteMcpServer(serverName: string) : Promise<Result<null, string>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("delete_mcp_server", { serverName }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+/**
+ * Start a server if needed and list its tools, resources and prompts
+ */
+async listMcpCapabilities(serverName: string) : Promise<Result<McpCapabilities, string>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("list_mcp_capabilities", { serverName }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+/**
+ * Approve or decline a tool call announced with the `toolApproval` event
+ */
+async answerToolApproval(requestId: string, approved: boolean) : Promise<Result<null, string>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("answer_tool_approval", { requestId, approved }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
 async getSettings() : Promise<Result<Settings, string>> {
     try {
     return { status: "ok", data: await TAURI_INVOKE("get_settings") };
@@ -220,12 +681,176 @@ async applyAndSaveSettings(newSettings: Settings) : Promise<Result<null, string>
 
 /** user-defined types **/
 
+/**
+ * A file attached to a message
+ */
+export type Attachment = { id: string; 
+/**
+ * "image", "text" for plain text and code, or "pdf"
+ */
+kind: string; file_name: string; mime_type: string; size_bytes: number }
+/**
+ * A model offered by a provider, as listed by its model endpoint
+ */
+export type AvailableModel = { model_name: string; display_name: string | null; 
+/**
+ * Context length, only reported by some providers
+ */
+context_window: number | null; 
+/**
+ * Whether the model is already in the models table
+ */
+imported: boolean }
 export type Chat = { id: string; display_name: string; creation_date: string; last_updated: string }
+/**
+ * Per-chat generation parameters, `None` falls back to the defaults of the chat's model
+ */
+export type ChatParameters = { temperature: number | null; top_p: number | null; max_tokens: number | null; stop_sequences: string[] | null; seed: number | null; frequency_penalty: number | null; presence_penalty: number | null }
 export type Chats = Chat[]
-export type Message = { id: string; role: string; content: string; model_name: string; blocks: MessageBlocks | null }
+/**
+ * A knowledge base of local documents that can be attached to chats
+ */
+export type Collection = { id: string; name: string; embedding_provider: string; embedding_model: string; document_count: number; chunk_count: number; created_at: string | null }
+/**
+ * A document of a collection
+ */
+export type CollectionDocument = { id: string; collection_id: string; 
+/**
+ * Absolute path the document was read from
+ */
+path: string; file_name: string; mime_type: string; size_bytes: number; chunk_count: number; created_at: string | null }
+/**
+ * Payload of the `contextTruncated` event, emitted when old messages did not fit into the context window
+ */
+export type ContextTruncated = { chat_id: string; 
+/**
+ * Number of messages that were not sent to the model
+ */
+excluded_messages: number; 
+/**
+ * Estimated number of prompt tokens that were sent
+ */
+estimated_tokens: number }
+/**
+ * Progress of embedding the messages with the configured embedding model
+ */
+export type EmbeddingStatus = { provider_name: string | null; model_name: string | null; indexed_messages: number; total_messages: number }
+/**
+ * File format of exported chats
+ */
+export type ExportFormat = 
+/**
+ * The active branch with the raw message contents
+ */
+"markdown" | 
+/**
+ * All messages with their blocks and metadata, can be imported again
+ */
+"json" | 
+/**
+ * The active branch as standalone page with the rendered blocks
+ */
+"html"
+/**
+ * Payload of the `importProgress` event, emitted after every chat of an import. The finished import returns the final counts.
+ */
+export type ImportProgress = { 
+/**
+ * "ChatGPT", "Claude" or "Byok"
+ */
+source: string; 
+/**
+ * Number of chats in the file
+ */
+total: number; imported: number; 
+/**
+ * Chats that were imported before or contain no messages
+ */
+skipped: number; failed: number }
+/**
+ * Tools, resources and prompts an MCP server offers
+ */
+export type McpCapabilities = { tools: McpTool[]; resources: McpResource[]; prompts: McpPrompt[] }
+export type McpPrompt = { name: string; description: string | null; arguments: McpPromptArgument[] }
+export type McpPromptArgument = { name: string; description: string | null; required: boolean }
+export type McpResource = { uri: string; name: string; description: string | null; mime_type: string | null }
+/**
+ * A Model Context Protocol server, started over stdio
+ */
+export type McpServer = { name: string; command: string; args?: string[]; 
+/**
+ * Environment variables set for the server process
+ */
+env?: Partial<{ [key in string]: string }>; enabled: boolean }
+export type McpTool = { name: string; description: string | null }
+export type Message = { id: string; role: string; content: string; model_name: string; 
+/**
+ * Message this one follows in the chat's message tree, `None` for the first message
+ */
+parent_id: string | null; 
+/**
+ * Provider the message was sent to, `None` for messages from before it was stored
+ */
+provider_name: string | null; 
+/**
+ * "ok", "error" when the provider request failed, or "interrupted" when the generation was cancelled
+ */
+status: string; 
+/**
+ * Milliseconds the provider took to answer, `None` for prompts
+ */
+latency_ms: number | null; 
+/**
+ * Tokens of the request that produced this answer, `None` if the provider did not report them
+ */
+prompt_tokens: number | null; completion_tokens: number | null; blocks: MessageBlocks | null; 
+/**
+ * Files attached to a prompt
+ */
+attachments?: Attachment[] }
 export type MessageBlock = { id: number | null; type_: string; language: string | null; raw_content: string; rendered_content: string; copied: boolean | null }
 export type MessageBlocks = MessageBlock[]
-export type Model = { provider_name: string; model_name: string; model_display_name: string; show: boolean; max_tokens: number; context_window: number }
+/**
+ * Payload of the `messageDelta` event, emitted while an assistant answer is streamed
+ */
+export type MessageDelta = { chat_id: string; 
+/**
+ * Id the assistant message will be saved under once the stream completes
+ */
+message_id: string; 
+/**
+ * Text received since the previous event
+ */
+delta: string; 
+/**
+ * Rendered blocks of the partial answer, only set when it was re-rendered
+ */
+blocks: MessageBlocks | null }
+export type Model = { provider_name: string; model_name: string; model_display_name: string; show: boolean; max_tokens: number; context_window: number; 
+/**
+ * Price in USD per million prompt tokens
+ */
+input_price: number | null; 
+/**
+ * Price in USD per million completion tokens
+ */
+output_price: number | null; 
+/**
+ * Set when the provider no longer lists the model
+ */
+deprecated?: boolean; 
+/**
+ * Whether the model accepts images
+ */
+supports_vision?: boolean }
+/**
+ * Payload of the `modelDeprecated` event, emitted when a chat uses a model its provider no longer lists
+ */
+export type ModelDeprecated = { chat_id: string; provider_name: string; model_name: string }
+/**
+ * A model of a provider to send a prompt to
+ */
+export type ModelTarget = { provider_name: string; model_name: string }
 /**
  * Used for updating a model, includes original keys to identify the model
  */
@@ -243,8 +868,133 @@ original_model_name: string;
  */
 model: Model }
 export type Models = Model[]
-export type ProviderData = { provider_name: string; api_key: string; display_name: string; api_key_valid: boolean; base_url: string | null; api_scheme: string | null; is_custom: boolean }
-export type Settings = { default_model: string; default_provider: string; code_theme: string }
+export type ProviderData = { provider_name: string; api_key: string; display_name: string; api_key_valid: boolean; base_url: string | null; api_scheme: string | null; is_custom: boolean; 
+/**
+ * Headers added to every request, e.g. for gateways or organization ids. Only supported for OpenAI-compatible APIs
+ */
+extra_headers?: Partial<{ [key in string]: string }>; 
+/**
+ * Query parameters added to every request, e.g. `api-version` for Azure OpenAI. Only supported for OpenAI-compatible APIs
+ */
+query_params?: Partial<{ [key in string]: string }> }
+/**
+ * Payload of the `providerRetry` event, emitted when a failed request is about to be retried
+ */
+export type ProviderRetry = { chat_id: string; provider_name: string; 
+/**
+ * Number of the attempt that failed, starting at 1
+ */
+attempt: number; max_attempts: number; 
+/**
+ * Milliseconds until the next attempt
+ */
+delay_ms: number; reason: string }
+/**
+ * Filters of a chat search, filters that are not set match everything
+ */
+export type SearchFilters = { model_name: string | null; 
+/**
+ * First day to include, as YYYY-MM-DD
+ */
+from_date: string | null; 
+/**
+ * Last day to include, as YYYY-MM-DD
+ */
+to_date: string | null; 
+/**
+ * `Some(false)` only searches active chats, `Some(true)` only archived ones
+ */
+archived: boolean | null; limit: number | null }
+/**
+ * A message or chat name matching a search, best matches first
+ */
+export type SearchHit = { chat_id: string; chat_display_name: string; 
+/**
+ * `None` if the name of the chat matched
+ */
+message_id: string | null; role: string | null; model_name: string | null; 
+/**
+ * Part of the text around the match, matched words are wrapped in `<mark>`
+ */
+snippet: string; created_at: string | null; archived: boolean }
+export type Settings = { default_model: string; default_provider: string; code_theme: string; 
+/**
+ * Default system prompt for chats without their own
+ */
+system_prompt?: string | null; 
+/**
+ * Whether models may call tools while answering
+ */
+tools_enabled?: boolean; 
+/**
+ * Files and folders the read_file tool may read
+ */
+approved_paths?: string[]; 
+/**
+ * Provider and model used to embed messages for semantic search, indexing is off while unset
+ */
+embedding_provider?: string | null; embedding_model?: string | null }
+/**
+ * A chat similar to a query or another chat, most similar first
+ */
+export type SimilarChat = { chat_id: string; chat_display_name: string; 
+/**
+ * The message of the chat closest to the query
+ */
+message_id: string; role: string; 
+/**
+ * Beginning of that message
+ */
+snippet: string; 
+/**
+ * Cosine similarity between the message and the query, from -1 to 1
+ */
+similarity: number; created_at: string | null; archived: boolean }
+/**
+ * Payload of the `toolActivity` event, emitted when a model calls a tool and again when the tool has finished
+ */
+export type ToolActivity = { chat_id: string; 
+/**
+ * Id the assistant message will be saved under
+ */
+message_id: string; tool_name: string; 
+/**
+ * Arguments as JSON object
+ */
+arguments: string; 
+/**
+ * Output of the tool, `None` while it is running
+ */
+output: string | null; is_error: boolean }
+/**
+ * Payload of the `toolApproval` event, emitted when a model wants to call a tool of an MCP server.
+ * The call runs once it is approved with `answer_tool_approval`.
+ */
+export type ToolApprovalRequest = { request_id: string; server_name: string; tool_name: string; 
+/**
+ * Arguments as JSON object
+ */
+arguments: string }
+/**
+ * Token usage and cost of a group of answers
+ */
+export type UsageSummary = { 
+/**
+ * Chat id, provider name or day (YYYY-MM-DD) the answers are grouped by
+ */
+label: string; 
+/**
+ * Number of answers with reported usage
+ */
+messages: number; prompt_tokens: number; completion_tokens: number; 
+/**
+ * Cost in USD
+ */
+cost: number; 
+/**
+ * Answers whose model has no price set, they are not included in the cost
+ */
+unpriced_messages: number }
 
 /** tauri-specta globals **/
 
@@ -0,0 +1,4 @@
+-- Add a status to messages
+-- 'ok' for complete messages, 'interrupted' for answers whose generation was cancelled
+
+ALTER TABLE messages ADD COLUMN status TEXT NOT NULL DEFAULT 'ok';
@@ -0,0 +1,15 @@
+-- Per-chat generation parameters
+-- NULL values fall back to the defaults of the model used in the chat
+
+CREATE TABLE IF NOT EXISTS chat_parameters
+(
+    chat_id TEXT NOT NULL PRIMARY KEY,
+    temperature REAL,
+    top_p REAL,
+    max_tokens INTEGER,
+    -- JSON array of strings
+    stop_sequences TEXT,
+    seed INTEGER,
+    frequency_penalty REAL,
+    presence_penalty REAL
+);
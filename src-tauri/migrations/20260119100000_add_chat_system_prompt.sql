@@ -0,0 +1,4 @@
+-- Per-chat system prompt
+-- NULL falls back to the global system prompt from the settings
+
+ALTER TABLE chats ADD COLUMN system_prompt TEXT DEFAULT NULL;
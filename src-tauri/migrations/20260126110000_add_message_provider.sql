@@ -0,0 +1,6 @@
+-- Store the provider a message was sent to, needed to re-send failed messages
+
+ALTER TABLE messages ADD COLUMN provider_name TEXT DEFAULT NULL;
+
+-- Best-effort backfill from the models table
+UPDATE messages SET provider_name = (SELECT models.provider_name FROM models WHERE models.model_name = messages.model_name LIMIT 1);
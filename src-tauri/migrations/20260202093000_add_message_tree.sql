@@ -0,0 +1,25 @@
+-- Store messages as a tree so that a prompt can have several alternative answers
+-- parent_id links a message to the message it follows, NULL for the first message of a chat
+-- active_message_id points to the last message of the branch that is shown in a chat
+
+ALTER TABLE messages ADD COLUMN parent_id TEXT DEFAULT NULL;
+ALTER TABLE chats ADD COLUMN active_message_id TEXT DEFAULT NULL;
+
+-- Messages are looked up by chat for the backfill below and whenever a chat is loaded
+-- The index holds the rowid as well, so finding the previous message of a chat is a single seek
+CREATE INDEX idx_messages_chat_id ON messages(chat_id);
+
+-- Existing chats are linear, every message follows the one inserted before it
+UPDATE messages SET parent_id = (
+    SELECT previous.id FROM messages AS previous
+    WHERE previous.chat_id = messages.chat_id AND previous.rowid < messages.rowid
+    ORDER BY previous.rowid DESC LIMIT 1
+);
+
+UPDATE chats SET active_message_id = (
+    SELECT messages.id FROM messages
+    WHERE messages.chat_id = chats.id
+    ORDER BY messages.rowid DESC LIMIT 1
+);
+
+CREATE INDEX idx_messages_parent_id ON messages(parent_id);
@@ -0,0 +1,4 @@
+-- Time in milliseconds the provider took to produce an answer
+-- NULL for user messages and for answers from before it was recorded
+
+ALTER TABLE messages ADD COLUMN latency_ms INTEGER DEFAULT NULL;
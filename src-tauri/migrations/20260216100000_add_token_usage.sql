@@ -0,0 +1,17 @@
+-- Token usage of answers and model prices for cost tracking
+
+-- Tokens reported by the provider, NULL if unknown
+ALTER TABLE messages ADD COLUMN prompt_tokens INTEGER DEFAULT NULL;
+ALTER TABLE messages ADD COLUMN completion_tokens INTEGER DEFAULT NULL;
+
+-- Time the message was saved, used to group costs by day
+ALTER TABLE messages ADD COLUMN created_at DATETIME DEFAULT NULL;
+
+-- Existing messages only have the timestamps of their chat
+UPDATE messages SET created_at = (
+    SELECT COALESCE(chats.last_updated, chats.creation_date) FROM chats WHERE chats.id = messages.chat_id
+);
+
+-- Prices in USD per million tokens, NULL if not set
+ALTER TABLE models ADD COLUMN input_price REAL DEFAULT NULL;
+ALTER TABLE models ADD COLUMN output_price REAL DEFAULT NULL;
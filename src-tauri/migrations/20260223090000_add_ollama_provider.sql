@@ -0,0 +1,6 @@
+-- Ollama as built-in provider
+-- It needs no API key, base_url holds the address of the Ollama server
+
+INSERT INTO providers (provider_name, display_name, api_key_valid, base_url, api_scheme, is_custom)
+SELECT 'ollama', 'Ollama', TRUE, 'http://localhost:11434', 'ollama', FALSE
+WHERE NOT EXISTS (SELECT 1 FROM providers WHERE provider_name = 'ollama');
@@ -0,0 +1,4 @@
+-- Flag models that their provider no longer lists
+-- Updated whenever the model list of a provider is fetched
+
+ALTER TABLE models ADD COLUMN deprecated BOOLEAN NOT NULL DEFAULT FALSE;
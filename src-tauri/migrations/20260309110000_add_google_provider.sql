@@ -0,0 +1,5 @@
+-- Google Gemini as built-in provider
+
+INSERT INTO providers (provider_name, display_name, api_scheme, is_custom)
+SELECT 'google', 'Google', 'google', FALSE
+WHERE NOT EXISTS (SELECT 1 FROM providers WHERE provider_name = 'google');
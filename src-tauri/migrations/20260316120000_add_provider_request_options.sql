@@ -0,0 +1,5 @@
+-- Extra headers and query parameters sent with every request to a provider
+-- Both are stored as JSON objects mapping names to values, NULL if there are none
+
+ALTER TABLE providers ADD COLUMN extra_headers TEXT DEFAULT NULL;
+ALTER TABLE providers ADD COLUMN query_params TEXT DEFAULT NULL;
@@ -0,0 +1,25 @@
+-- Files attached to messages, stored in the attachments folder of the app data directory
+
+CREATE TABLE IF NOT EXISTS attachments
+(
+    id TEXT NOT NULL PRIMARY KEY,
+    -- NULL until the message the file was attached to is sent
+    message_id TEXT,
+    -- "image"
+    kind TEXT NOT NULL,
+    file_name TEXT NOT NULL,
+    mime_type TEXT NOT NULL,
+    size_bytes INTEGER NOT NULL,
+    -- Path relative to the app data directory, copies of a message share the file
+    path TEXT NOT NULL,
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP
+);
+CREATE INDEX idx_attachments_message_id ON attachments(message_id);
+
+-- Whether a model accepts images
+ALTER TABLE models ADD COLUMN supports_vision BOOLEAN NOT NULL DEFAULT FALSE;
+
+UPDATE models SET supports_vision = TRUE
+WHERE (provider_name = 'openai' AND (model_name LIKE 'gpt-4o%' OR model_name LIKE 'gpt-4.1%' OR model_name LIKE 'gpt-5%'))
+    OR (provider_name = 'anthropic' AND model_name LIKE 'claude-%')
+    OR (provider_name = 'google' AND model_name LIKE 'gemini-%');
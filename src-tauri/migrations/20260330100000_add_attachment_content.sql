@@ -0,0 +1,4 @@
+-- Text extracted from attached text, code and PDF files, sent to the model with the message
+-- NULL for images
+
+ALTER TABLE attachments ADD COLUMN content TEXT DEFAULT NULL;
@@ -0,0 +1,13 @@
+-- Model Context Protocol servers that are started over stdio to offer their tools to models
+
+CREATE TABLE IF NOT EXISTS mcp_servers
+(
+    name TEXT NOT NULL PRIMARY KEY,
+    command TEXT NOT NULL,
+    -- JSON array of the command line arguments
+    args TEXT NOT NULL DEFAULT '[]',
+    -- JSON object of environment variables set for the server process
+    env TEXT NOT NULL DEFAULT '{}',
+    enabled BOOLEAN NOT NULL DEFAULT TRUE,
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP
+);
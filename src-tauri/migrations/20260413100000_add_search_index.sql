@@ -0,0 +1,49 @@
+-- Full-text indexes of the message contents and chat names, kept in sync with their tables by triggers
+-- The implicit rowid of messages and chats may be renumbered by VACUUM since their primary key is TEXT,
+-- so the indexes store their own text and map tables link each index row to the id of its message or chat.
+-- Rows are looked up by rowid through the map, bm25() ranks the matches
+
+CREATE VIRTUAL TABLE IF NOT EXISTS messages_fts USING fts5(content, tokenize = 'unicode61 remove_diacritics 2');
+CREATE VIRTUAL TABLE IF NOT EXISTS chats_fts USING fts5(display_name, tokenize = 'unicode61 remove_diacritics 2');
+
+CREATE TABLE IF NOT EXISTS messages_fts_map (
+    fts_rowid INTEGER PRIMARY KEY,
+    message_id TEXT NOT NULL UNIQUE
+);
+CREATE TABLE IF NOT EXISTS chats_fts_map (
+    fts_rowid INTEGER PRIMARY KEY,
+    chat_id TEXT NOT NULL UNIQUE
+);
+
+INSERT INTO messages_fts_map(message_id) SELECT id FROM messages;
+INSERT INTO messages_fts(rowid, content)
+SELECT messages_fts_map.fts_rowid, messages.content FROM messages_fts_map JOIN messages ON messages.id = messages_fts_map.message_id;
+INSERT INTO chats_fts_map(chat_id) SELECT id FROM chats;
+INSERT INTO chats_fts(rowid, display_name)
+SELECT chats_fts_map.fts_rowid, chats.display_name FROM chats_fts_map JOIN chats ON chats.id = chats_fts_map.chat_id;
+
+CREATE TRIGGER messages_fts_insert AFTER INSERT ON messages BEGIN
+    INSERT INTO messages_fts_map(message_id) VALUES (new.id);
+    INSERT INTO messages_fts(rowid, content) VALUES ((SELECT fts_rowid FROM messages_fts_map WHERE message_id = new.id), new.content);
+END;
+CREATE TRIGGER messages_fts_delete AFTER DELETE ON messages BEGIN
+    DELETE FROM messages_fts WHERE rowid = (SELECT fts_rowid FROM messages_fts_map WHERE message_id = old.id);
+    DELETE FROM messages_fts_map WHERE message_id = old.id;
+END;
+CREATE TRIGGER messages_fts_update AFTER UPDATE OF id, content ON messages BEGIN
+    UPDATE messages_fts SET content = new.content WHERE rowid = (SELECT fts_rowid FROM messages_fts_map WHERE message_id = old.id);
+    UPDATE messages_fts_map SET message_id = new.id WHERE message_id = old.id;
+END;
+
+CREATE TRIGGER chats_fts_insert AFTER INSERT ON chats BEGIN
+    INSERT INTO chats_fts_map(chat_id) VALUES (new.id);
+    INSERT INTO chats_fts(rowid, display_name) VALUES ((SELECT fts_rowid FROM chats_fts_map WHERE chat_id = new.id), new.display_name);
+END;
+CREATE TRIGGER chats_fts_delete AFTER DELETE ON chats BEGIN
+    DELETE FROM chats_fts WHERE rowid = (SELECT fts_rowid FROM chats_fts_map WHERE chat_id = old.id);
+    DELETE FROM chats_fts_map WHERE chat_id = old.id;
+END;
+CREATE TRIGGER chats_fts_update AFTER UPDATE OF id, display_name ON chats BEGIN
+    UPDATE chats_fts SET display_name = new.display_name WHERE rowid = (SELECT fts_rowid FROM chats_fts_map WHERE chat_id = old.id);
+    UPDATE chats_fts_map SET chat_id = new.id WHERE chat_id = old.id;
+END;
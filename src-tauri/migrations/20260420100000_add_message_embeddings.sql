@@ -0,0 +1,21 @@
+-- Embedding vectors of the messages for semantic search, computed in the background
+-- Messages without a vector of the configured embedding model are indexed, so existing chats are backfilled
+
+CREATE TABLE IF NOT EXISTS message_embeddings
+(
+    message_id TEXT NOT NULL PRIMARY KEY,
+    provider_name TEXT NOT NULL,
+    model_name TEXT NOT NULL,
+    dimensions INTEGER NOT NULL,
+    -- Little-endian f32 values
+    vector BLOB NOT NULL,
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE TRIGGER message_embeddings_delete AFTER DELETE ON messages BEGIN
+    DELETE FROM message_embeddings WHERE message_id = old.id;
+END;
+-- Edited messages are embedded again
+CREATE TRIGGER message_embeddings_update AFTER UPDATE OF content ON messages BEGIN
+    DELETE FROM message_embeddings WHERE message_id = old.id;
+END;
@@ -0,0 +1,43 @@
+-- Knowledge bases: collections of local documents that are split into chunks and embedded for retrieval
+
+CREATE TABLE IF NOT EXISTS collections
+(
+    id TEXT NOT NULL PRIMARY KEY,
+    name TEXT NOT NULL,
+    -- Embedding model the chunks were embedded with, questions are embedded with the same one
+    embedding_provider TEXT NOT NULL,
+    embedding_model TEXT NOT NULL,
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE TABLE IF NOT EXISTS collection_documents
+(
+    id TEXT NOT NULL PRIMARY KEY,
+    collection_id TEXT NOT NULL,
+    -- Absolute path the document was read from, adding it again replaces its chunks
+    path TEXT NOT NULL,
+    file_name TEXT NOT NULL,
+    mime_type TEXT NOT NULL,
+    size_bytes INTEGER NOT NULL,
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    UNIQUE (collection_id, path)
+);
+
+CREATE TABLE IF NOT EXISTS document_chunks
+(
+    document_id TEXT NOT NULL,
+    -- Index of the chunk within its document
+    position INTEGER NOT NULL,
+    content TEXT NOT NULL,
+    -- Little-endian f32 values
+    vector BLOB NOT NULL,
+    PRIMARY KEY (document_id, position)
+);
+
+-- Collections searched when answering in a chat
+CREATE TABLE IF NOT EXISTS chat_collections
+(
+    chat_id TEXT NOT NULL,
+    collection_id TEXT NOT NULL,
+    PRIMARY KEY (chat_id, collection_id)
+);
@@ -0,0 +1,196 @@
+//! Fits chat histories into the context window of a model
+
+use crate::llm_providers::{ApiScheme, LLMConfig};
+use crate::types::{MessageHistory, Model};
+
+/// Share of the context window that is filled, leaves room for estimation errors
+const CONTEXT_FILL_RATIO: f32 = 0.9;
+
+/// Context window of the models table's column default, models added without a known window have it
+const UNKNOWN_CONTEXT_WINDOW: u32 = 1024;
+
+/// Context window assumed for models whose window is unknown, current chat models offer at least this much
+const DEFAULT_CONTEXT_WINDOW: u32 = 32768;
+
+/// Chat history that fits into the context window of a model
+pub struct ChatContext {
+	pub messages: MessageHistory,
+	/// Number of messages left out because they did not fit
+	pub excluded: usize,
+	/// Estimated number of prompt tokens, including the system prompt
+	pub estimated_tokens: usize,
+}
+
+/// Drop the oldest turns of a history until it fits into the model's context window,
+/// leaving room for the answer. System messages and the latest user message are always kept.
+pub fn build_context(history: MessageHistory, model: &Model, config: &LLMConfig, scheme: &ApiScheme) -> ChatContext {
+	let context_window = match model.context_window {
+		0 | UNKNOWN_CONTEXT_WINDOW => DEFAULT_CONTEXT_WINDOW,
+		context_window => context_window,
+	};
+	// Models whose output limit equals their context window would leave no room for the prompt
+	let reserved_output = config.max_tokens.min(context_window / 2) as usize;
+	let budget = ((context_window as usize).saturating_sub(reserved_output) as f32 * CONTEXT_FILL_RATIO) as usize;
+
+	let mut keep = vec![false; history.len()];
+	let mut estimated_tokens = config.system_prompt.as_deref().map_or(0, |prompt| scheme.estimate_tokens(prompt));
+
+	// Pinned messages are kept regardless of the budget
+	let latest_user = history.iter().rposition(|msg| msg.role == "user");
+	for (index, msg) in history.iter().enumerate() {
+		if msg.role == "system" || Some(index) == latest_user {
+			keep[index] = true;
+			estimated_tokens += scheme.estimate_message_tokens(msg);
+		}
+	}
+
+	// Fill the remaining budget with the newest turns, everything older than the first turn that doesn't fit is dropped
+	for index in (0..history.len()).rev() {
+		if keep[index] {
+			continue;
+		}
+		let tokens = scheme.estimate_message_tokens(&history[index]);
+		if estimated_tokens + tokens > budget {
+			break;
+		}
+		keep[index] = true;
+		estimated_tokens += tokens;
+	}
+
+	// Providers expect the conversation to start with a user turn
+	if keep.contains(&false) {
+		let first_turn = (0..history.len()).find(|&index| keep[index] && history[index].role != "system");
+		if let Some(index) = first_turn {
+			if history[index].role == "assistant" {
+				keep[index] = false;
+				estimated_tokens -= scheme.estimate_message_tokens(&history[index]);
+			}
+		}
+	}
+
+	let total = history.len();
+	let messages: Vec<_> = history.0.into_iter().zip(keep).filter_map(|(msg, keep)| keep.then_some(msg)).collect();
+	ChatContext {
+		excluded: total - messages.len(),
+		messages: MessageHistory(messages),
+		estimated_tokens,
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::types::Message;
+
+	fn message(id: usize, role: &str, content: &str) -> Message {
+		Message {
+			id: id.to_string(),
+			role: role.to_string(),
+			content: content.to_string(),
+			model_name: "model".to_string(),
+			parent_id: None,
+			provider_name: None,
+			status: "ok".to_string(),
+			latency_ms: None,
+			prompt_tokens: None,
+			completion_tokens: None,
+			blocks: None,
+			attachments: Vec::new(),
+		}
+	}
+
+	/// Alternating prompts and answers of about 250 tokens each, ending with a prompt
+	fn conversation(turns: usize) -> MessageHistory {
+		let content = "word ".repeat(200);
+		MessageHistory(
+			(0..turns * 2 + 1)
+				.map(|index| message(index, if index % 2 == 0 { "user" } else { "assistant" }, &content))
+				.collect(),
+		)
+	}
+
+	fn model(context_window: u32) -> Model {
+		Model {
+			provider_name: "openai".to_string(),
+			model_name: "model".to_string(),
+			model_display_name: "Model".to_string(),
+			show: true,
+			max_tokens: 4096,
+			context_window,
+			input_price: None,
+			output_price: None,
+			deprecated: false,
+			supports_vision: false,
+		}
+	}
+
+	fn config(max_tokens: u32, system_prompt: Option<&str>) -> LLMConfig {
+		LLMConfig {
+			temperature: 0.7,
+			max_tokens,
+			top_p: None,
+			stop_sequences: None,
+			seed: None,
+			frequency_penalty: None,
+			presence_penalty: None,
+			system_prompt: system_prompt.map(str::to_string),
+		}
+	}
+
+	fn ids(context: &ChatContext) -> Vec<&str> {
+		context.messages.iter().map(|message| message.id.as_str()).collect()
+	}
+
+	#[test]
+	fn keeps_history_that_fits() {
+		let context = build_context(conversation(3), &model(128000), &config(4096, None), &ApiScheme::OpenAI);
+		assert_eq!(context.excluded, 0);
+		assert_eq!(ids(&context), ["0", "1", "2", "3", "4", "5", "6"]);
+	}
+
+	#[test]
+	fn drops_oldest_turns_first() {
+		// The budget of about 2100 tokens fits the latest prompt and the three turns before it
+		let context = build_context(conversation(10), &model(4400), &config(2000, None), &ApiScheme::OpenAI);
+		assert!(context.excluded > 0);
+		assert_eq!(context.messages.last().map(|message| message.id.as_str()), Some("20"));
+		let kept = ids(&context);
+		let first: usize = kept[0].parse().unwrap();
+		let expected: Vec<String> = (first..=20).map(|index| index.to_string()).collect();
+		assert_eq!(kept, expected);
+		assert_eq!(context.excluded, first);
+	}
+
+	#[test]
+	fn starts_with_a_prompt() {
+		for context_window in [3000, 3300, 3600, 3900, 4200] {
+			let context = build_context(conversation(10), &model(context_window), &config(1000, None), &ApiScheme::OpenAI);
+			assert_eq!(context.messages[0].role, "user", "context window {}", context_window);
+		}
+	}
+
+	#[test]
+	fn keeps_latest_prompt_and_system_messages_over_budget() {
+		let mut history = conversation(2);
+		history.0.insert(0, message(99, "system", "Be brief."));
+		history.0.last_mut().unwrap().content = "word ".repeat(20000);
+		let context = build_context(history, &model(8000), &config(1000, None), &ApiScheme::OpenAI);
+		assert_eq!(ids(&context), ["99", "4"]);
+		assert_eq!(context.excluded, 4);
+	}
+
+	#[test]
+	fn counts_system_prompt() {
+		let system_prompt = "rule ".repeat(400);
+		let without = build_context(conversation(10), &model(6000), &config(1000, None), &ApiScheme::OpenAI);
+		let with = build_context(conversation(10), &model(6000), &config(1000, Some(&system_prompt)), &ApiScheme::OpenAI);
+		assert!(with.excluded > without.excluded);
+		assert!(with.estimated_tokens > ApiScheme::OpenAI.estimate_tokens(&system_prompt));
+	}
+
+	#[test]
+	fn unknown_context_window_uses_default() {
+		let context = build_context(conversation(20), &model(UNKNOWN_CONTEXT_WINDOW), &config(4096, None), &ApiScheme::OpenAI);
+		assert_eq!(context.excluded, 0);
+	}
+}
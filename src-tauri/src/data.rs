@@ -1,11 +1,14 @@
 use sqlx::SqlitePool;
+use std::collections::HashMap;
 use std::env;
 use std::path::PathBuf;
 use std::sync::Arc;
 use tauri::State;
-use tokio::sync::Mutex;
+use tokio::sync::{oneshot, Mutex};
 
+use crate::mcp::McpClient;
 use crate::settings::Settings;
+use crate::types::McpServer;
 
 /// Application paths configuration
 #[derive(Clone)]
@@ -74,6 +77,114 @@ pub struct MainWindow(pub Arc<tauri::WebviewWindow>);
 /// Type alias for window state  
 pub type WindowState<'a> = State<'a, MainWindow>;
 
+/// Registry of in-flight generations, keyed by chat id.
+/// Sending on the stored channel cancels the generation of that chat. A cancelled generation stays registered until its task
+/// unregisters it, so the next generation of the chat only starts once the cancelled one stopped saving.
+#[derive(Default)]
+pub struct Generations(pub Arc<Mutex<HashMap<String, RunningGeneration>>>);
+
+/// A registered generation, the channel is taken when it is cancelled
+pub struct RunningGeneration {
+	id: String,
+	cancel_tx: Option<oneshot::Sender<()>>,
+}
+
+impl Generations {
+	/// Register a generation for a chat, returning its id and the receiver that signals cancellation
+	pub async fn register(&self, chat_id: &str) -> Result<(String, oneshot::Receiver<()>), String> {
+		let mut generations = self.0.lock().await;
+		if generations.contains_key(chat_id) {
+			return Err("A response is already being generated for this chat".to_string());
+		}
+		let id = uuid::Uuid::new_v4().to_string();
+		let (cancel_tx, cancel_rx) = oneshot::channel();
+		generations.insert(
+			chat_id.to_string(),
+			RunningGeneration {
+				id: id.clone(),
+				cancel_tx: Some(cancel_tx),
+			},
+		);
+		Ok((id, cancel_rx))
+	}
+
+	/// Remove a finished generation from the registry, a generation registered for the chat since is kept
+	pub async fn unregister(&self, chat_id: &str, generation_id: &str) {
+		let mut generations = self.0.lock().await;
+		if generations.get(chat_id).is_some_and(|generation| generation.id == generation_id) {
+			generations.remove(chat_id);
+		}
+	}
+
+	/// Cancel the generation running for a chat, returns false if there is none or it was already cancelled
+	pub async fn cancel(&self, chat_id: &str) -> bool {
+		match self.0.lock().await.get_mut(chat_id).and_then(|generation| generation.cancel_tx.take()) {
+			Some(cancel_tx) => cancel_tx.send(()).is_ok(),
+			None => false,
+		}
+	}
+}
+
+/// Type alias for generations state
+pub type GenerationsState<'a> = State<'a, Generations>;
+
+/// Running MCP servers by name, a server is started when its tools are first needed
+#[derive(Default)]
+pub struct McpClients(pub Arc<Mutex<HashMap<String, Arc<McpClient>>>>);
+
+impl McpClients {
+	/// Get the client of a server, (re)starting it if it is not running or its definition changed
+	pub async fn connect(&self, server: &McpServer) -> Result<Arc<McpClient>, String> {
+		let mut clients = self.0.lock().await;
+		if let Some(client) = clients.get(&server.name) {
+			if client.is_running() && client.server == *server {
+				return Ok(client.clone());
+			}
+		}
+		let client = Arc::new(McpClient::start(server).await?);
+		clients.insert(server.name.clone(), client.clone());
+		Ok(client)
+	}
+
+	/// Stop a server, the process is killed once running tool calls finished
+	pub async fn disconnect(&self, server_name: &str) {
+		self.0.lock().await.remove(server_name);
+	}
+}
+
+/// Type alias for MCP clients state
+pub type McpClientsState<'a> = State<'a, McpClients>;
+
+/// Tool calls waiting for the user's approval, keyed by request id.
+/// Sending on the stored channel approves or declines the call.
+#[derive(Default, Clone)]
+pub struct ToolApprovals(pub Arc<Mutex<HashMap<String, oneshot::Sender<bool>>>>);
+
+impl ToolApprovals {
+	/// Register a tool call, returning the receiver of the user's answer
+	pub async fn request(&self, request_id: &str) -> oneshot::Receiver<bool> {
+		let (answer_tx, answer_rx) = oneshot::channel();
+		self.0.lock().await.insert(request_id.to_string(), answer_tx);
+		answer_rx
+	}
+
+	/// Answer a pending tool call, returns false if it is no longer waiting
+	pub async fn answer(&self, request_id: &str, approved: bool) -> bool {
+		match self.0.lock().await.remove(request_id) {
+			Some(answer_tx) => answer_tx.send(approved).is_ok(),
+			None => false,
+		}
+	}
+
+	/// Forget a tool call that was not answered in time
+	pub async fn forget(&self, request_id: &str) {
+		self.0.lock().await.remove(request_id);
+	}
+}
+
+/// Type alias for tool approvals state
+pub type ToolApprovalsState<'a> = State<'a, ToolApprovals>;
+
 // ============================================================================
 // Legacy Combined State (for gradual migration)
 // ============================================================================
@@ -0,0 +1,309 @@
+//! Files attached to messages.
+//! The files are copied into the attachments folder of the app data directory, the attachments table references them.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use sqlx::SqlitePool;
+use tauri::command;
+use tauri::http::{header::CONTENT_TYPE, Response, StatusCode};
+
+use crate::data::DataState;
+use crate::types::{Attachment, MessageHistory};
+
+/// Folder of the app data directory the attached files are stored in
+pub const ATTACHMENTS_DIR: &str = "attachments";
+
+/// Largest image that can be attached, providers reject bigger ones
+const MAX_IMAGE_BYTES: u64 = 20 * 1024 * 1024;
+
+/// Largest text, code or PDF file that can be attached
+const MAX_FILE_BYTES: u64 = 10 * 1024 * 1024;
+
+const ATTACHMENT_COLUMNS: &str = "id, kind, file_name, mime_type, size_bytes, path, content";
+
+/// Mime type of a supported image, by its file extension
+fn image_mime_type(path: &Path) -> Option<&'static str> {
+	let extension = path.extension()?.to_str()?.to_lowercase();
+	match extension.as_str() {
+		"png" => Some("image/png"),
+		"jpg" | "jpeg" => Some("image/jpeg"),
+		"gif" => Some("image/gif"),
+		"webp" => Some("image/webp"),
+		_ => None,
+	}
+}
+
+/// Mime type of a text file, by its file extension. Code is sent as plain text.
+pub fn text_mime_type(path: &Path) -> &'static str {
+	let extension = path.extension().and_then(|extension| extension.to_str()).unwrap_or_default().to_lowercase();
+	match extension.as_str() {
+		"md" | "markdown" => "text/markdown",
+		"csv" => "text/csv",
+		"html" | "htm" => "text/html",
+		"json" => "application/json",
+		_ => "text/plain",
+	}
+}
+
+pub fn is_pdf(path: &Path) -> bool {
+	path.extension()
+		.and_then(|extension| extension.to_str())
+		.is_some_and(|extension| extension.eq_ignore_ascii_case("pdf"))
+}
+
+/// Read a text or code file, binary files are rejected
+pub fn read_text_file(path: &Path, file_name: &str) -> Result<String, String> {
+	let bytes = fs::read(path).map_err(|e| format!("Could not read {}: {}", file_name, e))?;
+	match String::from_utf8(bytes) {
+		Ok(text) if !text.contains('\0') => Ok(text),
+		_ => Err(format!("{} is not supported, attach an image, a PDF or a text file", file_name)),
+	}
+}
+
+/// Extract the text of a PDF, scanned documents without a text layer are rejected
+pub fn extract_pdf_text(path: &Path, file_name: &str) -> Result<String, String> {
+	let text = pdf_extract::extract_text(path).map_err(|e| format!("Could not extract the text of {}: {}", file_name, e))?;
+	if text.trim().is_empty() {
+		return Err(format!("{} contains no text, scanned documents are not supported", file_name));
+	}
+	Ok(text)
+}
+
+/// Copy a file into the attachments folder, the text of text, code and PDF files is extracted and stored with it.
+/// The attachment is linked to a message once the message is sent.
+#[command]
+#[specta::specta]
+pub async fn add_attachment(file_path: String, data: DataState<'_>) -> Result<Attachment, String> {
+	let source = PathBuf::from(&file_path);
+	let file_name = source
+		.file_name()
+		.map(|name| name.to_string_lossy().to_string())
+		.ok_or_else(|| "Not a file".to_string())?;
+	let size_bytes = tokio::fs::metadata(&source)
+		.await
+		.map_err(|e| format!("Could not read {}: {}", file_name, e))?
+		.len();
+
+	let image_mime_type = image_mime_type(&source);
+	let max_bytes = if image_mime_type.is_some() { MAX_IMAGE_BYTES } else { MAX_FILE_BYTES };
+	if size_bytes > max_bytes {
+		return Err(format!("{} is larger than {} MB", file_name, max_bytes / 1024 / 1024));
+	}
+	let (kind, mime_type) = match image_mime_type {
+		Some(mime_type) => ("image", mime_type),
+		None if is_pdf(&source) => ("pdf", "application/pdf"),
+		None => ("text", text_mime_type(&source)),
+	};
+	// Extracting the text of a PDF blocks for a while and pdf-extract panics on some malformed files, so it runs off the async runtime
+	let content = match kind {
+		"image" => None,
+		_ => {
+			let (path, name) = (source.clone(), file_name.clone());
+			let text = tauri::async_runtime::spawn_blocking(move || match is_pdf(&path) {
+				true => extract_pdf_text(&path, &name),
+				false => read_text_file(&path, &name),
+			})
+			.await
+			.map_err(|e| {
+				log::error!("Error reading the text of {}: {}", file_path, e);
+				format!("Could not read {}", file_name)
+			})??;
+			Some(text)
+		}
+	};
+
+	let id = uuid::Uuid::new_v4().to_string();
+	let path = match source.extension() {
+		Some(extension) => format!("{}/{}.{}", ATTACHMENTS_DIR, id, extension.to_string_lossy().to_lowercase()),
+		None => format!("{}/{}", ATTACHMENTS_DIR, id),
+	};
+
+	let (db_pool, app_dir) = {
+		let data = data.0.lock().await;
+		(data.db_pool.clone(), data.paths.app_dir.clone())
+	};
+	tokio::fs::create_dir_all(app_dir.join(ATTACHMENTS_DIR))
+		.await
+		.map_err(|e| format!("Error creating attachments folder: {}", e))?;
+	tokio::fs::copy(&source, app_dir.join(&path))
+		.await
+		.map_err(|e| format!("Error copying {}: {}", file_name, e))?;
+
+	let attachment = Attachment {
+		id,
+		kind: kind.to_string(),
+		file_name,
+		mime_type: mime_type.to_string(),
+		size_bytes: size_bytes as u32,
+		path,
+		content,
+		data: None,
+	};
+	let query = "INSERT INTO attachments (id, message_id, kind, file_name, mime_type, size_bytes, path, content) VALUES ($1, NULL, $2, $3, $4, $5, $6, $7)";
+	sqlx::query(query)
+		.bind(&attachment.id)
+		.bind(&attachment.kind)
+		.bind(&attachment.file_name)
+		.bind(&attachment.mime_type)
+		.bind(attachment.size_bytes)
+		.bind(&attachment.path)
+		.bind(&attachment.content)
+		.execute(&db_pool)
+		.await
+		.map_err(|e| {
+			log::error!("Error inserting attachment into database: {}", e);
+			e.to_string()
+		})?;
+	Ok(attachment)
+}
+
+/// Remove an attachment that has not been sent yet
+#[command]
+#[specta::specta]
+pub async fn remove_attachment(attachment_id: String, data: DataState<'_>) -> Result<(), String> {
+	let data = data.0.lock().await;
+	let paths = sqlx::query_as::<_, (String,)>("SELECT path FROM attachments WHERE id = $1 AND message_id IS NULL")
+		.bind(&attachment_id)
+		.fetch_all(&data.db_pool)
+		.await
+		.map_err(|e| e.to_string())?;
+	sqlx::query("DELETE FROM attachments WHERE id = $1 AND message_id IS NULL")
+		.bind(&attachment_id)
+		.execute(&data.db_pool)
+		.await
+		.map_err(|e| format!("Error deleting attachment: {}", e))?;
+	remove_unreferenced_files(paths.into_iter().map(|(path,)| path).collect(), &data.db_pool, &data.paths.app_dir).await;
+	Ok(())
+}
+
+/// Get attachments that were added with `add_attachment` and not sent yet, in the order of the ids
+pub async fn find_pending_attachments(attachment_ids: &[String], data: DataState<'_>) -> Result<Vec<Attachment>, String> {
+	if attachment_ids.is_empty() {
+		return Ok(Vec::new());
+	}
+	let ids = serde_json::to_string(attachment_ids).map_err(|e| e.to_string())?;
+	let query = format!(
+		"SELECT {} FROM attachments JOIN json_each($1) ON attachments.id = json_each.value WHERE message_id IS NULL ORDER BY json_each.key",
+		ATTACHMENT_COLUMNS
+	);
+	let attachments = sqlx::query_as::<_, Attachment>(&query)
+		.bind(ids)
+		.fetch_all(&data.0.lock().await.db_pool)
+		.await
+		.map_err(|e| {
+			log::error!("Error fetching attachments from database: {}", e);
+			e.to_string()
+		})?;
+	if attachments.len() != attachment_ids.len() {
+		return Err("Attachment not found, it may have been sent already".to_string());
+	}
+	Ok(attachments)
+}
+
+/// Get the attachments of a message
+pub async fn get_attachments(message_id: &str, data: DataState<'_>) -> Result<Vec<Attachment>, String> {
+	let query = format!(
+		"SELECT {} FROM attachments WHERE message_id = $1 ORDER BY created_at, rowid",
+		ATTACHMENT_COLUMNS
+	);
+	sqlx::query_as::<_, Attachment>(&query)
+		.bind(message_id)
+		.fetch_all(&data.0.lock().await.db_pool)
+		.await
+		.map_err(|e| {
+			log::error!("Error fetching attachments from database: {}", e);
+			e.to_string()
+		})
+}
+
+/// Link attachments to a message.
+/// Pending attachments are moved to the message, attachments of another message are copied and share its files.
+pub async fn link_attachments(message_id: &str, attachments: &[Attachment], data: DataState<'_>) -> Result<(), String> {
+	let data = data.0.lock().await;
+	for attachment in attachments {
+		let linked = sqlx::query("UPDATE attachments SET message_id = $1 WHERE id = $2 AND message_id IS NULL")
+			.bind(message_id)
+			.bind(&attachment.id)
+			.execute(&data.db_pool)
+			.await
+			.map_err(|e| format!("Error linking attachment: {}", e))?;
+		if linked.rows_affected() > 0 {
+			continue;
+		}
+		let copy_query = "INSERT INTO attachments (id, message_id, kind, file_name, mime_type, size_bytes, path, content) \
+			SELECT $1, $2, kind, file_name, mime_type, size_bytes, path, content FROM attachments WHERE id = $3";
+		sqlx::query(copy_query)
+			.bind(uuid::Uuid::new_v4().to_string())
+			.bind(message_id)
+			.bind(&attachment.id)
+			.execute(&data.db_pool)
+			.await
+			.map_err(|e| format!("Error copying attachment: {}", e))?;
+	}
+	Ok(())
+}
+
+/// Load the attachments of every message of a history, including the extracted text of files and the contents of the images
+pub async fn load_history_attachments(messages: &mut MessageHistory, data: DataState<'_>) -> Result<(), String> {
+	let app_dir = data.0.lock().await.paths.app_dir.clone();
+	for message in messages.0.iter_mut() {
+		message.attachments = get_attachments(&message.id, data.clone()).await?;
+		for attachment in message.attachments.iter_mut().filter(|attachment| attachment.is_image()) {
+			let contents = fs::read(app_dir.join(&attachment.path)).map_err(|e| format!("Could not read attached image {}: {}", attachment.file_name, e))?;
+			attachment.data = Some(contents);
+		}
+	}
+	Ok(())
+}
+
+/// Delete the attachments of all messages of a chat, files that no other message references are removed
+pub async fn delete_chat_attachments(chat_id: &str, pool: &SqlitePool, app_dir: &Path) -> Result<(), String> {
+	let paths = sqlx::query_as::<_, (String,)>("SELECT DISTINCT path FROM attachments WHERE message_id IN (SELECT id FROM messages WHERE chat_id = $1)")
+		.bind(chat_id)
+		.fetch_all(pool)
+		.await
+		.map_err(|e| e.to_string())?;
+	sqlx::query("DELETE FROM attachments WHERE message_id IN (SELECT id FROM messages WHERE chat_id = $1)")
+		.bind(chat_id)
+		.execute(pool)
+		.await
+		.map_err(|e| format!("Error deleting attachments: {}", e))?;
+	remove_unreferenced_files(paths.into_iter().map(|(path,)| path).collect(), pool, app_dir).await;
+	Ok(())
+}
+
+/// Remove stored files that no attachment references anymore
+pub async fn remove_unreferenced_files(paths: Vec<String>, pool: &SqlitePool, app_dir: &Path) {
+	for path in paths {
+		let referenced = sqlx::query_as::<_, (i64,)>("SELECT COUNT(*) FROM attachments WHERE path = $1")
+			.bind(&path)
+			.fetch_one(pool)
+			.await
+			.map(|(count,)| count > 0)
+			.unwrap_or(true);
+		if !referenced {
+			if let Err(e) = fs::remove_file(app_dir.join(&path)) {
+				log::warn!("Could not remove attachment file {}: {}", path, e);
+			}
+		}
+	}
+}
+
+/// Serve a stored file to the webview, used by the `attachment` protocol
+pub fn serve_attachment(attachments_dir: &Path, request_path: &str) -> Response<Vec<u8>> {
+	let file_name = request_path.trim_start_matches('/');
+	// Only files directly inside the attachments folder are served
+	let contents = if file_name.is_empty() || file_name.starts_with('.') || file_name.contains(['/', '\\']) {
+		None
+	} else {
+		fs::read(attachments_dir.join(file_name)).ok()
+	};
+	match contents {
+		Some(contents) => Response::builder()
+			.header(CONTENT_TYPE, image_mime_type(Path::new(file_name)).unwrap_or("application/octet-stream"))
+			.body(contents)
+			.unwrap_or_default(),
+		None => Response::builder().status(StatusCode::NOT_FOUND).body(Vec::new()).unwrap_or_default(),
+	}
+}
@@ -1,9 +1,31 @@
 //! Chat-related database operations
 
+use sqlx::sqlite::SqliteRow;
+use sqlx::Row;
 use tauri::command;
 
 use crate::data::DataState;
-use crate::types::{Chat, Chats};
+use crate::types::{Chat, ChatParameters, Chats};
+
+use super::providers_db::find_provider;
+
+use super::attachments::delete_chat_attachments;
+
+// Implement FromRow for ChatParameters, stop sequences are stored as a JSON array
+impl sqlx::FromRow<'_, SqliteRow> for ChatParameters {
+	fn from_row(row: &SqliteRow) -> Result<Self, sqlx::Error> {
+		let stop_sequences: Option<String> = row.try_get("stop_sequences")?;
+		Ok(ChatParameters {
+			temperature: row.try_get("temperature")?,
+			top_p: row.try_get("top_p")?,
+			max_tokens: row.try_get("max_tokens")?,
+			stop_sequences: stop_sequences.and_then(|s| serde_json::from_str(&s).ok()),
+			seed: row.try_get("seed")?,
+			frequency_penalty: row.try_get("frequency_penalty")?,
+			presence_penalty: row.try_get("presence_penalty")?,
+		})
+	}
+}
 
 /// Get all non-archived chats, ordered by last updated
 #[command]
@@ -71,17 +93,253 @@ pub async fn archive_chat(chat_id: String, data: DataState<'_>) -> Result<(), St
 	}
 }
 
-/// Permanently delete a chat
+/// Permanently delete a chat, including its attached files
 #[command]
 #[specta::specta]
 pub async fn delete_chat(chat_id: String, data: DataState<'_>) -> Result<(), String> {
 	let data = data.0.lock().await;
 	let query = "DELETE FROM chats WHERE id = $1";
 	match sqlx::query(query).bind(&chat_id).execute(&data.db_pool).await {
-		Ok(_) => Ok(()),
+		Ok(_) => {
+			let _ = sqlx::query("DELETE FROM chat_parameters WHERE chat_id = $1")
+				.bind(&chat_id)
+				.execute(&data.db_pool)
+				.await;
+			let _ = sqlx::query("DELETE FROM chat_collections WHERE chat_id = $1")
+				.bind(&chat_id)
+				.execute(&data.db_pool)
+				.await;
+			if let Err(e) = delete_chat_attachments(&chat_id, &data.db_pool, &data.paths.app_dir).await {
+				log::error!("Error deleting attachments of chat {}: {}", chat_id, e);
+			}
+			Ok(())
+		}
 		Err(e) => {
 			log::error!("Error deleting chat: {}", e);
 			Err(e.to_string())
 		}
 	}
 }
+
+/// Copy the conversation up to a message into a new chat, including the rendered blocks, attachments, system prompt, parameters and collections.
+/// Only the branch leading to the message is copied. Returns the id of the new chat.
+#[command]
+#[specta::specta]
+pub async fn fork_chat(message_id: String, data: DataState<'_>) -> Result<String, String> {
+	let data = data.0.lock().await;
+	let chat_query = "SELECT chats.id, chats.display_name FROM chats JOIN messages ON messages.chat_id = chats.id WHERE messages.id = $1";
+	let (source_chat_id, display_name) = sqlx::query_as::<_, (String, String)>(chat_query)
+		.bind(&message_id)
+		.fetch_optional(&data.db_pool)
+		.await
+		.map_err(|e| e.to_string())?
+		.ok_or_else(|| "Message not found".to_string())?;
+
+	let branch_query = "
+		WITH RECURSIVE branch(id, parent_id, depth) AS (
+			SELECT id, parent_id, 0 FROM messages WHERE id = $1
+			UNION ALL
+			SELECT messages.id, messages.parent_id, branch.depth + 1 FROM messages JOIN branch ON messages.id = branch.parent_id
+		)
+		SELECT id FROM branch ORDER BY depth DESC";
+	let branch = sqlx::query_as::<_, (String,)>(branch_query)
+		.bind(&message_id)
+		.fetch_all(&data.db_pool)
+		.await
+		.map_err(|e| e.to_string())?;
+
+	let chat_id = uuid::Uuid::new_v4().to_string();
+	let mut tx = data.db_pool.begin().await.map_err(|e| e.to_string())?;
+
+	let insert_chat = "INSERT INTO chats (id, model, api_key_id, display_name, archived, system_prompt, last_updated) \
+		SELECT $1, model, api_key_id, $2, 'false', system_prompt, CURRENT_TIMESTAMP FROM chats WHERE id = $3";
+	sqlx::query(insert_chat)
+		.bind(&chat_id)
+		.bind(format!("{} (fork)", display_name))
+		.bind(&source_chat_id)
+		.execute(&mut *tx)
+		.await
+		.map_err(|e| format!("Error creating forked chat: {}", e))?;
+
+	let insert_message = "INSERT INTO messages (id, role, content, status, chat_id, parent_id, provider_name, model_name, latency_ms, prompt_tokens, completion_tokens, created_at) \
+		SELECT $1, role, content, status, $2, $3, provider_name, model_name, latency_ms, prompt_tokens, completion_tokens, created_at FROM messages WHERE id = $4";
+	let copy_blocks = "INSERT INTO message_blocks (message_id, type_, language, raw_content, rendered_content, copied) \
+		SELECT $1, type_, language, raw_content, rendered_content, copied FROM message_blocks WHERE message_id = $2 ORDER BY id";
+	// Copied attachments share the stored files
+	let copy_attachment = "INSERT INTO attachments (id, message_id, kind, file_name, mime_type, size_bytes, path, content, created_at) \
+		SELECT $1, $2, kind, file_name, mime_type, size_bytes, path, content, created_at FROM attachments WHERE id = $3";
+	let mut parent_id: Option<String> = None;
+	for (source_message_id,) in branch {
+		let new_message_id = uuid::Uuid::new_v4().to_string();
+		sqlx::query(insert_message)
+			.bind(&new_message_id)
+			.bind(&chat_id)
+			.bind(&parent_id)
+			.bind(&source_message_id)
+			.execute(&mut *tx)
+			.await
+			.map_err(|e| format!("Error copying message: {}", e))?;
+		sqlx::query(copy_blocks)
+			.bind(&new_message_id)
+			.bind(&source_message_id)
+			.execute(&mut *tx)
+			.await
+			.map_err(|e| format!("Error copying message blocks: {}", e))?;
+		let attachments = sqlx::query_as::<_, (String,)>("SELECT id FROM attachments WHERE message_id = $1 ORDER BY created_at, rowid")
+			.bind(&source_message_id)
+			.fetch_all(&mut *tx)
+			.await
+			.map_err(|e| e.to_string())?;
+		for (attachment_id,) in attachments {
+			sqlx::query(copy_attachment)
+				.bind(uuid::Uuid::new_v4().to_string())
+				.bind(&new_message_id)
+				.bind(&attachment_id)
+				.execute(&mut *tx)
+				.await
+				.map_err(|e| format!("Error copying attachment: {}", e))?;
+		}
+		parent_id = Some(new_message_id);
+	}
+
+	sqlx::query("UPDATE chats SET active_message_id = $1 WHERE id = $2")
+		.bind(&parent_id)
+		.bind(&chat_id)
+		.execute(&mut *tx)
+		.await
+		.map_err(|e| e.to_string())?;
+
+	let copy_parameters = "INSERT INTO chat_parameters (chat_id, temperature, top_p, max_tokens, stop_sequences, seed, frequency_penalty, presence_penalty) \
+		SELECT $1, temperature, top_p, max_tokens, stop_sequences, seed, frequency_penalty, presence_penalty FROM chat_parameters WHERE chat_id = $2";
+	sqlx::query(copy_parameters)
+		.bind(&chat_id)
+		.bind(&source_chat_id)
+		.execute(&mut *tx)
+		.await
+		.map_err(|e| format!("Error copying chat parameters: {}", e))?;
+	sqlx::query("INSERT INTO chat_collections (chat_id, collection_id) SELECT $1, collection_id FROM chat_collections WHERE chat_id = $2")
+		.bind(&chat_id)
+		.bind(&source_chat_id)
+		.execute(&mut *tx)
+		.await
+		.map_err(|e| format!("Error copying chat collections: {}", e))?;
+
+	tx.commit().await.map_err(|e| {
+		log::error!("Error forking chat: {}", e);
+		e.to_string()
+	})?;
+	Ok(chat_id)
+}
+
+/// Get the generation parameter overrides stored for a chat, if any
+pub async fn find_chat_parameters(chat_id: &str, data: DataState<'_>) -> Result<Option<ChatParameters>, String> {
+	let query = "SELECT temperature, top_p, max_tokens, stop_sequences, seed, frequency_penalty, presence_penalty FROM chat_parameters WHERE chat_id = $1";
+	sqlx::query_as::<_, ChatParameters>(query)
+		.bind(chat_id)
+		.fetch_optional(&data.0.lock().await.db_pool)
+		.await
+		.map_err(|e| {
+			log::error!("Error fetching chat parameters: {}", e);
+			e.to_string()
+		})
+}
+
+/// Get the generation parameter overrides of a chat
+#[command]
+#[specta::specta]
+pub async fn get_chat_parameters(chat_id: String, data: DataState<'_>) -> Result<ChatParameters, String> {
+	Ok(find_chat_parameters(&chat_id, data).await?.unwrap_or_default())
+}
+
+/// Set the generation parameter overrides of a chat.
+/// Stop sequences, seed and penalties are rejected if the provider the chat uses can't send them.
+#[command]
+#[specta::specta]
+pub async fn update_chat_parameters(chat_id: String, provider_name: String, parameters: ChatParameters, data: DataState<'_>) -> Result<(), String> {
+	parameters.validate().map_err(|e| e.to_string())?;
+
+	let data = data.0.lock().await;
+	let extra_parameters = parameters.extra_parameters();
+	if !extra_parameters.is_empty() {
+		let provider = find_provider(&provider_name, &data.db_pool)
+			.await?
+			.ok_or_else(|| format!("Provider {} not found", provider_name))?;
+		if !provider.to_provider().map_err(|e| e.to_string())?.supports_extra_parameters() {
+			return Err(format!("{} does not support {}", provider.display_name, extra_parameters.join(", ")));
+		}
+	}
+	let stop_sequences = match &parameters.stop_sequences {
+		Some(stop_sequences) => Some(serde_json::to_string(stop_sequences).map_err(|e| e.to_string())?),
+		None => None,
+	};
+	let query = "INSERT INTO chat_parameters (chat_id, temperature, top_p, max_tokens, stop_sequences, seed, frequency_penalty, presence_penalty) \
+		VALUES ($1, $2, $3, $4, $5, $6, $7, $8) \
+		ON CONFLICT(chat_id) DO UPDATE SET temperature = excluded.temperature, top_p = excluded.top_p, max_tokens = excluded.max_tokens, \
+		stop_sequences = excluded.stop_sequences, seed = excluded.seed, frequency_penalty = excluded.frequency_penalty, presence_penalty = excluded.presence_penalty";
+	match sqlx::query(query)
+		.bind(&chat_id)
+		.bind(parameters.temperature)
+		.bind(parameters.top_p)
+		.bind(parameters.max_tokens)
+		.bind(stop_sequences)
+		.bind(parameters.seed)
+		.bind(parameters.frequency_penalty)
+		.bind(parameters.presence_penalty)
+		.execute(&data.db_pool)
+		.await
+	{
+		Ok(_) => Ok(()),
+		Err(e) => {
+			log::error!("Error updating chat parameters: {}", e);
+			Err(e.to_string())
+		}
+	}
+}
+
+/// Get the system prompt stored for a chat, if any
+pub async fn find_chat_system_prompt(chat_id: &str, data: DataState<'_>) -> Result<Option<String>, String> {
+	let query = "SELECT system_prompt FROM chats WHERE id = $1";
+	match sqlx::query_as::<_, (Option<String>,)>(query)
+		.bind(chat_id)
+		.fetch_optional(&data.0.lock().await.db_pool)
+		.await
+	{
+		Ok(row) => Ok(row.and_then(|(system_prompt,)| system_prompt)),
+		Err(e) => {
+			log::error!("Error fetching chat system prompt: {}", e);
+			Err(e.to_string())
+		}
+	}
+}
+
+/// Get the system prompt of a chat, `None` if the chat uses the global one
+#[command]
+#[specta::specta]
+pub async fn get_chat_system_prompt(chat_id: String, data: DataState<'_>) -> Result<Option<String>, String> {
+	find_chat_system_prompt(&chat_id, data).await
+}
+
+/// Set the system prompt of a chat, `None` or an empty prompt falls back to the global one.
+/// Creates the chat with a placeholder name if it has no messages yet.
+#[command]
+#[specta::specta]
+pub async fn set_chat_system_prompt(chat_id: String, system_prompt: Option<String>, data: DataState<'_>) -> Result<(), String> {
+	let data = data.0.lock().await;
+	let system_prompt = system_prompt.filter(|prompt| !prompt.trim().is_empty());
+	let query = "INSERT INTO chats (id, display_name, archived, system_prompt, last_updated) VALUES ($1, $2, $3, $4, CURRENT_TIMESTAMP) \
+		ON CONFLICT(id) DO UPDATE SET system_prompt = excluded.system_prompt";
+	match sqlx::query(query)
+		.bind(&chat_id)
+		.bind(format!("unnamed_new_chat_{}", chat_id))
+		.bind("false")
+		.bind(&system_prompt)
+		.execute(&data.db_pool)
+		.await
+	{
+		Ok(_) => Ok(()),
+		Err(e) => {
+			log::error!("Error setting chat system prompt: {}", e);
+			Err(e.to_string())
+		}
+	}
+}
@@ -0,0 +1,284 @@
+//! Knowledge base collections, their documents and the chats they are attached to
+
+use std::path::Path;
+
+use sqlx::SqlitePool;
+use tauri::command;
+
+use crate::data::DataState;
+use crate::db::embeddings::vector_to_blob;
+use crate::indexer::embedding_model;
+use crate::knowledge::{embed_chunks, read_document};
+use crate::types::{Collection, CollectionDocument};
+
+/// Collections with their number of documents and chunks
+const COLLECTIONS_QUERY: &str = "SELECT collections.id, collections.name, collections.embedding_provider, collections.embedding_model,
+		(SELECT COUNT(*) FROM collection_documents WHERE collection_id = collections.id) AS document_count,
+		(SELECT COUNT(*) FROM document_chunks JOIN collection_documents ON collection_documents.id = document_chunks.document_id
+			WHERE collection_documents.collection_id = collections.id) AS chunk_count,
+		collections.created_at
+	FROM collections";
+
+/// A chunk of a collection document with its vector
+#[derive(sqlx::FromRow)]
+pub struct StoredChunk {
+	pub file_name: String,
+	pub position: u32,
+	pub content: String,
+	pub vector: Vec<u8>,
+}
+
+#[command]
+#[specta::specta]
+pub async fn get_collections(data: DataState<'_>) -> Result<Vec<Collection>, String> {
+	let query = format!("{} ORDER BY collections.name", COLLECTIONS_QUERY);
+	sqlx::query_as::<_, Collection>(&query)
+		.fetch_all(&data.0.lock().await.db_pool)
+		.await
+		.map_err(|e| {
+			log::error!("Error fetching collections from database: {}", e);
+			e.to_string()
+		})
+}
+
+/// Create an empty collection, its documents are embedded with the embedding model configured in the settings
+#[command]
+#[specta::specta]
+pub async fn create_collection(name: String, data: DataState<'_>) -> Result<Collection, String> {
+	if name.trim().is_empty() {
+		return Err("Collection name must not be empty".to_string());
+	}
+	let data = data.0.lock().await;
+	let (provider_name, model_name) =
+		embedding_model(&data.settings).ok_or_else(|| "Select an embedding model in the settings to create collections".to_string())?;
+
+	let id = uuid::Uuid::new_v4().to_string();
+	sqlx::query("INSERT INTO collections (id, name, embedding_provider, embedding_model) VALUES ($1, $2, $3, $4)")
+		.bind(&id)
+		.bind(name.trim())
+		.bind(provider_name)
+		.bind(model_name)
+		.execute(&data.db_pool)
+		.await
+		.map_err(|e| {
+			log::error!("Error inserting collection into database: {}", e);
+			e.to_string()
+		})?;
+	find_collection(&id, &data.db_pool).await?.ok_or_else(|| "Collection not found".to_string())
+}
+
+#[command]
+#[specta::specta]
+pub async fn rename_collection(collection_id: String, name: String, data: DataState<'_>) -> Result<(), String> {
+	if name.trim().is_empty() {
+		return Err("Collection name must not be empty".to_string());
+	}
+	sqlx::query("UPDATE collections SET name = $1 WHERE id = $2")
+		.bind(name.trim())
+		.bind(&collection_id)
+		.execute(&data.0.lock().await.db_pool)
+		.await
+		.map_err(|e| {
+			log::error!("Error renaming collection: {}", e);
+			e.to_string()
+		})?;
+	Ok(())
+}
+
+/// Delete a collection with its documents and detach it from all chats
+#[command]
+#[specta::specta]
+pub async fn delete_collection(collection_id: String, data: DataState<'_>) -> Result<(), String> {
+	let data = data.0.lock().await;
+	let mut tx = data.db_pool.begin().await.map_err(|e| e.to_string())?;
+	let queries = [
+		"DELETE FROM document_chunks WHERE document_id IN (SELECT id FROM collection_documents WHERE collection_id = $1)",
+		"DELETE FROM collection_documents WHERE collection_id = $1",
+		"DELETE FROM chat_collections WHERE collection_id = $1",
+		"DELETE FROM collections WHERE id = $1",
+	];
+	for query in queries {
+		sqlx::query(query)
+			.bind(&collection_id)
+			.execute(&mut *tx)
+			.await
+			.map_err(|e| format!("Error deleting collection: {}", e))?;
+	}
+	tx.commit().await.map_err(|e| {
+		log::error!("Error deleting collection: {}", e);
+		e.to_string()
+	})
+}
+
+#[command]
+#[specta::specta]
+pub async fn get_collection_documents(collection_id: String, data: DataState<'_>) -> Result<Vec<CollectionDocument>, String> {
+	let query = "SELECT id, collection_id, path, file_name, mime_type, size_bytes,
+			(SELECT COUNT(*) FROM document_chunks WHERE document_id = collection_documents.id) AS chunk_count, created_at
+		FROM collection_documents WHERE collection_id = $1 ORDER BY file_name";
+	sqlx::query_as::<_, CollectionDocument>(query)
+		.bind(&collection_id)
+		.fetch_all(&data.0.lock().await.db_pool)
+		.await
+		.map_err(|e| {
+			log::error!("Error fetching collection documents from database: {}", e);
+			e.to_string()
+		})
+}
+
+/// Read, chunk and embed markdown, code, text and PDF files and add them to a collection.
+/// Files that are already part of the collection are read again and replace their previous chunks.
+#[command]
+#[specta::specta]
+pub async fn add_collection_documents(collection_id: String, file_paths: Vec<String>, data: DataState<'_>) -> Result<Vec<CollectionDocument>, String> {
+	let pool = data.0.lock().await.db_pool.clone();
+	let collection = find_collection(&collection_id, &pool)
+		.await?
+		.ok_or_else(|| "Collection not found".to_string())?;
+
+	let mut documents = Vec::with_capacity(file_paths.len());
+	for file_path in file_paths {
+		let document = read_document(Path::new(&file_path))?;
+		let vectors = embed_chunks(&collection.embedding_provider, &collection.embedding_model, &document.chunks, &pool).await?;
+
+		let mut tx = pool.begin().await.map_err(|e| e.to_string())?;
+		sqlx::query("DELETE FROM document_chunks WHERE document_id IN (SELECT id FROM collection_documents WHERE collection_id = $1 AND path = $2)")
+			.bind(&collection_id)
+			.bind(&file_path)
+			.execute(&mut *tx)
+			.await
+			.map_err(|e| format!("Error replacing document: {}", e))?;
+		sqlx::query("DELETE FROM collection_documents WHERE collection_id = $1 AND path = $2")
+			.bind(&collection_id)
+			.bind(&file_path)
+			.execute(&mut *tx)
+			.await
+			.map_err(|e| format!("Error replacing document: {}", e))?;
+
+		let document_id = uuid::Uuid::new_v4().to_string();
+		sqlx::query("INSERT INTO collection_documents (id, collection_id, path, file_name, mime_type, size_bytes) VALUES ($1, $2, $3, $4, $5, $6)")
+			.bind(&document_id)
+			.bind(&collection_id)
+			.bind(&file_path)
+			.bind(&document.file_name)
+			.bind(document.mime_type)
+			.bind(document.size_bytes as u32)
+			.execute(&mut *tx)
+			.await
+			.map_err(|e| format!("Error inserting document: {}", e))?;
+		let insert_chunk = "INSERT INTO document_chunks (document_id, position, content, vector) VALUES ($1, $2, $3, $4)";
+		for (position, (chunk, vector)) in document.chunks.iter().zip(&vectors).enumerate() {
+			sqlx::query(insert_chunk)
+				.bind(&document_id)
+				.bind(position as u32)
+				.bind(chunk)
+				.bind(vector_to_blob(vector))
+				.execute(&mut *tx)
+				.await
+				.map_err(|e| format!("Error inserting document chunk: {}", e))?;
+		}
+		tx.commit().await.map_err(|e| {
+			log::error!("Error adding document to collection: {}", e);
+			e.to_string()
+		})?;
+
+		documents.push(CollectionDocument {
+			id: document_id,
+			collection_id: collection_id.clone(),
+			path: file_path,
+			file_name: document.file_name,
+			mime_type: document.mime_type.to_string(),
+			size_bytes: document.size_bytes as u32,
+			chunk_count: document.chunks.len() as u32,
+			created_at: None,
+		});
+	}
+	Ok(documents)
+}
+
+#[command]
+#[specta::specta]
+pub async fn remove_collection_document(document_id: String, data: DataState<'_>) -> Result<(), String> {
+	let data = data.0.lock().await;
+	let mut tx = data.db_pool.begin().await.map_err(|e| e.to_string())?;
+	for query in [
+		"DELETE FROM document_chunks WHERE document_id = $1",
+		"DELETE FROM collection_documents WHERE id = $1",
+	] {
+		sqlx::query(query)
+			.bind(&document_id)
+			.execute(&mut *tx)
+			.await
+			.map_err(|e| format!("Error removing document: {}", e))?;
+	}
+	tx.commit().await.map_err(|e| e.to_string())
+}
+
+/// Ids of the collections attached to a chat
+#[command]
+#[specta::specta]
+pub async fn get_chat_collections(chat_id: String, data: DataState<'_>) -> Result<Vec<String>, String> {
+	let pool = data.0.lock().await.db_pool.clone();
+	Ok(find_chat_collections(&chat_id, &pool)
+		.await?
+		.into_iter()
+		.map(|collection| collection.id)
+		.collect())
+}
+
+/// Replace the collections attached to a chat, they can be set before the first message is sent
+#[command]
+#[specta::specta]
+pub async fn set_chat_collections(chat_id: String, collection_ids: Vec<String>, data: DataState<'_>) -> Result<(), String> {
+	let data = data.0.lock().await;
+	let collection_ids = serde_json::to_string(&collection_ids).map_err(|e| e.to_string())?;
+	let mut tx = data.db_pool.begin().await.map_err(|e| e.to_string())?;
+	sqlx::query("DELETE FROM chat_collections WHERE chat_id = $1")
+		.bind(&chat_id)
+		.execute(&mut *tx)
+		.await
+		.map_err(|e| format!("Error updating chat collections: {}", e))?;
+	sqlx::query("INSERT OR IGNORE INTO chat_collections (chat_id, collection_id) SELECT $1, id FROM collections WHERE id IN (SELECT value FROM json_each($2))")
+		.bind(&chat_id)
+		.bind(&collection_ids)
+		.execute(&mut *tx)
+		.await
+		.map_err(|e| format!("Error updating chat collections: {}", e))?;
+	tx.commit().await.map_err(|e| {
+		log::error!("Error updating chat collections: {}", e);
+		e.to_string()
+	})
+}
+
+/// Get a single collection
+pub async fn find_collection(collection_id: &str, pool: &SqlitePool) -> Result<Option<Collection>, String> {
+	let query = format!("{} WHERE collections.id = $1", COLLECTIONS_QUERY);
+	sqlx::query_as::<_, Collection>(&query)
+		.bind(collection_id)
+		.fetch_optional(pool)
+		.await
+		.map_err(|e| format!("Error fetching collection {}: {}", collection_id, e))
+}
+
+/// Collections attached to a chat
+pub async fn find_chat_collections(chat_id: &str, pool: &SqlitePool) -> Result<Vec<Collection>, String> {
+	let query = format!(
+		"{} JOIN chat_collections ON chat_collections.collection_id = collections.id WHERE chat_collections.chat_id = $1 ORDER BY collections.name",
+		COLLECTIONS_QUERY
+	);
+	sqlx::query_as::<_, Collection>(&query).bind(chat_id).fetch_all(pool).await.map_err(|e| {
+		log::error!("Error fetching chat collections from database: {}", e);
+		e.to_string()
+	})
+}
+
+/// All chunks of a collection with their vectors
+pub async fn find_collection_chunks(collection_id: &str, pool: &SqlitePool) -> Result<Vec<StoredChunk>, String> {
+	let query = "SELECT collection_documents.file_name, document_chunks.position, document_chunks.content, document_chunks.vector
+		FROM document_chunks JOIN collection_documents ON collection_documents.id = document_chunks.document_id
+		WHERE collection_documents.collection_id = $1";
+	sqlx::query_as::<_, StoredChunk>(query).bind(collection_id).fetch_all(pool).await.map_err(|e| {
+		log::error!("Error fetching collection chunks from database: {}", e);
+		e.to_string()
+	})
+}
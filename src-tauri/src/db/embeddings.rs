@@ -0,0 +1,250 @@
+//! Embedding vectors of the messages and semantic search over them.
+//! Vectors are compared in memory with cosine similarity, only vectors of the configured embedding model are used.
+
+use std::collections::HashMap;
+
+use sqlx::SqlitePool;
+use tauri::command;
+
+use crate::data::DataState;
+use crate::indexer::{embed_texts, embedding_model};
+use crate::types::{EmbeddingStatus, SimilarChat};
+
+/// Number of chats returned if no limit is given
+const DEFAULT_SIMILAR_LIMIT: u32 = 10;
+
+/// Number of characters of a message shown as snippet
+const SNIPPET_CHARS: usize = 200;
+
+/// Find the chats whose messages are closest in meaning to a query, even if they use other words
+#[command]
+#[specta::specta]
+pub async fn search_similar_chats(query: String, limit: Option<u32>, data: DataState<'_>) -> Result<Vec<SimilarChat>, String> {
+	let (pool, settings) = {
+		let data = data.0.lock().await;
+		(data.db_pool.clone(), data.settings.clone())
+	};
+	let (provider_name, model_name) = embedding_model(&settings).ok_or_else(|| "No embedding model is configured".to_string())?;
+	if query.trim().is_empty() {
+		return Ok(Vec::new());
+	}
+	let vector = embed_texts(provider_name, model_name, &[query], &pool).await?.remove(0);
+	rank_similar_chats(&vector, provider_name, model_name, None, limit.unwrap_or(DEFAULT_SIMILAR_LIMIT), &pool).await
+}
+
+/// Find the chats closest in meaning to a chat, compared by the average of its message vectors
+#[command]
+#[specta::specta]
+pub async fn get_similar_chats(chat_id: String, limit: Option<u32>, data: DataState<'_>) -> Result<Vec<SimilarChat>, String> {
+	let (pool, settings) = {
+		let data = data.0.lock().await;
+		(data.db_pool.clone(), data.settings.clone())
+	};
+	let (provider_name, model_name) = embedding_model(&settings).ok_or_else(|| "No embedding model is configured".to_string())?;
+
+	let query = "SELECT vector FROM message_embeddings JOIN messages ON messages.id = message_embeddings.message_id
+		WHERE messages.chat_id = $1 AND provider_name = $2 AND model_name = $3";
+	let vectors: Vec<Vec<f32>> = sqlx::query_as::<_, (Vec<u8>,)>(query)
+		.bind(&chat_id)
+		.bind(provider_name)
+		.bind(model_name)
+		.fetch_all(&pool)
+		.await
+		.map_err(|e| {
+			log::error!("Error fetching message embeddings from database: {}", e);
+			e.to_string()
+		})?
+		.into_iter()
+		.map(|(blob,)| blob_to_vector(&blob))
+		.collect();
+	let Some(first) = vectors.first() else {
+		return Ok(Vec::new());
+	};
+
+	let dimensions = first.len();
+	let mut mean = vec![0.0; dimensions];
+	for vector in vectors.iter().filter(|vector| vector.len() == dimensions) {
+		for (sum, value) in mean.iter_mut().zip(vector) {
+			*sum += value;
+		}
+	}
+	rank_similar_chats(&mean, provider_name, model_name, Some(&chat_id), limit.unwrap_or(DEFAULT_SIMILAR_LIMIT), &pool).await
+}
+
+/// Number of messages embedded with the configured embedding model, out of all messages that can be embedded
+#[command]
+#[specta::specta]
+pub async fn get_embedding_status(data: DataState<'_>) -> Result<EmbeddingStatus, String> {
+	let (pool, settings) = {
+		let data = data.0.lock().await;
+		(data.db_pool.clone(), data.settings.clone())
+	};
+	let model = embedding_model(&settings);
+	let query = "SELECT COUNT(message_embeddings.message_id), COUNT(*) FROM messages JOIN chats ON chats.id = messages.chat_id
+		LEFT JOIN message_embeddings ON message_embeddings.message_id = messages.id AND provider_name = $1 AND model_name = $2
+		WHERE messages.status != 'error' AND trim(messages.content) != ''";
+	let (indexed_messages, total_messages) = sqlx::query_as::<_, (u32, u32)>(query)
+		.bind(model.map(|(provider_name, _)| provider_name))
+		.bind(model.map(|(_, model_name)| model_name))
+		.fetch_one(&pool)
+		.await
+		.map_err(|e| {
+			log::error!("Error counting message embeddings: {}", e);
+			e.to_string()
+		})?;
+	Ok(EmbeddingStatus {
+		provider_name: settings.embedding_provider.clone(),
+		model_name: settings.embedding_model.clone(),
+		indexed_messages,
+		total_messages,
+	})
+}
+
+/// Messages of existing chats without a vector of an embedding model as `(id, content)`, in the order they were written.
+/// The messages in `skip` are left out.
+pub async fn find_unembedded_messages(
+	provider_name: &str,
+	model_name: &str,
+	skip: &[&str],
+	limit: u32,
+	pool: &SqlitePool,
+) -> Result<Vec<(String, String)>, String> {
+	let query = "SELECT messages.id, messages.content FROM messages JOIN chats ON chats.id = messages.chat_id
+		LEFT JOIN message_embeddings ON message_embeddings.message_id = messages.id AND provider_name = $1 AND model_name = $2
+		WHERE message_embeddings.message_id IS NULL AND messages.status != 'error' AND trim(messages.content) != ''
+			AND messages.id NOT IN (SELECT value FROM json_each($4))
+		ORDER BY messages.rowid LIMIT $3";
+	let skip = serde_json::to_string(skip).map_err(|e| e.to_string())?;
+	sqlx::query_as::<_, (String, String)>(query)
+		.bind(provider_name)
+		.bind(model_name)
+		.bind(limit)
+		.bind(skip)
+		.fetch_all(pool)
+		.await
+		.map_err(|e| {
+			log::error!("Error fetching messages to embed: {}", e);
+			e.to_string()
+		})
+}
+
+/// Store the vector of a message, replacing the vector of another model.
+/// Nothing is stored if the message was deleted while it was embedded.
+pub async fn save_embedding(message_id: &str, provider_name: &str, model_name: &str, vector: &[f32], pool: &SqlitePool) -> Result<(), String> {
+	let query = "INSERT OR REPLACE INTO message_embeddings (message_id, provider_name, model_name, dimensions, vector, created_at)
+		SELECT $1, $2, $3, $4, $5, CURRENT_TIMESTAMP WHERE EXISTS (SELECT 1 FROM messages WHERE id = $1)";
+	sqlx::query(query)
+		.bind(message_id)
+		.bind(provider_name)
+		.bind(model_name)
+		.bind(vector.len() as u32)
+		.bind(vector_to_blob(vector))
+		.execute(pool)
+		.await
+		.map_err(|e| {
+			log::error!("Error inserting message embedding into database: {}", e);
+			e.to_string()
+		})?;
+	Ok(())
+}
+
+#[derive(sqlx::FromRow)]
+struct EmbeddedMessage {
+	message_id: String,
+	role: String,
+	content: String,
+	created_at: Option<String>,
+	chat_id: String,
+	chat_display_name: String,
+	archived: bool,
+	vector: Vec<u8>,
+}
+
+/// Rank the chats by their message closest to a vector
+async fn rank_similar_chats(
+	vector: &[f32],
+	provider_name: &str,
+	model_name: &str,
+	exclude_chat_id: Option<&str>,
+	limit: u32,
+	pool: &SqlitePool,
+) -> Result<Vec<SimilarChat>, String> {
+	let query = "SELECT messages.id AS message_id, messages.role, messages.content, messages.created_at, chats.id AS chat_id,
+			chats.display_name AS chat_display_name, chats.archived = 'true' AS archived, message_embeddings.vector
+		FROM message_embeddings JOIN messages ON messages.id = message_embeddings.message_id JOIN chats ON chats.id = messages.chat_id
+		WHERE provider_name = $1 AND model_name = $2 AND messages.status != 'error' AND ($3 IS NULL OR chats.id != $3)";
+	let messages = sqlx::query_as::<_, EmbeddedMessage>(query)
+		.bind(provider_name)
+		.bind(model_name)
+		.bind(exclude_chat_id)
+		.fetch_all(pool)
+		.await
+		.map_err(|e| {
+			log::error!("Error fetching message embeddings from database: {}", e);
+			e.to_string()
+		})?;
+
+	let mut best_by_chat: HashMap<String, SimilarChat> = HashMap::new();
+	for message in messages {
+		let Some(similarity) = cosine_similarity(vector, &blob_to_vector(&message.vector)) else {
+			continue;
+		};
+		if best_by_chat.get(&message.chat_id).is_some_and(|best| best.similarity >= similarity) {
+			continue;
+		}
+		best_by_chat.insert(
+			message.chat_id.clone(),
+			SimilarChat {
+				chat_id: message.chat_id,
+				chat_display_name: message.chat_display_name,
+				message_id: message.message_id,
+				role: message.role,
+				snippet: snippet(&message.content),
+				similarity,
+				created_at: message.created_at,
+				archived: message.archived,
+			},
+		);
+	}
+
+	let mut chats: Vec<SimilarChat> = best_by_chat.into_values().collect();
+	chats.sort_by(|a, b| b.similarity.total_cmp(&a.similarity));
+	chats.truncate(limit as usize);
+	Ok(chats)
+}
+
+/// Cosine similarity of two vectors, `None` if their dimensions differ or one of them is zero
+pub fn cosine_similarity(a: &[f32], b: &[f32]) -> Option<f32> {
+	if a.len() != b.len() {
+		return None;
+	}
+	let (mut dot, mut norm_a, mut norm_b) = (0.0, 0.0, 0.0);
+	for (x, y) in a.iter().zip(b) {
+		dot += x * y;
+		norm_a += x * x;
+		norm_b += y * y;
+	}
+	match norm_a > 0.0 && norm_b > 0.0 {
+		true => Some(dot / (norm_a.sqrt() * norm_b.sqrt())),
+		false => None,
+	}
+}
+
+pub fn vector_to_blob(vector: &[f32]) -> Vec<u8> {
+	vector.iter().flat_map(|value| value.to_le_bytes()).collect()
+}
+
+pub fn blob_to_vector(blob: &[u8]) -> Vec<f32> {
+	blob.chunks_exact(4)
+		.map(|bytes| f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+		.collect()
+}
+
+/// First characters of a message on one line
+fn snippet(content: &str) -> String {
+	let content = content.split_whitespace().collect::<Vec<_>>().join(" ");
+	match content.char_indices().nth(SNIPPET_CHARS) {
+		Some((end, _)) => format!("{}…", &content[..end]),
+		None => content,
+	}
+}
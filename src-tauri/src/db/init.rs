@@ -22,6 +22,10 @@ lazy_static! {
 			show: true,
 			max_tokens: 16384,
 			context_window: 128000,
+			input_price: None,
+			output_price: None,
+			deprecated: false,
+			supports_vision: true,
 		},
 		Model {
 			provider_name: "anthropic".to_string(),
@@ -30,6 +34,10 @@ lazy_static! {
 			show: true,
 			max_tokens: 8192,
 			context_window: 200000,
+			input_price: None,
+			output_price: None,
+			deprecated: false,
+			supports_vision: true,
 		},
 		Model {
 			// Database uses "mistralai", Provider::new handles both "mistral" and "mistralai"
@@ -39,6 +47,10 @@ lazy_static! {
 			show: true,
 			max_tokens: 32768,
 			context_window: 32768,
+			input_price: None,
+			output_price: None,
+			deprecated: false,
+			supports_vision: false,
 		},
 		Model {
 			// Database uses "groqcloud", Provider::new handles both "groq" and "groqcloud"
@@ -48,6 +60,22 @@ lazy_static! {
 			show: true,
 			max_tokens: 2048,
 			context_window: 131072,
+			input_price: None,
+			output_price: None,
+			deprecated: false,
+			supports_vision: false,
+		},
+		Model {
+			provider_name: "google".to_string(),
+			model_name: "gemini-2.5-pro".to_string(),
+			model_display_name: "Gemini".to_string(),
+			show: true,
+			max_tokens: 65536,
+			context_window: 1048576,
+			input_price: None,
+			output_price: None,
+			deprecated: false,
+			supports_vision: true,
 		},
 	];
 }
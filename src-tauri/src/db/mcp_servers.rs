@@ -0,0 +1,104 @@
+//! MCP server definitions
+
+use sqlx::sqlite::SqliteRow;
+use sqlx::{Row, SqlitePool};
+use tauri::command;
+
+use crate::data::{DataState, McpClientsState};
+use crate::types::McpServer;
+
+const MCP_SERVER_COLUMNS: &str = "name, command, args, env, enabled";
+
+// Implement FromRow for McpServer, arguments and environment are stored as JSON
+impl sqlx::FromRow<'_, SqliteRow> for McpServer {
+	fn from_row(row: &SqliteRow) -> Result<Self, sqlx::Error> {
+		let args: String = row.try_get("args")?;
+		let env: String = row.try_get("env")?;
+		Ok(McpServer {
+			name: row.try_get("name")?,
+			command: row.try_get("command")?,
+			args: serde_json::from_str(&args).unwrap_or_default(),
+			env: serde_json::from_str(&env).unwrap_or_default(),
+			enabled: row.try_get("enabled")?,
+		})
+	}
+}
+
+/// Load all MCP server definitions
+#[command]
+#[specta::specta]
+pub async fn get_mcp_servers(data: DataState<'_>) -> Result<Vec<McpServer>, String> {
+	let query = format!("SELECT {} FROM mcp_servers ORDER BY name", MCP_SERVER_COLUMNS);
+	sqlx::query_as::<_, McpServer>(&query)
+		.fetch_all(&data.0.lock().await.db_pool)
+		.await
+		.map_err(|e| {
+			log::error!("Error fetching MCP servers from database: {}", e);
+			e.to_string()
+		})
+}
+
+/// Add an MCP server or update the one with the same name.
+/// A running server is stopped so the next chat starts it with the new definition.
+#[command]
+#[specta::specta]
+pub async fn save_mcp_server(server: McpServer, data: DataState<'_>, clients: McpClientsState<'_>) -> Result<(), String> {
+	if server.name.trim().is_empty() {
+		return Err("The server needs a name".to_string());
+	}
+	if server.command.trim().is_empty() {
+		return Err("The server needs a command to start it".to_string());
+	}
+	let args = serde_json::to_string(&server.args).map_err(|e| e.to_string())?;
+	let env = serde_json::to_string(&server.env).map_err(|e| e.to_string())?;
+
+	let query = "INSERT INTO mcp_servers (name, command, args, env, enabled) VALUES ($1, $2, $3, $4, $5) \
+		ON CONFLICT(name) DO UPDATE SET command = excluded.command, args = excluded.args, env = excluded.env, enabled = excluded.enabled";
+	sqlx::query(query)
+		.bind(server.name.trim())
+		.bind(server.command.trim())
+		.bind(args)
+		.bind(env)
+		.bind(server.enabled)
+		.execute(&data.0.lock().await.db_pool)
+		.await
+		.map_err(|e| format!("Error saving MCP server: {}", e))?;
+
+	clients.disconnect(server.name.trim()).await;
+	Ok(())
+}
+
+/// Delete an MCP server definition and stop the server
+#[command]
+#[specta::specta]
+pub async fn delete_mcp_server(server_name: String, data: DataState<'_>, clients: McpClientsState<'_>) -> Result<(), String> {
+	let result = sqlx::query("DELETE FROM mcp_servers WHERE name = $1")
+		.bind(&server_name)
+		.execute(&data.0.lock().await.db_pool)
+		.await
+		.map_err(|e| format!("Error deleting MCP server: {}", e))?;
+	if result.rows_affected() == 0 {
+		return Err("MCP server not found".to_string());
+	}
+	clients.disconnect(&server_name).await;
+	Ok(())
+}
+
+/// Get an MCP server definition by name
+pub async fn find_mcp_server(server_name: &str, pool: &SqlitePool) -> Result<Option<McpServer>, String> {
+	let query = format!("SELECT {} FROM mcp_servers WHERE name = $1", MCP_SERVER_COLUMNS);
+	sqlx::query_as::<_, McpServer>(&query)
+		.bind(server_name)
+		.fetch_optional(pool)
+		.await
+		.map_err(|e| format!("Error fetching MCP server: {}", e))
+}
+
+/// Get the servers whose tools are offered to models
+pub async fn get_enabled_mcp_servers(pool: &SqlitePool) -> Result<Vec<McpServer>, String> {
+	let query = format!("SELECT {} FROM mcp_servers WHERE enabled = TRUE ORDER BY name", MCP_SERVER_COLUMNS);
+	sqlx::query_as::<_, McpServer>(&query)
+		.fetch_all(pool)
+		.await
+		.map_err(|e| format!("Error fetching MCP servers: {}", e))
+}
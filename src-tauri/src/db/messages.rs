@@ -1,11 +1,13 @@
 //! Message-related database operations
 
 use sqlx::sqlite::SqliteRow;
-use sqlx::Row;
+use sqlx::{FromRow, Row, SqlitePool};
 use tauri::command;
 
 use crate::data::DataState;
-use crate::types::{Message, MessageBlock, MessageBlocks, MessageHistory};
+use crate::types::{Attachment, Message, MessageBlock, MessageBlocks, MessageHistory};
+
+use super::attachments::{load_history_attachments, remove_unreferenced_files};
 
 // Implement FromRow for Message to map database rows
 impl sqlx::FromRow<'_, SqliteRow> for Message {
@@ -15,22 +17,41 @@ impl sqlx::FromRow<'_, SqliteRow> for Message {
 			role: row.try_get("role")?,
 			content: row.try_get("content")?,
 			model_name: row.try_get("model_name")?,
+			parent_id: row.try_get("parent_id")?,
+			provider_name: row.try_get("provider_name")?,
+			status: row.try_get("status")?,
+			latency_ms: row.try_get("latency_ms")?,
+			prompt_tokens: row.try_get("prompt_tokens")?,
+			completion_tokens: row.try_get("completion_tokens")?,
 			blocks: None,
+			attachments: Vec::new(),
 		})
 	}
 }
 
-/// Load all messages for a chat, including their rendered blocks
+/// Messages of the active branch of a chat, from the first message to the active one.
+/// Walks up the message tree starting at the chat's active message.
+const ACTIVE_BRANCH_QUERY: &str = "
+	WITH RECURSIVE branch(id, depth) AS (
+		SELECT active_message_id, 0 FROM chats WHERE id = $1
+		UNION ALL
+		SELECT messages.parent_id, branch.depth + 1 FROM messages JOIN branch ON messages.id = branch.id WHERE messages.parent_id IS NOT NULL
+	)
+	SELECT messages.id, role, content, model_name, parent_id, provider_name, status, latency_ms, prompt_tokens, completion_tokens
+	FROM branch JOIN messages ON messages.id = branch.id
+	ORDER BY branch.depth DESC";
+
+/// Load the messages of the active branch of a chat, including their rendered blocks and attachments
 #[command]
 #[specta::specta]
 pub async fn load_chat(chat_id: String, data: DataState<'_>) -> Result<Vec<Message>, String> {
 	let data = data.0.lock().await;
-	let fetch_query = "SELECT id, role, content, model_name FROM messages WHERE chat_id = $1";
-	let messages_result = sqlx::query_as::<_, Message>(fetch_query).bind(&chat_id).fetch_all(&data.db_pool).await;
+	let messages_result = sqlx::query_as::<_, Message>(ACTIVE_BRANCH_QUERY).bind(&chat_id).fetch_all(&data.db_pool).await;
 
 	match messages_result {
 		Ok(mut messages) => {
 			let blocks_query = "SELECT id, type_, language, raw_content, rendered_content, copied FROM message_blocks WHERE message_id = $1";
+			let attachments_query = "SELECT id, kind, file_name, mime_type, size_bytes, path FROM attachments WHERE message_id = $1 ORDER BY created_at, rowid";
 			for message in messages.iter_mut() {
 				match sqlx::query_as::<_, MessageBlock>(blocks_query).bind(&message.id).fetch_all(&data.db_pool).await {
 					Ok(message_blocks) => message.blocks = Some(MessageBlocks(message_blocks)),
@@ -38,6 +59,16 @@ pub async fn load_chat(chat_id: String, data: DataState<'_>) -> Result<Vec<Messa
 						log::error!("Error fetching message blocks from database: {}", err);
 					}
 				}
+				match sqlx::query_as::<_, Attachment>(attachments_query)
+					.bind(&message.id)
+					.fetch_all(&data.db_pool)
+					.await
+				{
+					Ok(attachments) => message.attachments = attachments,
+					Err(err) => {
+						log::error!("Error fetching attachments from database: {}", err);
+					}
+				}
 			}
 			Ok(messages)
 		}
@@ -48,10 +79,10 @@ pub async fn load_chat(chat_id: String, data: DataState<'_>) -> Result<Vec<Messa
 	}
 }
 
-/// Get all messages for a chat (without blocks, for LLM context)
+/// Get the messages of the active branch of a chat (without blocks, for LLM context).
+/// Attached images are loaded so they can be sent along. Failed messages only contain error text and are left out.
 pub async fn get_messages(chat_id: &str, data: DataState<'_>) -> Result<MessageHistory, anyhow::Error> {
-	let query = "SELECT id, role, content, model_name FROM messages WHERE chat_id = $1";
-	let messages = sqlx::query_as::<_, Message>(query)
+	let messages = sqlx::query_as::<_, Message>(ACTIVE_BRANCH_QUERY)
 		.bind(chat_id)
 		.fetch_all(&data.0.lock().await.db_pool)
 		.await
@@ -59,37 +90,204 @@ pub async fn get_messages(chat_id: &str, data: DataState<'_>) -> Result<MessageH
 			log::error!("Error fetching messages from database: {}", e);
 			anyhow::anyhow!("Database error: {}", e)
 		})?;
-	Ok(MessageHistory(messages))
+	let mut history = MessageHistory(messages.into_iter().filter(|msg| msg.status != "error").collect());
+	load_history_attachments(&mut history, data).await.map_err(|e| anyhow::anyhow!(e))?;
+	Ok(history)
 }
 
-/// Insert a new message
-pub async fn insert_message(message_id: &str, role: &str, content: &str, chat_id: &str, model_name: &str, data: DataState<'_>) {
-	let query = "INSERT INTO messages (id, role, content, chat_id, model_name) VALUES ($1, $2, $3, $4, $5)";
-	let _ = sqlx::query(query)
+/// Get the messages of the active branch of a chat without blocks and attachments, including failed ones
+pub async fn get_branch_messages(chat_id: &str, pool: &SqlitePool) -> Result<Vec<Message>, String> {
+	sqlx::query_as::<_, Message>(ACTIVE_BRANCH_QUERY)
+		.bind(chat_id)
+		.fetch_all(pool)
+		.await
+		.map_err(|e| {
+			log::error!("Error fetching messages from database: {}", e);
+			e.to_string()
+		})
+}
+
+/// List all versions of a message: the message itself and the alternatives that share its parent, oldest first
+#[command]
+#[specta::specta]
+pub async fn get_message_versions(message_id: String, data: DataState<'_>) -> Result<Vec<Message>, String> {
+	let query = "
+		SELECT versions.id, versions.role, versions.content, versions.model_name, versions.parent_id, versions.provider_name, versions.status, versions.latency_ms,
+			versions.prompt_tokens, versions.completion_tokens
+		FROM messages AS versions JOIN messages AS message ON versions.chat_id = message.chat_id AND versions.parent_id IS message.parent_id
+		WHERE message.id = $1
+		ORDER BY versions.rowid";
+	sqlx::query_as::<_, Message>(query)
+		.bind(&message_id)
+		.fetch_all(&data.0.lock().await.db_pool)
+		.await
+		.map_err(|e| {
+			log::error!("Error fetching message versions from database: {}", e);
+			e.to_string()
+		})
+}
+
+/// Show another version of a message.
+/// The chat continues with the most recent messages that follow the selected version.
+#[command]
+#[specta::specta]
+pub async fn switch_message_version(message_id: String, data: DataState<'_>) -> Result<(), String> {
+	let (chat_id, _) = find_message(&message_id, data.clone()).await?.ok_or_else(|| "Message not found".to_string())?;
+
+	let mut active_message_id = message_id;
+	let child_query = "SELECT id FROM messages WHERE parent_id = $1 ORDER BY rowid DESC LIMIT 1";
+	loop {
+		let child = sqlx::query_as::<_, (String,)>(child_query)
+			.bind(&active_message_id)
+			.fetch_optional(&data.0.lock().await.db_pool)
+			.await
+			.map_err(|e| e.to_string())?;
+		match child {
+			Some((child_id,)) => active_message_id = child_id,
+			None => break,
+		}
+	}
+
+	set_active_message_id(&chat_id, Some(&active_message_id), data).await
+}
+
+/// Get a single message and the id of the chat it belongs to
+pub async fn find_message(message_id: &str, data: DataState<'_>) -> Result<Option<(String, Message)>, String> {
+	let query = "SELECT id, role, content, model_name, parent_id, provider_name, status, latency_ms, prompt_tokens, completion_tokens, chat_id FROM messages WHERE id = $1";
+	let row = sqlx::query(query)
+		.bind(message_id)
+		.fetch_optional(&data.0.lock().await.db_pool)
+		.await
+		.map_err(|e| {
+			log::error!("Error fetching message from database: {}", e);
+			e.to_string()
+		})?;
+	match row {
+		Some(row) => {
+			let message = Message::from_row(&row).map_err(|e| e.to_string())?;
+			let chat_id: String = row.try_get("chat_id").map_err(|e| e.to_string())?;
+			Ok(Some((chat_id, message)))
+		}
+		None => Ok(None),
+	}
+}
+
+/// Get the id of the last message of the active branch of a chat
+pub async fn get_active_message_id(chat_id: &str, data: DataState<'_>) -> Result<Option<String>, String> {
+	let query = "SELECT active_message_id FROM chats WHERE id = $1";
+	sqlx::query_as::<_, (Option<String>,)>(query)
+		.bind(chat_id)
+		.fetch_optional(&data.0.lock().await.db_pool)
+		.await
+		.map(|row| row.and_then(|(id,)| id))
+		.map_err(|e| e.to_string())
+}
+
+/// Set the last message of the active branch of a chat
+pub async fn set_active_message_id(chat_id: &str, message_id: Option<&str>, data: DataState<'_>) -> Result<(), String> {
+	let query = "UPDATE chats SET active_message_id = $1 WHERE id = $2";
+	sqlx::query(query)
 		.bind(message_id)
-		.bind(role)
-		.bind(content)
 		.bind(chat_id)
-		.bind(model_name)
 		.execute(&data.0.lock().await.db_pool)
-		.await;
+		.await
+		.map_err(|e| {
+			log::error!("Error updating active message in database: {}", e);
+			e.to_string()
+		})?;
+	Ok(())
+}
+
+/// Insert a new message
+pub async fn insert_message(message: &Message, chat_id: &str, db_pool: &SqlitePool) -> Result<(), String> {
+	let query = "INSERT INTO messages (id, role, content, status, chat_id, parent_id, provider_name, model_name, latency_ms, prompt_tokens, completion_tokens, created_at) \
+		VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, CURRENT_TIMESTAMP)";
+	sqlx::query(query)
+		.bind(&message.id)
+		.bind(&message.role)
+		.bind(&message.content)
+		.bind(&message.status)
+		.bind(chat_id)
+		.bind(&message.parent_id)
+		.bind(&message.provider_name)
+		.bind(&message.model_name)
+		.bind(message.latency_ms)
+		.bind(message.prompt_tokens)
+		.bind(message.completion_tokens)
+		.execute(db_pool)
+		.await
+		.map_err(|e| format!("Error inserting message: {}", e))?;
+	Ok(())
+}
+
+/// Delete a message without replies with its rendered blocks, including its sources, and its attachments.
+/// If it was the active message of its chat, its parent becomes the active one.
+pub async fn delete_message(message_id: &str, data: DataState<'_>) -> Result<(), String> {
+	let data = data.0.lock().await;
+	let mut tx = data.db_pool.begin().await.map_err(|e| e.to_string())?;
+	sqlx::query("UPDATE chats SET active_message_id = (SELECT parent_id FROM messages WHERE id = $1) WHERE active_message_id = $1")
+		.bind(message_id)
+		.execute(&mut *tx)
+		.await
+		.map_err(|e| format!("Error updating active message: {}", e))?;
+	sqlx::query("DELETE FROM message_blocks WHERE message_id = $1")
+		.bind(message_id)
+		.execute(&mut *tx)
+		.await
+		.map_err(|e| format!("Error deleting message blocks: {}", e))?;
+	let paths = sqlx::query_as::<_, (String,)>("DELETE FROM attachments WHERE message_id = $1 RETURNING path")
+		.bind(message_id)
+		.fetch_all(&mut *tx)
+		.await
+		.map_err(|e| format!("Error deleting attachments: {}", e))?;
+	sqlx::query("DELETE FROM messages WHERE id = $1")
+		.bind(message_id)
+		.execute(&mut *tx)
+		.await
+		.map_err(|e| format!("Error deleting message: {}", e))?;
+	tx.commit().await.map_err(|e| e.to_string())?;
+
+	// Files are only removed once the rows are gone, forked chats may still reference them
+	remove_unreferenced_files(paths.into_iter().map(|(path,)| path).collect(), &data.db_pool, &data.paths.app_dir).await;
+	Ok(())
+}
+
+/// Replace the content of a message and its rendered blocks.
+/// The message is marked as complete since its content no longer comes from the provider.
+pub async fn update_message_content(message_id: &str, content: &str, message_blocks: &MessageBlocks, data: DataState<'_>) -> Result<(), String> {
+	{
+		let data = data.0.lock().await;
+		sqlx::query("UPDATE messages SET content = $1, status = 'ok' WHERE id = $2")
+			.bind(content)
+			.bind(message_id)
+			.execute(&data.db_pool)
+			.await
+			.map_err(|e| format!("Error updating message: {}", e))?;
+		// Image and tool blocks are kept, only the blocks rendered from the text are replaced
+		sqlx::query("DELETE FROM message_blocks WHERE message_id = $1 AND type_ IN ('text', 'code')")
+			.bind(message_id)
+			.execute(&data.db_pool)
+			.await
+			.map_err(|e| format!("Error deleting message blocks: {}", e))?;
+	}
+	let db_pool = data.0.lock().await.db_pool.clone();
+	insert_message_blocks(message_id, message_blocks, &db_pool).await
 }
 
 /// Insert rendered message blocks for a message
-pub async fn insert_message_blocks(message_id: &str, message_blocks: &MessageBlocks, data: DataState<'_>) {
+pub async fn insert_message_blocks(message_id: &str, message_blocks: &MessageBlocks, db_pool: &SqlitePool) -> Result<(), String> {
 	let query = "INSERT INTO message_blocks (message_id, type_, language, raw_content, rendered_content, copied) VALUES ($1, $2, $3, $4, $5, $6)";
 	for block in message_blocks.iter() {
-		let result = sqlx::query(query)
+		sqlx::query(query)
 			.bind(message_id)
 			.bind(&block.type_)
 			.bind(&block.language)
 			.bind(&block.raw_content)
 			.bind(&block.rendered_content)
 			.bind(0)
-			.execute(&data.0.lock().await.db_pool)
-			.await;
-		if let Err(e) = result {
-			log::error!("Error inserting message blocks into database: {}", e);
-		}
+			.execute(db_pool)
+			.await
+			.map_err(|e| format!("Error inserting message blocks: {}", e))?;
 	}
+	Ok(())
 }
@@ -2,29 +2,73 @@
 //!
 //! This module is organized into submodules by domain:
 //! - `init` - Database initialization and default models
+//! - `attachments` - Files attached to messages
 //! - `chats` - Chat CRUD operations
+//! - `collections` - Knowledge base collections and their documents
+//! - `embeddings` - Message embeddings and semantic search
+//! - `mcp_servers` - MCP server definitions
 //! - `messages` - Message CRUD operations
 //! - `models` - Model CRUD operations
 //! - `providers_db` - Provider/API key operations
+//! - `search` - Full-text search over messages and chat names
+//! - `usage` - Token usage and cost queries
 
 // Make submodules public so Tauri command macros can access generated symbols
+pub mod attachments;
 pub mod chats;
+pub mod collections;
+pub mod embeddings;
 pub mod init;
+pub mod mcp_servers;
 pub mod messages;
 pub mod models;
 pub mod providers_db;
+pub mod search;
+pub mod usage;
 
 // Re-export initialization
 pub use init::{init, DEFAULT_MODELS};
 
+// Re-export attachment operations
+pub use attachments::{
+	add_attachment, delete_chat_attachments, find_pending_attachments, get_attachments, link_attachments, load_history_attachments, remove_attachment,
+	serve_attachment, ATTACHMENTS_DIR,
+};
+
 // Re-export chat operations
-pub use chats::{archive_chat, delete_chat, get_chat_display_name, get_chats, insert_chat_display_name, rename_chat};
+pub use chats::{
+	archive_chat, delete_chat, find_chat_parameters, find_chat_system_prompt, fork_chat, get_chat_display_name, get_chat_parameters, get_chat_system_prompt,
+	get_chats, insert_chat_display_name, rename_chat, set_chat_system_prompt, update_chat_parameters,
+};
+
+// Re-export collection operations
+pub use collections::{
+	add_collection_documents, create_collection, delete_collection, find_chat_collections, find_collection, find_collection_chunks, get_chat_collections,
+	get_collection_documents, get_collections, remove_collection_document, rename_collection, set_chat_collections,
+};
+
+// Re-export embedding operations
+pub use embeddings::{find_unembedded_messages, get_embedding_status, get_similar_chats, save_embedding, search_similar_chats};
+
+// Re-export MCP server operations
+pub use mcp_servers::{delete_mcp_server, find_mcp_server, get_enabled_mcp_servers, get_mcp_servers, save_mcp_server};
 
 // Re-export message operations
-pub use messages::{get_messages, insert_message, insert_message_blocks, load_chat};
+pub use messages::{
+	delete_message, find_message, get_active_message_id, get_branch_messages, get_message_versions, get_messages, insert_message, insert_message_blocks,
+	load_chat, set_active_message_id, switch_message_version, update_message_content,
+};
 
 // Re-export model operations
-pub use models::{add_model, delete_model, get_all_models, get_models, update_model};
+pub use models::{
+	add_model, delete_model, find_model, get_all_models, get_model, get_models, import_models, list_provider_models, refresh_ollama_models, update_model,
+};
 
 // Re-export provider operations
-pub use providers_db::{add_provider, delete_provider, get_api_key, load_providers, read_api_keys_from_env, set_api_key, update_provider};
+pub use providers_db::{add_provider, delete_provider, find_provider, get_api_key, load_providers, read_api_keys_from_env, set_api_key, update_provider};
+
+// Re-export search
+pub use search::{find_search_hits, search_chats, MATCH_END, MATCH_START};
+
+// Re-export usage queries
+pub use usage::{get_chat_usage, get_usage_by_day, get_usage_by_provider};
@@ -4,7 +4,15 @@ use sqlx::SqlitePool;
 use tauri::command;
 
 use crate::data::DataState;
-use crate::types::{Model, Models, ModelUpdate};
+use crate::llm_providers::{list_models, list_ollama_models, ApiScheme, RequestOptions, OLLAMA_DEFAULT_CONTEXT, OLLAMA_DEFAULT_URL};
+use crate::providers::ProviderData;
+use crate::types::{AvailableModel, Model, ModelUpdate, Models};
+
+/// Context size of imported models whose provider does not report one
+const UNKNOWN_CONTEXT_WINDOW: u32 = 8192;
+
+/// Output limit of discovered models, capped by their context size
+const DISCOVERED_MAX_TOKENS: u32 = 4096;
 
 /// Insert a model into the database if it doesn't already exist
 pub async fn insert_model(model: &Model, pool: &SqlitePool) -> Result<(), String> {
@@ -19,7 +27,7 @@ pub async fn insert_model(model: &Model, pool: &SqlitePool) -> Result<(), String
 		return Ok(());
 	}
 
-	let insert_query = "INSERT INTO models (provider_name, model_name, model_display_name, show, max_tokens, context_window) VALUES ($1, $2, $3, $4, $5, $6)";
+	let insert_query = "INSERT INTO models (provider_name, model_name, model_display_name, show, max_tokens, context_window, input_price, output_price, supports_vision) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)";
 	let _ = sqlx::query(insert_query)
 		.bind(&model.provider_name)
 		.bind(&model.model_name)
@@ -27,17 +35,41 @@ pub async fn insert_model(model: &Model, pool: &SqlitePool) -> Result<(), String
 		.bind(&model.show)
 		.bind(&model.max_tokens)
 		.bind(&model.context_window)
+		.bind(model.input_price)
+		.bind(model.output_price)
+		.bind(model.supports_vision)
 		.execute(pool)
 		.await;
 	Ok(())
 }
 
+/// Get a single model by provider and model name
+pub async fn get_model(provider_name: &str, model_name: &str, data: DataState<'_>) -> Result<Option<Model>, String> {
+	let pool = data.0.lock().await.db_pool.clone();
+	find_model(provider_name, model_name, &pool).await
+}
+
+/// Get a single model by provider and model name, for callers without the app state
+pub async fn find_model(provider_name: &str, model_name: &str, pool: &SqlitePool) -> Result<Option<Model>, String> {
+	let query =
+		"SELECT provider_name, model_name, model_display_name, show, max_tokens, context_window, input_price, output_price, deprecated, supports_vision FROM models WHERE provider_name = $1 AND model_name = $2";
+	sqlx::query_as::<_, Model>(query)
+		.bind(provider_name)
+		.bind(model_name)
+		.fetch_optional(pool)
+		.await
+		.map_err(|e| {
+			log::error!("Error fetching model from database: {}", e);
+			e.to_string()
+		})
+}
+
 /// Get models that have valid API keys configured
 #[command]
 #[specta::specta]
 pub async fn get_models(data: DataState<'_>) -> Result<Models, String> {
 	let data = data.0.lock().await;
-	let query = "SELECT provider_name, model_name, model_display_name, show, max_tokens, context_window FROM models WHERE provider_name IN (SELECT provider_name FROM providers WHERE api_key != '') OR provider_name = 'local'";
+	let query = "SELECT provider_name, model_name, model_display_name, show, max_tokens, context_window, input_price, output_price, deprecated, supports_vision FROM models WHERE provider_name IN (SELECT provider_name FROM providers WHERE api_key != '' OR provider_name = 'ollama') OR provider_name = 'local'";
 	let result = sqlx::query_as::<_, Model>(query).fetch_all(&data.db_pool).await;
 	match result {
 		Ok(models) => Ok(Models(models)),
@@ -53,7 +85,7 @@ pub async fn get_models(data: DataState<'_>) -> Result<Models, String> {
 #[specta::specta]
 pub async fn get_all_models(data: DataState<'_>) -> Result<Models, String> {
 	let data = data.0.lock().await;
-	let query = "SELECT provider_name, model_name, model_display_name, show, max_tokens, context_window FROM models";
+	let query = "SELECT provider_name, model_name, model_display_name, show, max_tokens, context_window, input_price, output_price, deprecated, supports_vision FROM models";
 	let result = sqlx::query_as::<_, Model>(query).fetch_all(&data.db_pool).await;
 	match result {
 		Ok(models) => Ok(Models(models)),
@@ -82,7 +114,7 @@ pub async fn add_model(model: Model, data: DataState<'_>) -> Result<(), String>
 		return Err("A model with this provider and name already exists".to_string());
 	}
 
-	let query = "INSERT INTO models (provider_name, model_name, model_display_name, show, max_tokens, context_window) VALUES ($1, $2, $3, $4, $5, $6)";
+	let query = "INSERT INTO models (provider_name, model_name, model_display_name, show, max_tokens, context_window, input_price, output_price, supports_vision) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)";
 	match sqlx::query(query)
 		.bind(&model.provider_name)
 		.bind(&model.model_name)
@@ -90,6 +122,9 @@ pub async fn add_model(model: Model, data: DataState<'_>) -> Result<(), String>
 		.bind(&model.show)
 		.bind(&model.max_tokens)
 		.bind(&model.context_window)
+		.bind(model.input_price)
+		.bind(model.output_price)
+		.bind(model.supports_vision)
 		.execute(&data.db_pool)
 		.await
 	{
@@ -122,7 +157,7 @@ pub async fn update_model(update: ModelUpdate, data: DataState<'_>) -> Result<()
 		}
 	}
 
-	let query = "UPDATE models SET provider_name = $1, model_name = $2, model_display_name = $3, show = $4, max_tokens = $5, context_window = $6 WHERE provider_name = $7 AND model_name = $8";
+	let query = "UPDATE models SET provider_name = $1, model_name = $2, model_display_name = $3, show = $4, max_tokens = $5, context_window = $6, input_price = $7, output_price = $8, supports_vision = $9 WHERE provider_name = $10 AND model_name = $11";
 	match sqlx::query(query)
 		.bind(&model.provider_name)
 		.bind(&model.model_name)
@@ -130,6 +165,9 @@ pub async fn update_model(update: ModelUpdate, data: DataState<'_>) -> Result<()
 		.bind(&model.show)
 		.bind(&model.max_tokens)
 		.bind(&model.context_window)
+		.bind(model.input_price)
+		.bind(model.output_price)
+		.bind(model.supports_vision)
 		.bind(&update.original_provider_name)
 		.bind(&update.original_model_name)
 		.execute(&data.db_pool)
@@ -169,3 +207,154 @@ pub async fn delete_model(provider_name: String, model_name: String, data: DataS
 		}
 	}
 }
+
+/// Sync the models installed on the Ollama server into the models table.
+/// New models are added, known ones get their context size updated and are shown again if they were reinstalled,
+/// models that are no longer installed are hidden.
+/// Returns the installed models.
+#[command]
+#[specta::specta]
+pub async fn refresh_ollama_models(data: DataState<'_>) -> Result<Models, String> {
+	let base_url = sqlx::query_as::<_, (Option<String>,)>("SELECT base_url FROM providers WHERE provider_name = 'ollama'")
+		.fetch_optional(&data.0.lock().await.db_pool)
+		.await
+		.map_err(|e| format!("Error fetching Ollama server address: {}", e))?
+		.and_then(|(base_url,)| base_url)
+		.filter(|base_url| !base_url.is_empty())
+		.unwrap_or_else(|| OLLAMA_DEFAULT_URL.to_string());
+
+	let installed = list_ollama_models(&base_url).await.map_err(|e| {
+		log::error!("Error listing Ollama models at {}: {}", base_url, e);
+		e.to_string()
+	})?;
+	log::info!("Found {} models on Ollama server {}", installed.len(), base_url);
+
+	let data = data.0.lock().await;
+	let mut tx = data.db_pool.begin().await.map_err(|e| e.to_string())?;
+	for model in &installed {
+		let updated = sqlx::query(
+			"UPDATE models SET context_window = COALESCE($1, context_window), show = CASE WHEN deprecated THEN TRUE ELSE show END, deprecated = FALSE \
+			WHERE provider_name = 'ollama' AND model_name = $2",
+		)
+		.bind(model.context_window)
+		.bind(&model.name)
+		.execute(&mut *tx)
+		.await
+		.map_err(|e| format!("Error updating Ollama model: {}", e))?;
+		if updated.rows_affected() > 0 {
+			continue;
+		}
+
+		let context_window = model.context_window.unwrap_or(OLLAMA_DEFAULT_CONTEXT);
+		let insert_query =
+			"INSERT INTO models (provider_name, model_name, model_display_name, show, max_tokens, context_window) VALUES ('ollama', $1, $2, $3, $4, $5)";
+		sqlx::query(insert_query)
+			.bind(&model.name)
+			.bind(&model.name)
+			.bind(true)
+			.bind(DISCOVERED_MAX_TOKENS.min(context_window))
+			.bind(context_window)
+			.execute(&mut *tx)
+			.await
+			.map_err(|e| format!("Error adding Ollama model: {}", e))?;
+	}
+
+	// Model names are passed as JSON array to compare against all of them in a single query
+	let names = serde_json::to_string(&installed.iter().map(|model| model.name.as_str()).collect::<Vec<_>>()).map_err(|e| e.to_string())?;
+	sqlx::query("UPDATE models SET show = FALSE, deprecated = TRUE WHERE provider_name = 'ollama' AND model_name NOT IN (SELECT value FROM json_each($1))")
+		.bind(&names)
+		.execute(&mut *tx)
+		.await
+		.map_err(|e| format!("Error hiding removed Ollama models: {}", e))?;
+	tx.commit().await.map_err(|e| e.to_string())?;
+
+	let query = "SELECT provider_name, model_name, model_display_name, show, max_tokens, context_window, input_price, output_price, deprecated, supports_vision FROM models \
+		WHERE provider_name = 'ollama' AND model_name IN (SELECT value FROM json_each($1))";
+	match sqlx::query_as::<_, Model>(query).bind(&names).fetch_all(&data.db_pool).await {
+		Ok(models) => Ok(Models(models)),
+		Err(e) => {
+			log::error!("Error fetching Ollama models from database: {}", e);
+			Err(e.to_string())
+		}
+	}
+}
+
+/// List the models offered by a provider's model endpoint, marking the ones that are already imported.
+/// Imported models the provider no longer lists are flagged as deprecated.
+#[command]
+#[specta::specta]
+pub async fn list_provider_models(provider_name: String, data: DataState<'_>) -> Result<Vec<AvailableModel>, String> {
+	let query = "SELECT provider_name, api_key, display_name, api_key_valid, base_url, api_scheme, is_custom, extra_headers, query_params FROM providers WHERE provider_name = $1";
+	let provider = sqlx::query_as::<_, ProviderData>(query)
+		.bind(&provider_name)
+		.fetch_optional(&data.0.lock().await.db_pool)
+		.await
+		.map_err(|e| format!("Error fetching provider: {}", e))?
+		.ok_or_else(|| "Provider not found".to_string())?;
+	let api_scheme = provider
+		.api_scheme
+		.as_deref()
+		.and_then(ApiScheme::from_str)
+		.ok_or_else(|| format!("Provider {} has no supported API scheme", provider_name))?;
+	let options = RequestOptions {
+		headers: provider.extra_headers,
+		query: provider.query_params,
+	};
+
+	let mut listed = list_models(&provider_name, &api_scheme, &provider.api_key, provider.base_url.as_deref(), &options)
+		.await
+		.map_err(|e| {
+			log::error!("Error listing models of provider {}: {}", provider_name, e);
+			e.to_string()
+		})?;
+	listed.sort_by(|a, b| a.id.cmp(&b.id));
+
+	let data = data.0.lock().await;
+	let names = serde_json::to_string(&listed.iter().map(|model| model.id.as_str()).collect::<Vec<_>>()).map_err(|e| e.to_string())?;
+	sqlx::query("UPDATE models SET deprecated = (model_name NOT IN (SELECT value FROM json_each($1))) WHERE provider_name = $2")
+		.bind(&names)
+		.bind(&provider_name)
+		.execute(&data.db_pool)
+		.await
+		.map_err(|e| format!("Error flagging deprecated models: {}", e))?;
+
+	let imported: Vec<(String,)> = sqlx::query_as("SELECT model_name FROM models WHERE provider_name = $1")
+		.bind(&provider_name)
+		.fetch_all(&data.db_pool)
+		.await
+		.map_err(|e| format!("Error fetching models: {}", e))?;
+
+	Ok(listed
+		.into_iter()
+		.map(|model| AvailableModel {
+			imported: imported.iter().any(|(model_name,)| *model_name == model.id),
+			model_name: model.id,
+			display_name: model.display_name,
+			context_window: model.context_window,
+		})
+		.collect())
+}
+
+/// Add models listed by `list_provider_models` to the models table, models that already exist are skipped
+#[command]
+#[specta::specta]
+pub async fn import_models(provider_name: String, models: Vec<AvailableModel>, data: DataState<'_>) -> Result<(), String> {
+	let data = data.0.lock().await;
+	for model in models {
+		let context_window = model.context_window.unwrap_or(UNKNOWN_CONTEXT_WINDOW);
+		let model = Model {
+			provider_name: provider_name.clone(),
+			model_display_name: model.display_name.unwrap_or_else(|| model.model_name.clone()),
+			model_name: model.model_name,
+			show: true,
+			max_tokens: DISCOVERED_MAX_TOKENS.min(context_window),
+			context_window,
+			input_price: None,
+			output_price: None,
+			deprecated: false,
+			supports_vision: false,
+		};
+		insert_model(&model, &data.db_pool).await?;
+	}
+	Ok(())
+}
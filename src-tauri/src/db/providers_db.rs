@@ -1,25 +1,54 @@
 //! Provider-related database operations
 
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::env;
 
 use dotenv::dotenv;
+use sqlx::sqlite::SqliteRow;
+use sqlx::{Row, SqlitePool};
 use tauri::command;
 
 use crate::data::DataState;
-use crate::llm_providers::{LLMConfig, Provider};
+use crate::llm_providers::LLMConfig;
 use crate::providers::ProviderData;
 use crate::throw;
 use crate::types::{Message, MessageHistory};
 
 use super::init::DEFAULT_MODELS;
 
+// Implement FromRow for ProviderData, extra headers and query parameters are stored as JSON objects
+impl sqlx::FromRow<'_, SqliteRow> for ProviderData {
+	fn from_row(row: &SqliteRow) -> Result<Self, sqlx::Error> {
+		let extra_headers: Option<String> = row.try_get("extra_headers")?;
+		let query_params: Option<String> = row.try_get("query_params")?;
+		Ok(ProviderData {
+			provider_name: row.try_get("provider_name")?,
+			api_key: row.try_get("api_key")?,
+			display_name: row.try_get("display_name")?,
+			api_key_valid: row.try_get("api_key_valid")?,
+			base_url: row.try_get("base_url")?,
+			api_scheme: row.try_get("api_scheme")?,
+			is_custom: row.try_get("is_custom")?,
+			extra_headers: extra_headers.and_then(|s| serde_json::from_str(&s).ok()).unwrap_or_default(),
+			query_params: query_params.and_then(|s| serde_json::from_str(&s).ok()).unwrap_or_default(),
+		})
+	}
+}
+
+/// Serialize extra headers or query parameters for storage, `None` if there are none
+fn to_json_column(values: &BTreeMap<String, String>) -> Result<Option<String>, String> {
+	if values.is_empty() {
+		return Ok(None);
+	}
+	serde_json::to_string(values).map(Some).map_err(|e| e.to_string())
+}
+
 /// Load all provider configurations
 #[command]
 #[specta::specta]
 pub async fn load_providers(data: DataState<'_>) -> Result<Vec<ProviderData>, String> {
 	let data = data.0.lock().await;
-	let query = "SELECT provider_name, api_key, display_name, api_key_valid, base_url, api_scheme, is_custom FROM providers";
+	let query = "SELECT provider_name, api_key, display_name, api_key_valid, base_url, api_scheme, is_custom, extra_headers, query_params FROM providers";
 	let providers = sqlx::query_as::<_, ProviderData>(query);
 	match providers.fetch_all(&data.db_pool).await {
 		Ok(providers) => {
@@ -35,7 +64,14 @@ pub async fn load_providers(data: DataState<'_>) -> Result<Vec<ProviderData>, St
 #[specta::specta]
 pub async fn set_api_key(provider: ProviderData, data: DataState<'_>) -> Result<(), String> {
 	let data = data.0.lock().await;
-	let is_valid = validate_api_key(&provider).await?;
+
+	// Prefer a model configured for this provider, Azure deployments for example have no default
+	let configured_model: Option<(String,)> = sqlx::query_as("SELECT model_name FROM models WHERE provider_name = $1 AND show = TRUE LIMIT 1")
+		.bind(&provider.provider_name)
+		.fetch_optional(&data.db_pool)
+		.await
+		.map_err(|e| format!("Error fetching models of provider {}: {}", &provider.provider_name, e))?;
+	let is_valid = validate_api_key(&provider, configured_model.map(|(model_name,)| model_name)).await?;
 
 	log::info!("API key for provider {} is valid: {}", &provider.provider_name, is_valid);
 
@@ -54,29 +90,34 @@ pub async fn set_api_key(provider: ProviderData, data: DataState<'_>) -> Result<
 }
 
 /// Validate an API key by sending a test message
-async fn validate_api_key(provider: &ProviderData) -> Result<bool, String> {
+async fn validate_api_key(provider: &ProviderData, configured_model: Option<String>) -> Result<bool, String> {
 	// For custom providers, try to find a model associated with this provider
 	// or use a default test model name
-	let model_name = DEFAULT_MODELS
-		.iter()
-		.find(|m| m.provider_name == provider.provider_name)
-		.map(|m| m.model_name.clone())
+	let model_name = configured_model
+		.or_else(|| {
+			DEFAULT_MODELS
+				.iter()
+				.find(|m| m.provider_name == provider.provider_name)
+				.map(|m| m.model_name.clone())
+		})
 		.unwrap_or_else(|| "gpt-3.5-turbo".to_string()); // Fallback for custom providers
 
 	let llm_config = LLMConfig::default();
-	let llm = Provider::from_provider_data(
-		&provider.provider_name,
-		&provider.api_key,
-		provider.base_url.as_deref(),
-		provider.api_scheme.as_deref(),
-	).map_err(|e| format!("Failed to create provider: {}", e))?;
+	let llm = provider.to_provider().map_err(|e| format!("Failed to create provider: {}", e))?;
 
 	let messages = MessageHistory(vec![Message {
 		id: String::new(),
 		role: "user".to_string(),
 		content: "Hello".to_string(),
 		model_name: model_name.clone(),
+		parent_id: None,
+		provider_name: Some(provider.provider_name.clone()),
+		status: "ok".to_string(),
+		latency_ms: None,
+		prompt_tokens: None,
+		completion_tokens: None,
 		blocks: None,
+		attachments: Vec::new(),
 	}]);
 
 	match llm.send_message(&messages, &model_name, &llm_config).await {
@@ -92,6 +133,7 @@ async fn validate_api_key(provider: &ProviderData) -> Result<bool, String> {
 #[command]
 #[specta::specta]
 pub async fn add_provider(provider: ProviderData, data: DataState<'_>) -> Result<(), String> {
+	provider.validate_request_options()?;
 	let data = data.0.lock().await;
 
 	// Check if provider already exists
@@ -105,7 +147,8 @@ pub async fn add_provider(provider: ProviderData, data: DataState<'_>) -> Result
 		return Err("A provider with this name already exists".to_string());
 	}
 
-	let query = "INSERT INTO providers (provider_name, api_key, display_name, api_key_valid, base_url, api_scheme, is_custom) VALUES ($1, $2, $3, $4, $5, $6, $7)";
+	let query = "INSERT INTO providers (provider_name, api_key, display_name, api_key_valid, base_url, api_scheme, is_custom, extra_headers, query_params) \
+		VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)";
 	sqlx::query(query)
 		.bind(&provider.provider_name)
 		.bind(&provider.api_key)
@@ -114,6 +157,8 @@ pub async fn add_provider(provider: ProviderData, data: DataState<'_>) -> Result
 		.bind(&provider.base_url)
 		.bind(&provider.api_scheme)
 		.bind(true) // is_custom = true for new providers
+		.bind(to_json_column(&provider.extra_headers)?)
+		.bind(to_json_column(&provider.query_params)?)
 		.execute(&data.db_pool)
 		.await
 		.map_err(|e| format!("Error adding provider: {}", e))?;
@@ -125,14 +170,18 @@ pub async fn add_provider(provider: ProviderData, data: DataState<'_>) -> Result
 #[command]
 #[specta::specta]
 pub async fn update_provider(provider: ProviderData, data: DataState<'_>) -> Result<(), String> {
+	provider.validate_request_options()?;
 	let data = data.0.lock().await;
 
-	let query = "UPDATE providers SET display_name = $1, base_url = $2, api_scheme = $3, api_key = $4 WHERE provider_name = $5";
+	let query =
+		"UPDATE providers SET display_name = $1, base_url = $2, api_scheme = $3, api_key = $4, extra_headers = $5, query_params = $6 WHERE provider_name = $7";
 	let result = sqlx::query(query)
 		.bind(&provider.display_name)
 		.bind(&provider.base_url)
 		.bind(&provider.api_scheme)
 		.bind(&provider.api_key)
+		.bind(to_json_column(&provider.extra_headers)?)
+		.bind(to_json_column(&provider.query_params)?)
 		.bind(&provider.provider_name)
 		.execute(&data.db_pool)
 		.await
@@ -181,6 +230,16 @@ pub async fn delete_provider(provider_name: String, data: DataState<'_>) -> Resu
 	}
 }
 
+/// Get the configuration of a provider
+pub async fn find_provider(provider_name: &str, pool: &SqlitePool) -> Result<Option<ProviderData>, String> {
+	let query = "SELECT provider_name, api_key, display_name, api_key_valid, base_url, api_scheme, is_custom, extra_headers, query_params FROM providers WHERE provider_name = $1";
+	sqlx::query_as::<_, ProviderData>(query)
+		.bind(provider_name)
+		.fetch_optional(pool)
+		.await
+		.map_err(|e| format!("Error fetching provider {}: {}", provider_name, e))
+}
+
 /// Get the API key for a specific provider
 pub async fn get_api_key(provider_name: &str, data: DataState<'_>) -> Result<String, String> {
 	let query = "SELECT api_key FROM providers WHERE provider_name = $1";
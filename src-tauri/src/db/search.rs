@@ -0,0 +1,100 @@
+//! Full-text search over the messages and chat names, backed by the FTS5 indexes messages_fts and chats_fts
+
+use sqlx::SqlitePool;
+use tauri::command;
+
+use crate::data::DataState;
+use crate::types::{SearchFilters, SearchHit};
+
+/// Characters FTS5 wraps around matched words in snippets, replaced once the snippet is escaped
+pub const MATCH_START: char = '\u{E000}';
+pub const MATCH_END: char = '\u{E001}';
+
+/// Number of results returned if the filters set no limit
+const DEFAULT_SEARCH_LIMIT: u32 = 50;
+
+/// Number of words of a snippet
+const SNIPPET_WORDS: u32 = 16;
+
+/// Search all chats for messages and chat names containing the words of a query.
+/// The last word also matches as prefix so results show up while typing.
+#[command]
+#[specta::specta]
+pub async fn search_chats(query: String, filters: Option<SearchFilters>, data: DataState<'_>) -> Result<Vec<SearchHit>, String> {
+	let pool = data.0.lock().await.db_pool.clone();
+	let mut hits = find_search_hits(&query, &filters.unwrap_or_default(), &pool).await?;
+	for hit in hits.iter_mut() {
+		hit.snippet = highlight_snippet(&hit.snippet);
+	}
+	Ok(hits)
+}
+
+/// Ranked search hits, matched words of the snippets are wrapped in `MATCH_START` and `MATCH_END`
+pub async fn find_search_hits(query: &str, filters: &SearchFilters, pool: &SqlitePool) -> Result<Vec<SearchHit>, String> {
+	let Some(match_query) = fts_query(query) else {
+		return Ok(Vec::new());
+	};
+	let search_query = format!(
+		"SELECT chat_id, chat_display_name, message_id, role, model_name, snippet, created_at, archived FROM (
+			SELECT chats.id AS chat_id, chats.display_name AS chat_display_name, messages.id AS message_id, messages.role AS role,
+				messages.model_name AS model_name, snippet(messages_fts, 0, char({start}), char({end}), '…', {words}) AS snippet,
+				messages.created_at AS created_at, chats.archived = 'true' AS archived, bm25(messages_fts) AS rank
+			FROM messages_fts JOIN messages_fts_map ON messages_fts_map.fts_rowid = messages_fts.rowid
+				JOIN messages ON messages.id = messages_fts_map.message_id JOIN chats ON chats.id = messages.chat_id
+			WHERE messages_fts MATCH $1 AND messages.status != 'error'
+				AND ($2 IS NULL OR messages.model_name = $2)
+				AND ($3 IS NULL OR messages.created_at >= $3)
+				AND ($4 IS NULL OR messages.created_at < date($4, '+1 day'))
+				AND ($5 IS NULL OR (chats.archived = 'true') = $5)
+			UNION ALL
+			SELECT chats.id, chats.display_name, NULL, NULL, chats.model, snippet(chats_fts, 0, char({start}), char({end}), '…', {words}),
+				COALESCE(chats.last_updated, chats.creation_date), chats.archived = 'true', bm25(chats_fts)
+			FROM chats_fts JOIN chats_fts_map ON chats_fts_map.fts_rowid = chats_fts.rowid JOIN chats ON chats.id = chats_fts_map.chat_id
+			WHERE chats_fts MATCH $1
+				AND ($2 IS NULL OR chats.model = $2)
+				AND ($3 IS NULL OR COALESCE(chats.last_updated, chats.creation_date) >= $3)
+				AND ($4 IS NULL OR COALESCE(chats.last_updated, chats.creation_date) < date($4, '+1 day'))
+				AND ($5 IS NULL OR (chats.archived = 'true') = $5)
+		)
+		ORDER BY rank LIMIT $6",
+		start = MATCH_START as u32,
+		end = MATCH_END as u32,
+		words = SNIPPET_WORDS
+	);
+	sqlx::query_as::<_, SearchHit>(&search_query)
+		.bind(match_query)
+		.bind(&filters.model_name)
+		.bind(&filters.from_date)
+		.bind(&filters.to_date)
+		.bind(filters.archived)
+		.bind(filters.limit.unwrap_or(DEFAULT_SEARCH_LIMIT))
+		.fetch_all(pool)
+		.await
+		.map_err(|e| {
+			log::error!("Error searching chats: {}", e);
+			e.to_string()
+		})
+}
+
+/// FTS5 query matching all words of the input. Words are quoted so operators and special characters are searched literally.
+fn fts_query(input: &str) -> Option<String> {
+	let words: Vec<String> = input
+		.split_whitespace()
+		.map(|word| word.replace('"', ""))
+		.filter(|word| !word.is_empty())
+		.map(|word| format!("\"{}\"", word))
+		.collect();
+	let last = words.last()?;
+	Some(format!("{} {}*", words[..words.len() - 1].join(" "), last).trim_start().to_string())
+}
+
+/// Escape a snippet for the webview and mark the matched words
+fn highlight_snippet(snippet: &str) -> String {
+	snippet
+		.replace('&', "&amp;")
+		.replace('<', "&lt;")
+		.replace('>', "&gt;")
+		.replace('"', "&quot;")
+		.replace(MATCH_START, "<mark>")
+		.replace(MATCH_END, "</mark>")
+}
@@ -0,0 +1,73 @@
+//! Token usage and cost queries
+
+use tauri::command;
+
+use crate::data::DataState;
+use crate::types::UsageSummary;
+
+/// Aggregates the usage of answers, `{label}` is replaced by the expression the answers are grouped by.
+/// Costs are calculated with the current prices of the models.
+const USAGE_QUERY: &str = "
+	SELECT
+		{label} AS label,
+		COUNT(*) AS messages,
+		COALESCE(SUM(messages.prompt_tokens), 0) AS prompt_tokens,
+		COALESCE(SUM(messages.completion_tokens), 0) AS completion_tokens,
+		COALESCE(SUM((COALESCE(messages.prompt_tokens, 0) * COALESCE(models.input_price, 0)
+			+ COALESCE(messages.completion_tokens, 0) * COALESCE(models.output_price, 0)) / 1000000.0), 0.0) AS cost,
+		COALESCE(SUM(CASE WHEN models.input_price IS NULL OR models.output_price IS NULL THEN 1 ELSE 0 END), 0) AS unpriced_messages
+	FROM messages
+	LEFT JOIN models ON models.provider_name = messages.provider_name AND models.model_name = messages.model_name
+	WHERE messages.role = 'assistant' AND (messages.prompt_tokens IS NOT NULL OR messages.completion_tokens IS NOT NULL)";
+
+/// Optional date range filter, dates are formatted as YYYY-MM-DD and inclusive
+const DATE_FILTER: &str = "AND ($1 IS NULL OR date(messages.created_at) >= $1) AND ($2 IS NULL OR date(messages.created_at) <= $2)";
+
+/// Get the token usage and cost of a chat
+#[command]
+#[specta::specta]
+pub async fn get_chat_usage(chat_id: String, data: DataState<'_>) -> Result<UsageSummary, String> {
+	let query = format!("{} AND messages.chat_id = $1", USAGE_QUERY.replace("{label}", "$1"));
+	sqlx::query_as::<_, UsageSummary>(&query)
+		.bind(&chat_id)
+		.fetch_one(&data.0.lock().await.db_pool)
+		.await
+		.map_err(|e| {
+			log::error!("Error fetching chat usage from database: {}", e);
+			e.to_string()
+		})
+}
+
+/// Get the token usage and cost per provider, optionally limited to a date range
+#[command]
+#[specta::specta]
+pub async fn get_usage_by_provider(since: Option<String>, until: Option<String>, data: DataState<'_>) -> Result<Vec<UsageSummary>, String> {
+	let label = "COALESCE(messages.provider_name, 'unknown')";
+	let query = format!("{} {} GROUP BY label ORDER BY cost DESC", USAGE_QUERY.replace("{label}", label), DATE_FILTER);
+	sqlx::query_as::<_, UsageSummary>(&query)
+		.bind(&since)
+		.bind(&until)
+		.fetch_all(&data.0.lock().await.db_pool)
+		.await
+		.map_err(|e| {
+			log::error!("Error fetching usage by provider from database: {}", e);
+			e.to_string()
+		})
+}
+
+/// Get the token usage and cost per day, optionally limited to a date range
+#[command]
+#[specta::specta]
+pub async fn get_usage_by_day(since: Option<String>, until: Option<String>, data: DataState<'_>) -> Result<Vec<UsageSummary>, String> {
+	let label = "COALESCE(date(messages.created_at), 'unknown')";
+	let query = format!("{} {} GROUP BY label ORDER BY label", USAGE_QUERY.replace("{label}", label), DATE_FILTER);
+	sqlx::query_as::<_, UsageSummary>(&query)
+		.bind(&since)
+		.bind(&until)
+		.fetch_all(&data.0.lock().await.db_pool)
+		.await
+		.map_err(|e| {
+			log::error!("Error fetching usage by day from database: {}", e);
+			e.to_string()
+		})
+}
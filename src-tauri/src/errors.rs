@@ -1,6 +1,7 @@
 //! Application error types for better error handling and context
 
 use std::fmt;
+use std::time::Duration;
 
 /// Application-level error types
 #[derive(Debug)]
@@ -116,12 +117,16 @@ pub enum ProviderError {
 	Unsupported(String),
 	/// Invalid API key
 	InvalidApiKey { provider: String, reason: String },
-	/// Failed to send message
-	MessageFailed { provider: String, details: String },
-	/// Rate limited
-	RateLimited { provider: String },
+	/// Failed to send message, with the HTTP status if the provider answered with one
+	MessageFailed { provider: String, details: String, status: Option<u16> },
+	/// Rate limited, with the delay the provider asked for if it sent one
+	RateLimited { provider: String, retry_after: Option<Duration> },
 	/// Provider configuration error
 	Configuration { provider: String, details: String },
+	/// Failed to list the models a provider offers
+	ModelListFailed { provider: String, details: String },
+	/// Failed to embed texts with an embedding model
+	EmbeddingFailed { provider: String, details: String },
 }
 
 impl fmt::Display for ProviderError {
@@ -133,21 +138,46 @@ impl fmt::Display for ProviderError {
 			ProviderError::InvalidApiKey { provider, reason } => {
 				write!(f, "Invalid API key for {}: {}", provider, reason)
 			}
-			ProviderError::MessageFailed { provider, details } => {
+			ProviderError::MessageFailed { provider, details, .. } => {
 				write!(f, "Failed to send message to {}: {}", provider, details)
 			}
-			ProviderError::RateLimited { provider } => {
+			ProviderError::RateLimited { provider, .. } => {
 				write!(f, "Rate limited by {}", provider)
 			}
 			ProviderError::Configuration { provider, details } => {
 				write!(f, "Configuration error for {}: {}", provider, details)
 			}
+			ProviderError::ModelListFailed { provider, details } => {
+				write!(f, "Failed to list models of {}: {}", provider, details)
+			}
+			ProviderError::EmbeddingFailed { provider, details } => {
+				write!(f, "Failed to embed texts with {}: {}", provider, details)
+			}
 		}
 	}
 }
 
 impl std::error::Error for ProviderError {}
 
+impl ProviderError {
+	/// Whether the request may succeed when sent again (rate limits and server errors)
+	pub fn is_retryable(&self) -> bool {
+		match self {
+			ProviderError::RateLimited { .. } => true,
+			ProviderError::MessageFailed { status: Some(status), .. } => *status >= 500,
+			_ => false,
+		}
+	}
+
+	/// Delay the provider asked for before retrying
+	pub fn retry_after(&self) -> Option<Duration> {
+		match self {
+			ProviderError::RateLimited { retry_after, .. } => *retry_after,
+			_ => None,
+		}
+	}
+}
+
 /// Configuration/settings errors
 #[derive(Debug)]
 pub enum ConfigError {
@@ -0,0 +1,397 @@
+//! Exporting chats as Markdown, as Byok JSON that can be imported again, or as standalone HTML pages
+
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use tauri::{command, WebviewWindow};
+use tauri_plugin_dialog::DialogExt;
+use tokio::sync::oneshot;
+
+use crate::data::DataState;
+use crate::db::find_chat_parameters;
+use crate::types::{Attachment, ChatArchive, ExportFormat, ExportedAttachment, ExportedChat, ExportedMessage, MessageBlock};
+
+/// Value of the `format` field of Byok JSON exports
+pub const ARCHIVE_FORMAT: &str = "byok";
+
+/// Version of the JSON export format, raised when it changes incompatibly
+pub const ARCHIVE_VERSION: u32 = 1;
+
+/// Longest file name derived from a chat name
+const MAX_FILE_NAME_LENGTH: usize = 80;
+
+/// Styles of exported HTML pages, following the colors of the app
+const HTML_STYLE: &str = "
+	body { background: #22262d; color: #e5e7eb; font-family: -apple-system, BlinkMacSystemFont, 'Segoe UI', sans-serif; margin: 0 auto; max-width: 56rem; padding: 2rem; }
+	h1 { font-size: 1.5rem; }
+	h2 { font-size: 1rem; margin: 0 0 0.5rem; }
+	section { border-top: 1px solid #3b4048; padding: 1rem 0; }
+	section.user .content { white-space: pre-wrap; }
+	.meta, .files { color: #9ca3af; font-size: 0.8rem; }
+	.code, details { margin: 0.5rem 0; }
+	.language, summary { background: #363b44; border-radius: 0.375rem 0.375rem 0 0; color: #d1d5db; font-family: monospace; font-size: 0.75rem; padding: 0.5rem 0.75rem; }
+	summary { border-radius: 0.375rem; cursor: pointer; }
+	.highlighted { font-family: monospace; font-size: 0.75rem; overflow-x: auto; white-space: pre-wrap; }
+	img { border-radius: 0.375rem; max-height: 24rem; max-width: 100%; }
+	table { border-collapse: collapse; }
+	td, th { border: 1px solid #3b4048; padding: 0.25rem 0.5rem; }
+";
+
+/// Export a chat to a file chosen by the user. Returns the path of the file, `None` if the user cancelled.
+#[command]
+#[specta::specta]
+pub async fn export_chat(chat_id: String, format: ExportFormat, data: DataState<'_>) -> Result<Option<String>, String> {
+	let chat = load_exported_chat(&chat_id, data.clone()).await?;
+	let window = data.0.lock().await.window.clone();
+	let file_name = format!("{}.{}", file_stem(&chat.display_name), extension(format));
+	let Some(path) = choose_file(&window, &file_name, format).await else {
+		return Ok(None);
+	};
+	write_export(&path, &render_chat(chat, format)?)?;
+	Ok(Some(path.to_string_lossy().to_string()))
+}
+
+/// Export several chats, all chats if no ids are given.
+/// JSON exports are written to one file, Markdown and HTML exports to one file per chat in a folder chosen by the user.
+/// Returns the path of the file or folder, `None` if the user cancelled.
+#[command]
+#[specta::specta]
+pub async fn export_chats(chat_ids: Option<Vec<String>>, format: ExportFormat, data: DataState<'_>) -> Result<Option<String>, String> {
+	let chat_ids = match chat_ids {
+		Some(chat_ids) => chat_ids,
+		None => sqlx::query_as::<_, (String,)>("SELECT id FROM chats ORDER BY creation_date, rowid")
+			.fetch_all(&data.0.lock().await.db_pool)
+			.await
+			.map_err(|e| {
+				log::error!("Error fetching chats from database: {}", e);
+				e.to_string()
+			})?
+			.into_iter()
+			.map(|(id,)| id)
+			.collect(),
+	};
+	if chat_ids.is_empty() {
+		return Err("There are no chats to export".to_string());
+	}
+	let mut chats = Vec::with_capacity(chat_ids.len());
+	for chat_id in &chat_ids {
+		chats.push(load_exported_chat(chat_id, data.clone()).await?);
+	}
+	let window = data.0.lock().await.window.clone();
+
+	if format == ExportFormat::Json {
+		let file_name = format!("byok-chats-{}.json", chrono::Local::now().format("%Y-%m-%d"));
+		let Some(path) = choose_file(&window, &file_name, format).await else {
+			return Ok(None);
+		};
+		write_export(&path, &archive_json(chats)?)?;
+		return Ok(Some(path.to_string_lossy().to_string()));
+	}
+
+	let Some(folder) = choose_folder(&window).await else {
+		return Ok(None);
+	};
+	let mut file_names = HashSet::new();
+	for chat in chats {
+		// Chats with the same name get numbered files
+		let stem = file_stem(&chat.display_name);
+		let mut file_name = format!("{}.{}", stem, extension(format));
+		let mut number = 1;
+		while !file_names.insert(file_name.clone()) {
+			number += 1;
+			file_name = format!("{} ({}).{}", stem, number, extension(format));
+		}
+		write_export(&folder.join(file_name), &render_chat(chat, format)?)?;
+	}
+	Ok(Some(folder.to_string_lossy().to_string()))
+}
+
+/// Load a chat with all versions of its messages, their blocks and attachments.
+/// Attached images are read from the app data directory and embedded.
+pub async fn load_exported_chat(chat_id: &str, data: DataState<'_>) -> Result<ExportedChat, String> {
+	let (pool, app_dir) = {
+		let data = data.0.lock().await;
+		(data.db_pool.clone(), data.paths.app_dir.clone())
+	};
+	let chat_query = "SELECT id, display_name, model, archived IS 'true' AS archived, system_prompt, creation_date, last_updated, active_message_id
+		FROM chats WHERE id = $1";
+	let mut chat = sqlx::query_as::<_, ExportedChat>(chat_query)
+		.bind(chat_id)
+		.fetch_optional(&pool)
+		.await
+		.map_err(|e| {
+			log::error!("Error fetching chat from database: {}", e);
+			e.to_string()
+		})?
+		.ok_or_else(|| "Chat not found".to_string())?;
+	chat.parameters = find_chat_parameters(chat_id, data).await?;
+
+	let messages_query = "SELECT id, parent_id, role, content, status, provider_name, model_name, latency_ms, prompt_tokens, completion_tokens, created_at
+		FROM messages WHERE chat_id = $1 ORDER BY rowid";
+	let blocks_query = "SELECT id, type_, language, raw_content, rendered_content, copied FROM message_blocks WHERE message_id = $1 ORDER BY id";
+	let attachments_query =
+		"SELECT id, kind, file_name, mime_type, size_bytes, path, content FROM attachments WHERE message_id = $1 ORDER BY created_at, rowid";
+	chat.messages = sqlx::query_as::<_, ExportedMessage>(messages_query)
+		.bind(chat_id)
+		.fetch_all(&pool)
+		.await
+		.map_err(|e| {
+			log::error!("Error fetching messages from database: {}", e);
+			e.to_string()
+		})?;
+	for message in chat.messages.iter_mut() {
+		message.blocks = sqlx::query_as::<_, MessageBlock>(blocks_query)
+			.bind(&message.id)
+			.fetch_all(&pool)
+			.await
+			.map_err(|e| format!("Error fetching message blocks: {}", e))?;
+		message.attachments = sqlx::query_as::<_, Attachment>(attachments_query)
+			.bind(&message.id)
+			.fetch_all(&pool)
+			.await
+			.map_err(|e| format!("Error fetching attachments: {}", e))?
+			.into_iter()
+			.map(|attachment| export_attachment(attachment, &app_dir))
+			.collect();
+	}
+	Ok(chat)
+}
+
+fn export_attachment(attachment: Attachment, app_dir: &Path) -> ExportedAttachment {
+	let data = match attachment.is_image() {
+		true => match fs::read(app_dir.join(&attachment.path)) {
+			Ok(bytes) => Some(BASE64.encode(bytes)),
+			Err(e) => {
+				log::warn!("Could not read attached image {}: {}", attachment.file_name, e);
+				None
+			}
+		},
+		false => None,
+	};
+	ExportedAttachment {
+		id: attachment.id,
+		kind: attachment.kind,
+		file_name: attachment.file_name,
+		mime_type: attachment.mime_type,
+		size_bytes: attachment.size_bytes,
+		content: attachment.content,
+		data,
+	}
+}
+
+fn render_chat(chat: ExportedChat, format: ExportFormat) -> Result<String, String> {
+	match format {
+		ExportFormat::Markdown => Ok(to_markdown(&chat)),
+		ExportFormat::Json => archive_json(vec![chat]),
+		ExportFormat::Html => Ok(to_html(&chat)),
+	}
+}
+
+fn archive_json(chats: Vec<ExportedChat>) -> Result<String, String> {
+	let archive = ChatArchive {
+		format: ARCHIVE_FORMAT.to_string(),
+		version: ARCHIVE_VERSION,
+		exported_at: chrono::Utc::now().to_rfc3339(),
+		chats,
+	};
+	serde_json::to_string_pretty(&archive).map_err(|e| e.to_string())
+}
+
+/// Messages of the active branch, from the first message to the active one
+fn active_branch(chat: &ExportedChat) -> Vec<&ExportedMessage> {
+	let messages: HashMap<&str, &ExportedMessage> = chat.messages.iter().map(|message| (message.id.as_str(), message)).collect();
+	let mut branch = Vec::new();
+	let mut next_id = chat.active_message_id.as_deref();
+	while let Some(message) = next_id.and_then(|id| messages.get(id)) {
+		// A broken tree must not loop forever
+		if branch.len() == messages.len() {
+			break;
+		}
+		branch.push(*message);
+		next_id = message.parent_id.as_deref();
+	}
+	branch.reverse();
+	branch
+}
+
+/// Heading of a message: "You" for prompts, the model name for answers
+fn author(message: &ExportedMessage) -> String {
+	let author = match message.role.as_str() {
+		"user" => "You".to_string(),
+		"system" => "System".to_string(),
+		_ => message.model_name.clone(),
+	};
+	match message.status.as_str() {
+		"error" => format!("{} (failed)", author),
+		"interrupted" => format!("{} (interrupted)", author),
+		_ => author,
+	}
+}
+
+fn to_markdown(chat: &ExportedChat) -> String {
+	let mut markdown = format!("# {}\n\n", chat.display_name);
+	if let Some(creation_date) = &chat.creation_date {
+		markdown.push_str(&format!("*Created {}*\n\n", creation_date));
+	}
+	if let Some(system_prompt) = &chat.system_prompt {
+		markdown.push_str(&format!("> **System prompt:** {}\n\n", system_prompt.trim().replace('\n', "\n> ")));
+	}
+	for message in active_branch(chat) {
+		markdown.push_str(&format!("## {}\n\n", author(message)));
+		if !message.attachments.is_empty() {
+			let file_names: Vec<&str> = message.attachments.iter().map(|attachment| attachment.file_name.as_str()).collect();
+			markdown.push_str(&format!("*Attached: {}*\n\n", file_names.join(", ")));
+		}
+		markdown.push_str(message.content.trim());
+		markdown.push_str("\n\n");
+	}
+	markdown
+}
+
+/// Standalone page of the active branch. Answers reuse their rendered blocks so code keeps its highlighting, images are embedded.
+fn to_html(chat: &ExportedChat) -> String {
+	let mut body = format!("<h1>{}</h1>\n", escape_html(&chat.display_name));
+	if let Some(creation_date) = &chat.creation_date {
+		body.push_str(&format!("<p class=\"meta\">Created {}</p>\n", escape_html(creation_date)));
+	}
+	if let Some(system_prompt) = &chat.system_prompt {
+		body.push_str(&format!("<p class=\"meta\">System prompt: {}</p>\n", escape_html(system_prompt)));
+	}
+
+	for message in active_branch(chat) {
+		body.push_str(&format!(
+			"<section class=\"{}\">\n<h2>{}</h2>\n",
+			escape_html(&message.role),
+			escape_html(&author(message))
+		));
+		let files: Vec<String> = message
+			.attachments
+			.iter()
+			.filter(|attachment| attachment.kind != "image")
+			.map(|attachment| escape_html(&attachment.file_name))
+			.collect();
+		if !files.is_empty() {
+			body.push_str(&format!("<p class=\"files\">Attached: {}</p>\n", files.join(", ")));
+		}
+		for block in message.blocks.iter().filter(|block| message.role != "user" || block.type_ == "image") {
+			body.push_str(&render_block(block, message));
+		}
+		// Prompts are shown as written, like in the app
+		if message.role == "user" || message.blocks.is_empty() {
+			body.push_str(&format!("<div class=\"content\">{}</div>\n", escape_html(&message.content)));
+		}
+		body.push_str("</section>\n");
+	}
+
+	format!(
+		"<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{}</title>\n<style>{}</style>\n</head>\n<body>\n{}</body>\n</html>\n",
+		escape_html(&chat.display_name),
+		HTML_STYLE,
+		body
+	)
+}
+
+fn render_block(block: &MessageBlock, message: &ExportedMessage) -> String {
+	let language = escape_html(block.language.as_deref().unwrap_or_default());
+	match block.type_.as_str() {
+		"code" => format!(
+			"<div class=\"code\">{}<div class=\"highlighted\">{}</div></div>\n",
+			match language.is_empty() {
+				true => String::new(),
+				false => format!("<div class=\"language\">{}</div>", language),
+			},
+			block.rendered_content
+		),
+		// The block references the attachment by id, the stored file is not part of the page
+		"image" => message
+			.attachments
+			.iter()
+			.find(|attachment| attachment.id == block.raw_content)
+			.and_then(|attachment| {
+				let data = attachment.data.as_ref()?;
+				Some(format!(
+					"<img src=\"data:{};base64,{}\" alt=\"{}\">\n",
+					attachment.mime_type,
+					data,
+					escape_html(&attachment.file_name)
+				))
+			})
+			.unwrap_or_default(),
+		"tool_call" | "tool_result" | "source" => {
+			let summary = match block.type_.as_str() {
+				"tool_call" => format!("Called {}", language),
+				"tool_result" => format!("Result of {}", language),
+				_ => language,
+			};
+			format!(
+				"<details><summary>{}</summary><div class=\"highlighted\">{}</div></details>\n",
+				summary, block.rendered_content
+			)
+		}
+		_ => format!("<div class=\"text\">{}</div>\n", block.rendered_content),
+	}
+}
+
+fn escape_html(text: &str) -> String {
+	text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+/// File name for a chat without characters that are not allowed in file names
+fn file_stem(display_name: &str) -> String {
+	let stem: String = display_name
+		.chars()
+		.map(|c| if c.is_control() || "/\\:*?\"<>|".contains(c) { '-' } else { c })
+		.take(MAX_FILE_NAME_LENGTH)
+		.collect();
+	let stem = stem.trim().trim_matches('.').to_string();
+	match stem.is_empty() {
+		true => "chat".to_string(),
+		false => stem,
+	}
+}
+
+fn extension(format: ExportFormat) -> &'static str {
+	match format {
+		ExportFormat::Markdown => "md",
+		ExportFormat::Json => "json",
+		ExportFormat::Html => "html",
+	}
+}
+
+/// Ask the user where to save an export, `None` if the dialog was cancelled
+async fn choose_file(window: &WebviewWindow, file_name: &str, format: ExportFormat) -> Option<PathBuf> {
+	let filter_name = match format {
+		ExportFormat::Markdown => "Markdown",
+		ExportFormat::Json => "JSON",
+		ExportFormat::Html => "HTML",
+	};
+	let (path_tx, path_rx) = oneshot::channel();
+	window
+		.dialog()
+		.file()
+		.add_filter(filter_name, &[extension(format)])
+		.set_file_name(file_name)
+		.save_file(move |path| {
+			let _ = path_tx.send(path);
+		});
+	path_rx.await.ok().flatten().and_then(|path| path.into_path().ok())
+}
+
+/// Ask the user for the folder to export chats into, `None` if the dialog was cancelled
+async fn choose_folder(window: &WebviewWindow) -> Option<PathBuf> {
+	let (path_tx, path_rx) = oneshot::channel();
+	window.dialog().file().pick_folder(move |path| {
+		let _ = path_tx.send(path);
+	});
+	path_rx.await.ok().flatten().and_then(|path| path.into_path().ok())
+}
+
+fn write_export(path: &Path, contents: &str) -> Result<(), String> {
+	fs::write(path, contents).map_err(|e| {
+		log::error!("Error writing export to {}: {}", path.display(), e);
+		format!("Could not write {}: {}", path.display(), e)
+	})
+}
@@ -0,0 +1,763 @@
+//! Importing chats from ChatGPT's `conversations.json`, from the `conversations.json` of Claude's data export and from Byok's own JSON export.
+//! Imported chats keep the ids of the export, so chats that are already in the database are skipped when a file is imported again.
+//! Attachments get new ids, their files are stored under them.
+
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use serde_json::{Map, Value};
+use sqlx::SqlitePool;
+use tauri::{command, Emitter, WebviewWindow};
+use tauri_plugin_dialog::DialogExt;
+use tokio::fs;
+use tokio::sync::oneshot;
+
+use crate::data::DataState;
+use crate::db::ATTACHMENTS_DIR;
+use crate::export::{ARCHIVE_FORMAT, ARCHIVE_VERSION};
+use crate::providers::fallback_chat_title;
+use crate::types::{Attachment, ChatArchive, ExportedAttachment, ExportedChat, ExportedMessage, ImportProgress, MessageBlock};
+use crate::utils::{render_attachments, render_message};
+
+#[derive(Deserialize)]
+struct ChatGptConversation {
+	#[serde(default)]
+	id: Option<String>,
+	#[serde(default)]
+	conversation_id: Option<String>,
+	#[serde(default)]
+	title: Option<String>,
+	#[serde(default)]
+	create_time: Option<f64>,
+	#[serde(default)]
+	update_time: Option<f64>,
+	#[serde(default)]
+	default_model_slug: Option<String>,
+	/// Nodes of the message tree by id, edited prompts and regenerated answers are siblings
+	mapping: HashMap<String, ChatGptNode>,
+	/// Last node of the branch shown in ChatGPT
+	#[serde(default)]
+	current_node: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct ChatGptNode {
+	#[serde(default)]
+	message: Option<ChatGptMessage>,
+	#[serde(default)]
+	parent: Option<String>,
+	#[serde(default)]
+	children: Vec<String>,
+}
+
+#[derive(Deserialize)]
+struct ChatGptMessage {
+	id: String,
+	author: ChatGptAuthor,
+	#[serde(default)]
+	create_time: Option<f64>,
+	content: ChatGptContent,
+	#[serde(default)]
+	metadata: Map<String, Value>,
+}
+
+#[derive(Deserialize)]
+struct ChatGptAuthor {
+	role: String,
+}
+
+#[derive(Deserialize)]
+struct ChatGptContent {
+	content_type: String,
+	/// Text parts, images and other attachments are objects
+	#[serde(default)]
+	parts: Vec<Value>,
+}
+
+#[derive(Deserialize)]
+struct ClaudeConversation {
+	uuid: String,
+	#[serde(default)]
+	name: Option<String>,
+	#[serde(default)]
+	created_at: Option<String>,
+	#[serde(default)]
+	updated_at: Option<String>,
+	#[serde(default)]
+	model: Option<String>,
+	chat_messages: Vec<ClaudeMessage>,
+}
+
+#[derive(Deserialize)]
+struct ClaudeMessage {
+	uuid: String,
+	/// "human" or "assistant"
+	sender: String,
+	#[serde(default)]
+	text: Option<String>,
+	/// Newer exports split the text into typed parts, including tool use
+	#[serde(default)]
+	content: Vec<ClaudeContent>,
+	#[serde(default)]
+	created_at: Option<String>,
+	#[serde(default)]
+	parent_message_uuid: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct ClaudeContent {
+	#[serde(rename = "type")]
+	type_: String,
+	#[serde(default)]
+	text: Option<String>,
+}
+
+/// Import the chats of an export file, the user picks the file if no path is given.
+/// An `importProgress` event is emitted after every chat. Returns the final counts, `None` if the user cancelled.
+#[command]
+#[specta::specta]
+pub async fn import_chats(file_path: Option<String>, data: DataState<'_>) -> Result<Option<ImportProgress>, String> {
+	let (pool, window, app_dir, code_theme) = {
+		let data = data.0.lock().await;
+		(
+			data.db_pool.clone(),
+			data.window.clone(),
+			data.paths.app_dir.clone(),
+			data.settings.code_theme.clone(),
+		)
+	};
+	let path = match file_path {
+		Some(file_path) => PathBuf::from(file_path),
+		None => match choose_file(&window).await {
+			Some(path) => path,
+			None => return Ok(None),
+		},
+	};
+
+	// Exports can be hundreds of megabytes, they are read and parsed off the async runtime
+	let (source, chats) = tauri::async_runtime::spawn_blocking(move || {
+		let contents = std::fs::read_to_string(&path).map_err(|e| format!("Could not read {}: {}", path.display(), e))?;
+		let export: Value = serde_json::from_str(&contents).map_err(|e| format!("{} is not a JSON file: {}", path.display(), e))?;
+		parse_export(export)
+	})
+	.await
+	.map_err(|e| e.to_string())??;
+
+	let mut progress = ImportProgress {
+		source: source.to_string(),
+		total: chats.len() as u32,
+		..ImportProgress::default()
+	};
+	for chat in chats {
+		match chat {
+			Some(chat) if !chat_exists(&chat.id, &pool).await? => match insert_chat(&chat, &code_theme, &pool, &app_dir).await {
+				Ok(()) => progress.imported += 1,
+				Err(e) => {
+					log::error!("Error importing chat {} from {}: {}", chat.id, source, e);
+					progress.failed += 1;
+				}
+			},
+			_ => progress.skipped += 1,
+		}
+		let _ = window.emit("importProgress", &progress);
+	}
+
+	log::info!(
+		"Imported {} of {} chats from {} ({} skipped, {} failed)",
+		progress.imported,
+		progress.total,
+		source,
+		progress.skipped,
+		progress.failed
+	);
+	if progress.imported > 0 {
+		let _ = window.emit("newChat", ());
+	}
+	Ok(Some(progress))
+}
+
+/// Recognize the format of an export and convert its conversations. Conversations without messages become `None`.
+fn parse_export(export: Value) -> Result<(&'static str, Vec<Option<ExportedChat>>), String> {
+	if export.get("format").and_then(Value::as_str) == Some(ARCHIVE_FORMAT) {
+		let archive: ChatArchive = serde_json::from_value(export).map_err(|e| format!("Invalid Byok export: {}", e))?;
+		if archive.version > ARCHIVE_VERSION {
+			return Err("The export was made by a newer version of Byok, please update the app".to_string());
+		}
+		return Ok(("Byok", archive.chats.into_iter().map(Some).collect()));
+	}
+
+	let Value::Array(conversations) = export else {
+		return Err("Unknown export format, select a Byok export or the conversations.json of a ChatGPT or Claude export".to_string());
+	};
+	let is_chatgpt = conversations.first().is_some_and(|conversation| conversation.get("mapping").is_some());
+	let source = if is_chatgpt { "ChatGPT" } else { "Claude" };
+	let chats = conversations
+		.into_iter()
+		.map(|conversation| match is_chatgpt {
+			true => serde_json::from_value(conversation).map(chatgpt_chat),
+			false => serde_json::from_value(conversation).map(claude_chat),
+		})
+		.collect::<Result<Vec<_>, _>>()
+		.map_err(|e| format!("Invalid {} export: {}", source, e))?;
+	Ok((source, chats))
+}
+
+/// Convert a ChatGPT conversation, keeping its branches.
+/// Messages that ChatGPT does not show, like system messages and tool calls, are left out and their replies attached to the closest message shown.
+fn chatgpt_chat(conversation: ChatGptConversation) -> Option<ExportedChat> {
+	let id = conversation.id.or(conversation.conversation_id)?;
+	let mapping = &conversation.mapping;
+	let default_model = conversation.default_model_slug.as_deref().unwrap_or("chatgpt");
+
+	// Walk the tree depth first so every message follows its parent and older versions come before newer ones
+	let mut roots: Vec<&String> = mapping
+		.iter()
+		.filter(|(_, node)| node.parent.as_ref().is_none_or(|parent| !mapping.contains_key(parent)))
+		.map(|(node_id, _)| node_id)
+		.collect();
+	roots.sort();
+	let mut stack: Vec<(&String, Option<String>)> = roots.into_iter().rev().map(|node_id| (node_id, None)).collect();
+	let mut shown_message_ids: HashMap<&String, Option<String>> = HashMap::new();
+	let mut messages = Vec::new();
+	while let Some((node_id, parent_id)) = stack.pop() {
+		if shown_message_ids.contains_key(node_id) {
+			continue;
+		}
+		let node = &mapping[node_id];
+		let message = node
+			.message
+			.as_ref()
+			.and_then(|message| chatgpt_message(message, parent_id.clone(), default_model));
+		let shown_id = message.as_ref().map(|message| message.id.clone()).or(parent_id);
+		shown_message_ids.insert(node_id, shown_id.clone());
+		messages.extend(message);
+		for child in node.children.iter().rev().filter(|child| mapping.contains_key(*child)) {
+			stack.push((child, shown_id.clone()));
+		}
+	}
+	if messages.is_empty() {
+		return None;
+	}
+
+	let active_message_id = conversation
+		.current_node
+		.as_ref()
+		.and_then(|node_id| shown_message_ids.get(node_id).cloned().flatten())
+		.or_else(|| messages.last().map(|message: &ExportedMessage| message.id.clone()));
+	let display_name = match conversation.title.as_deref().map(str::trim) {
+		Some(title) if !title.is_empty() => title.to_string(),
+		_ => fallback_chat_title(&messages[0].content),
+	};
+	let creation_date = conversation.create_time.and_then(unix_timestamp);
+	Some(ExportedChat {
+		id,
+		display_name,
+		model: Some(default_model.to_string()),
+		archived: false,
+		system_prompt: None,
+		last_updated: conversation.update_time.and_then(unix_timestamp).or(creation_date.clone()),
+		creation_date,
+		active_message_id,
+		parameters: None,
+		messages,
+	})
+}
+
+/// Convert a prompt or answer of a ChatGPT conversation, `None` for messages ChatGPT does not show
+fn chatgpt_message(message: &ChatGptMessage, parent_id: Option<String>, default_model: &str) -> Option<ExportedMessage> {
+	if !matches!(message.author.role.as_str(), "user" | "assistant") || !matches!(message.content.content_type.as_str(), "text" | "multimodal_text") {
+		return None;
+	}
+	if message.metadata.get("is_visually_hidden_from_conversation").and_then(Value::as_bool) == Some(true) {
+		return None;
+	}
+	let parts: Vec<&str> = message
+		.content
+		.parts
+		.iter()
+		.filter_map(Value::as_str)
+		.filter(|part| !part.trim().is_empty())
+		.collect();
+	if parts.is_empty() {
+		return None;
+	}
+	let model_name = message.metadata.get("model_slug").and_then(Value::as_str).unwrap_or(default_model);
+	Some(imported_message(
+		message.id.clone(),
+		parent_id,
+		&message.author.role,
+		parts.join("\n\n"),
+		"openai",
+		model_name,
+		message.create_time.and_then(unix_timestamp),
+	))
+}
+
+/// Convert a Claude conversation. Replies follow the message they answer, or the previous message in older exports without parent ids.
+fn claude_chat(conversation: ClaudeConversation) -> Option<ExportedChat> {
+	let model_name = conversation.model.as_deref().unwrap_or("claude");
+	let mut messages: Vec<ExportedMessage> = Vec::new();
+	let mut message_ids = HashSet::new();
+	for message in &conversation.chat_messages {
+		let role = match message.sender.as_str() {
+			"human" => "user",
+			"assistant" => "assistant",
+			_ => continue,
+		};
+		let parts: Vec<&str> = message
+			.content
+			.iter()
+			.filter(|content| content.type_ == "text")
+			.filter_map(|content| content.text.as_deref())
+			.collect();
+		let text = match parts.is_empty() {
+			true => message.text.clone().unwrap_or_default(),
+			false => parts.join("\n\n"),
+		};
+		if text.trim().is_empty() {
+			continue;
+		}
+		let parent_id = match &message.parent_message_uuid {
+			Some(parent_id) if message_ids.contains(parent_id) => Some(parent_id.clone()),
+			_ => messages.last().map(|previous| previous.id.clone()),
+		};
+		message_ids.insert(message.uuid.clone());
+		messages.push(imported_message(
+			message.uuid.clone(),
+			parent_id,
+			role,
+			text,
+			"anthropic",
+			model_name,
+			message.created_at.as_deref().and_then(iso_timestamp),
+		));
+	}
+	if messages.is_empty() {
+		return None;
+	}
+
+	let display_name = match conversation.name.as_deref().map(str::trim) {
+		Some(name) if !name.is_empty() => name.to_string(),
+		_ => fallback_chat_title(&messages[0].content),
+	};
+	let creation_date = conversation.created_at.as_deref().and_then(iso_timestamp);
+	Some(ExportedChat {
+		id: conversation.uuid,
+		display_name,
+		model: Some(model_name.to_string()),
+		archived: false,
+		system_prompt: None,
+		last_updated: conversation.updated_at.as_deref().and_then(iso_timestamp).or(creation_date.clone()),
+		creation_date,
+		active_message_id: messages.last().map(|message| message.id.clone()),
+		parameters: None,
+		messages,
+	})
+}
+
+fn imported_message(
+	id: String,
+	parent_id: Option<String>,
+	role: &str,
+	content: String,
+	provider_name: &str,
+	model_name: &str,
+	created_at: Option<String>,
+) -> ExportedMessage {
+	ExportedMessage {
+		id,
+		parent_id,
+		role: role.to_string(),
+		content,
+		status: "ok".to_string(),
+		provider_name: Some(provider_name.to_string()),
+		model_name: model_name.to_string(),
+		latency_ms: None,
+		prompt_tokens: None,
+		completion_tokens: None,
+		created_at,
+		blocks: Vec::new(),
+		attachments: Vec::new(),
+	}
+}
+
+/// Timestamp in the format SQLite's CURRENT_TIMESTAMP uses, from seconds since the epoch
+fn unix_timestamp(seconds: f64) -> Option<String> {
+	let timestamp = DateTime::from_timestamp(seconds.trunc() as i64, (seconds.fract() * 1e9) as u32)?;
+	Some(sqlite_timestamp(timestamp))
+}
+
+/// Timestamp in the format SQLite's CURRENT_TIMESTAMP uses, from an RFC 3339 date
+fn iso_timestamp(date: &str) -> Option<String> {
+	let timestamp = DateTime::parse_from_rfc3339(date).ok()?;
+	Some(sqlite_timestamp(timestamp.with_timezone(&Utc)))
+}
+
+fn sqlite_timestamp(timestamp: DateTime<Utc>) -> String {
+	timestamp.format("%Y-%m-%d %H:%M:%S").to_string()
+}
+
+async fn chat_exists(chat_id: &str, pool: &SqlitePool) -> Result<bool, String> {
+	sqlx::query_as::<_, (i64,)>("SELECT COUNT(*) FROM chats WHERE id = $1")
+		.bind(chat_id)
+		.fetch_one(pool)
+		.await
+		.map(|(count,)| count > 0)
+		.map_err(|e| e.to_string())
+}
+
+/// Insert a chat with its messages, blocks, attachments and parameters.
+/// Messages without blocks are rendered like new messages, the blocks of Byok exports are kept.
+async fn insert_chat(chat: &ExportedChat, code_theme: &str, pool: &SqlitePool, app_dir: &Path) -> Result<(), String> {
+	let mut blocks: Vec<Vec<MessageBlock>> = Vec::with_capacity(chat.messages.len());
+	for message in &chat.messages {
+		blocks.push(match message.blocks.is_empty() {
+			true => render_message(&message.content, code_theme).await.0,
+			false => message.blocks.clone(),
+		});
+	}
+
+	// The files are written first and removed again if the chat can't be inserted
+	let mut attachments: Vec<Vec<Attachment>> = Vec::with_capacity(chat.messages.len());
+	let mut stored_files = Vec::new();
+	for (message, blocks) in chat.messages.iter().zip(blocks.iter_mut()) {
+		let mut message_attachments = Vec::with_capacity(message.attachments.len());
+		for exported in &message.attachments {
+			let attachment = match store_attachment(exported, app_dir).await {
+				Ok(Some(attachment)) => attachment,
+				Ok(None) => continue,
+				Err(e) => {
+					remove_files(&stored_files).await;
+					return Err(e);
+				}
+			};
+			stored_files.push(app_dir.join(&attachment.path));
+			// Image blocks of Byok exports reference the attachment by the id it had in the export
+			if let Some(image) = render_attachments(std::slice::from_ref(&attachment)).pop() {
+				for block in blocks.iter_mut().filter(|block| block.type_ == "image" && block.raw_content == exported.id) {
+					*block = image.clone();
+				}
+			}
+			message_attachments.push(attachment);
+		}
+		attachments.push(message_attachments);
+	}
+
+	let result = insert_chat_rows(chat, blocks, attachments, pool).await;
+	if result.is_err() {
+		remove_files(&stored_files).await;
+	}
+	result
+}
+
+/// Write the file of an exported attachment under a new id, `None` if the export contains no contents for it.
+/// Ids and file names of an export are never used in paths, only the extension is kept if it is alphanumeric.
+async fn store_attachment(exported: &ExportedAttachment, app_dir: &Path) -> Result<Option<Attachment>, String> {
+	let bytes = match (&exported.data, &exported.content) {
+		(Some(data), _) => BASE64.decode(data).map_err(|e| format!("Invalid image {}: {}", exported.file_name, e))?,
+		(None, Some(content)) => content.as_bytes().to_vec(),
+		(None, None) => return Ok(None),
+	};
+	let id = uuid::Uuid::new_v4().to_string();
+	let extension = Path::new(&exported.file_name)
+		.extension()
+		.and_then(|extension| extension.to_str())
+		.filter(|extension| extension.chars().all(|c| c.is_ascii_alphanumeric()))
+		.map(str::to_lowercase);
+	let path = match extension {
+		Some(extension) => format!("{}/{}.{}", ATTACHMENTS_DIR, id, extension),
+		None => format!("{}/{}", ATTACHMENTS_DIR, id),
+	};
+	fs::create_dir_all(app_dir.join(ATTACHMENTS_DIR))
+		.await
+		.map_err(|e| format!("Error creating attachments folder: {}", e))?;
+	fs::write(app_dir.join(&path), bytes)
+		.await
+		.map_err(|e| format!("Error writing {}: {}", exported.file_name, e))?;
+	Ok(Some(Attachment {
+		id,
+		kind: exported.kind.clone(),
+		file_name: exported.file_name.clone(),
+		mime_type: exported.mime_type.clone(),
+		size_bytes: exported.size_bytes,
+		path,
+		content: exported.content.clone(),
+		data: None,
+	}))
+}
+
+async fn remove_files(paths: &[PathBuf]) {
+	for path in paths {
+		if let Err(e) = fs::remove_file(path).await {
+			log::warn!("Error removing {}: {}", path.display(), e);
+		}
+	}
+}
+
+/// Insert the rows of a chat in one transaction
+async fn insert_chat_rows(chat: &ExportedChat, blocks: Vec<Vec<MessageBlock>>, attachments: Vec<Vec<Attachment>>, pool: &SqlitePool) -> Result<(), String> {
+	let mut tx = pool.begin().await.map_err(|e| e.to_string())?;
+
+	// Messages left behind by a deleted chat are removed with their blocks and attachments,
+	// messages of another chat with the same id make the import fail instead of being taken over
+	for message in &chat.messages {
+		let orphaned = sqlx::query("DELETE FROM messages WHERE id = $1 AND chat_id NOT IN (SELECT id FROM chats)")
+			.bind(&message.id)
+			.execute(&mut *tx)
+			.await
+			.map_err(|e| format!("Error removing orphaned message: {}", e))?;
+		if orphaned.rows_affected() == 0 {
+			continue;
+		}
+		for query in [
+			"DELETE FROM message_blocks WHERE message_id = $1",
+			"DELETE FROM attachments WHERE message_id = $1",
+		] {
+			sqlx::query(query)
+				.bind(&message.id)
+				.execute(&mut *tx)
+				.await
+				.map_err(|e| format!("Error removing orphaned message: {}", e))?;
+		}
+	}
+
+	let insert_chat = "INSERT INTO chats (id, model, display_name, archived, system_prompt, creation_date, last_updated, active_message_id) \
+		VALUES ($1, $2, $3, $4, $5, COALESCE($6, CURRENT_TIMESTAMP), COALESCE($7, $6, CURRENT_TIMESTAMP), $8)";
+	sqlx::query(insert_chat)
+		.bind(&chat.id)
+		.bind(&chat.model)
+		.bind(&chat.display_name)
+		.bind(if chat.archived { "true" } else { "false" })
+		.bind(&chat.system_prompt)
+		.bind(&chat.creation_date)
+		.bind(&chat.last_updated)
+		.bind(&chat.active_message_id)
+		.execute(&mut *tx)
+		.await
+		.map_err(|e| format!("Error inserting chat: {}", e))?;
+
+	if let Some(parameters) = &chat.parameters {
+		let stop_sequences = match &parameters.stop_sequences {
+			Some(stop_sequences) => Some(serde_json::to_string(stop_sequences).map_err(|e| e.to_string())?),
+			None => None,
+		};
+		let insert_parameters = "INSERT OR REPLACE INTO chat_parameters \
+			(chat_id, temperature, top_p, max_tokens, stop_sequences, seed, frequency_penalty, presence_penalty) VALUES ($1, $2, $3, $4, $5, $6, $7, $8)";
+		sqlx::query(insert_parameters)
+			.bind(&chat.id)
+			.bind(parameters.temperature)
+			.bind(parameters.top_p)
+			.bind(parameters.max_tokens)
+			.bind(stop_sequences)
+			.bind(parameters.seed)
+			.bind(parameters.frequency_penalty)
+			.bind(parameters.presence_penalty)
+			.execute(&mut *tx)
+			.await
+			.map_err(|e| format!("Error inserting chat parameters: {}", e))?;
+	}
+
+	let insert_message = "INSERT INTO messages \
+		(id, role, content, status, chat_id, parent_id, provider_name, model_name, latency_ms, prompt_tokens, completion_tokens, created_at) \
+		VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, COALESCE($12, CURRENT_TIMESTAMP))";
+	let insert_block = "INSERT INTO message_blocks (message_id, type_, language, raw_content, rendered_content, copied) VALUES ($1, $2, $3, $4, $5, $6)";
+	let insert_attachment = "INSERT INTO attachments (id, message_id, kind, file_name, mime_type, size_bytes, path, content) \
+		VALUES ($1, $2, $3, $4, $5, $6, $7, $8)";
+	for ((message, blocks), attachments) in chat.messages.iter().zip(blocks).zip(attachments) {
+		sqlx::query(insert_message)
+			.bind(&message.id)
+			.bind(&message.role)
+			.bind(&message.content)
+			.bind(&message.status)
+			.bind(&chat.id)
+			.bind(&message.parent_id)
+			.bind(&message.provider_name)
+			.bind(&message.model_name)
+			.bind(message.latency_ms)
+			.bind(message.prompt_tokens)
+			.bind(message.completion_tokens)
+			.bind(&message.created_at)
+			.execute(&mut *tx)
+			.await
+			.map_err(|e| format!("Error inserting message: {}", e))?;
+		for block in blocks {
+			sqlx::query(insert_block)
+				.bind(&message.id)
+				.bind(&block.type_)
+				.bind(&block.language)
+				.bind(&block.raw_content)
+				.bind(&block.rendered_content)
+				.bind(block.copied.unwrap_or(false))
+				.execute(&mut *tx)
+				.await
+				.map_err(|e| format!("Error inserting message block: {}", e))?;
+		}
+		for attachment in attachments {
+			sqlx::query(insert_attachment)
+				.bind(&attachment.id)
+				.bind(&message.id)
+				.bind(&attachment.kind)
+				.bind(&attachment.file_name)
+				.bind(&attachment.mime_type)
+				.bind(attachment.size_bytes)
+				.bind(&attachment.path)
+				.bind(&attachment.content)
+				.execute(&mut *tx)
+				.await
+				.map_err(|e| format!("Error inserting attachment: {}", e))?;
+		}
+	}
+
+	tx.commit().await.map_err(|e| e.to_string())
+}
+
+/// Ask the user for the export file to import, `None` if the dialog was cancelled
+async fn choose_file(window: &WebviewWindow) -> Option<PathBuf> {
+	let (path_tx, path_rx) = oneshot::channel();
+	window.dialog().file().add_filter("JSON", &["json"]).pick_file(move |path| {
+		let _ = path_tx.send(path);
+	});
+	path_rx.await.ok().flatten().and_then(|path| path.into_path().ok())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use serde_json::json;
+
+	fn chatgpt_node(id: &str, role: &str, text: &str, parent: Option<&str>, children: &[&str]) -> Value {
+		json!({
+			"id": id,
+			"message": {
+				"id": id,
+				"author": { "role": role },
+				"create_time": 1700000000.5,
+				"content": { "content_type": "text", "parts": [text] },
+				"metadata": {}
+			},
+			"parent": parent,
+			"children": children
+		})
+	}
+
+	fn chatgpt_conversation() -> Value {
+		json!({
+			"id": "chatgpt-chat",
+			"title": "Greetings",
+			"create_time": 1700000000.0,
+			"default_model_slug": "gpt-4o",
+			"current_node": "a1",
+			"mapping": {
+				"root": { "id": "root", "message": null, "parent": null, "children": ["system"] },
+				"system": chatgpt_node("system", "system", "You are helpful", Some("root"), &["u1"]),
+				"u1": chatgpt_node("u1", "user", "Hi", Some("system"), &["a1", "a2"]),
+				"a1": chatgpt_node("a1", "assistant", "Hello", Some("u1"), &[]),
+				"a2": chatgpt_node("a2", "assistant", "Hey", Some("u1"), &[])
+			}
+		})
+	}
+
+	fn claude_message(uuid: &str, sender: &str, text: &str, parent: Option<&str>) -> Value {
+		json!({
+			"uuid": uuid,
+			"sender": sender,
+			"text": text,
+			"created_at": "2024-05-01T12:00:00Z",
+			"parent_message_uuid": parent
+		})
+	}
+
+	fn parents(chat: &ExportedChat) -> Vec<(&str, Option<&str>)> {
+		chat.messages
+			.iter()
+			.map(|message| (message.id.as_str(), message.parent_id.as_deref()))
+			.collect()
+	}
+
+	#[test]
+	fn recognizes_export_formats() {
+		let (source, chats) = parse_export(json!([chatgpt_conversation()])).unwrap();
+		assert_eq!(source, "ChatGPT");
+		assert_eq!(chats.len(), 1);
+
+		let claude = json!([{ "uuid": "claude-chat", "chat_messages": [] }]);
+		let (source, chats) = parse_export(claude).unwrap();
+		assert_eq!(source, "Claude");
+		assert!(chats[0].is_none());
+
+		let archive = json!({ "format": ARCHIVE_FORMAT, "version": ARCHIVE_VERSION, "exported_at": "2024-05-01 12:00:00", "chats": [] });
+		assert_eq!(parse_export(archive).unwrap().0, "Byok");
+		let newer = json!({ "format": ARCHIVE_FORMAT, "version": ARCHIVE_VERSION + 1, "exported_at": "2024-05-01 12:00:00", "chats": [] });
+		assert!(parse_export(newer).is_err());
+
+		assert!(parse_export(json!({ "conversations": [] })).is_err());
+		assert!(parse_export(json!([{ "mapping": "not a tree" }])).is_err());
+	}
+
+	#[test]
+	fn keeps_chatgpt_branches() {
+		let conversation: ChatGptConversation = serde_json::from_value(chatgpt_conversation()).unwrap();
+		let chat = chatgpt_chat(conversation).unwrap();
+		assert_eq!(chat.id, "chatgpt-chat");
+		assert_eq!(chat.display_name, "Greetings");
+		assert_eq!(parents(&chat), vec![("u1", None), ("a1", Some("u1")), ("a2", Some("u1"))]);
+		assert_eq!(chat.active_message_id.as_deref(), Some("a1"));
+		assert_eq!(chat.messages[0].model_name, "gpt-4o");
+		assert_eq!(chat.messages[0].created_at.as_deref(), Some("2023-11-14 22:13:20"));
+	}
+
+	#[test]
+	fn skips_chatgpt_conversations_without_shown_messages() {
+		let conversation: ChatGptConversation = serde_json::from_value(json!({
+			"id": "empty",
+			"mapping": { "system": chatgpt_node("system", "system", "You are helpful", None, &[]) }
+		}))
+		.unwrap();
+		assert!(chatgpt_chat(conversation).is_none());
+	}
+
+	#[test]
+	fn follows_claude_parent_ids() {
+		let conversation: ClaudeConversation = serde_json::from_value(json!({
+			"uuid": "claude-chat",
+			"name": "",
+			"chat_messages": [
+				claude_message("h1", "human", "What is Rust?", None),
+				claude_message("a1", "assistant", "A language", Some("h1")),
+				claude_message("h2", "human", "What is Rust really?", Some("h1")),
+				claude_message("a2", "assistant", "A systems language", Some("h2")),
+				claude_message("s1", "system", "Ignored", Some("a2"))
+			]
+		}))
+		.unwrap();
+		let chat = claude_chat(conversation).unwrap();
+		assert_eq!(parents(&chat), vec![("h1", None), ("a1", Some("h1")), ("h2", Some("h1")), ("a2", Some("h2"))]);
+		assert_eq!(chat.active_message_id.as_deref(), Some("a2"));
+		assert_eq!(chat.display_name, fallback_chat_title("What is Rust?"));
+		assert_eq!(chat.messages[0].role, "user");
+		assert_eq!(chat.messages[0].created_at.as_deref(), Some("2024-05-01 12:00:00"));
+	}
+
+	#[test]
+	fn chains_claude_messages_without_parent_ids() {
+		let conversation: ClaudeConversation = serde_json::from_value(json!({
+			"uuid": "claude-chat",
+			"name": "Old export",
+			"chat_messages": [
+				claude_message("h1", "human", "Hi", None),
+				{ "uuid": "a1", "sender": "assistant", "content": [{ "type": "tool_use" }, { "type": "text", "text": "Hello" }] },
+				claude_message("h2", "human", "  ", None),
+				claude_message("h3", "human", "Bye", Some("unknown"))
+			]
+		}))
+		.unwrap();
+		let chat = claude_chat(conversation).unwrap();
+		assert_eq!(parents(&chat), vec![("h1", None), ("a1", Some("h1")), ("h3", Some("a1"))]);
+		assert_eq!(chat.messages[1].content, "Hello");
+		assert_eq!(chat.display_name, "Old export");
+	}
+}
@@ -0,0 +1,141 @@
+//! Background indexing of the messages for semantic search.
+//! Messages without a vector of the configured embedding model are embedded in batches, oldest first.
+//! Progress lives in the database, so indexing resumes where it stopped and existing chats are backfilled.
+
+use std::collections::HashMap;
+use std::time::Duration;
+
+use sqlx::SqlitePool;
+use tauri::{AppHandle, Manager};
+
+use crate::data::ArcData;
+use crate::db::{find_provider, find_unembedded_messages, save_embedding};
+use crate::llm_providers::embed;
+use crate::settings::Settings;
+
+/// Number of messages embedded with one request
+const BATCH_SIZE: u32 = 32;
+
+/// Longer messages are cut off before they are embedded, embedding models only read a few thousand tokens
+const MAX_EMBEDDING_CHARS: usize = 8000;
+
+/// Time to wait for new messages once all are indexed, or after a failed request
+const INDEX_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Number of times a message may fail to embed before it is skipped until the app restarts
+const MAX_EMBEDDING_ATTEMPTS: u32 = 3;
+
+/// Failed attempts to embed a message, by provider, model and message id
+type FailedAttempts = HashMap<(String, String, String), u32>;
+
+/// Provider and model configured for embeddings, `None` if indexing is off
+pub fn embedding_model(settings: &Settings) -> Option<(&str, &str)> {
+	Some((settings.embedding_provider.as_deref()?, settings.embedding_model.as_deref()?))
+}
+
+/// Embed texts with an embedding model of a configured provider
+pub async fn embed_texts(provider_name: &str, model_name: &str, texts: &[String], pool: &SqlitePool) -> Result<Vec<Vec<f32>>, String> {
+	let provider = find_provider(provider_name, pool)
+		.await?
+		.ok_or_else(|| format!("Provider {} not found", provider_name))?;
+	let api_scheme = provider.to_provider().map_err(|e| e.to_string())?.api_scheme();
+	embed(
+		&provider.provider_name,
+		&api_scheme,
+		&provider.api_key,
+		provider.base_url.as_deref(),
+		&provider.request_options(),
+		model_name,
+		texts,
+	)
+	.await
+	.map_err(|e| e.to_string())
+}
+
+/// Index messages for as long as the app runs
+pub async fn run(app: AppHandle) {
+	let mut failed_attempts = FailedAttempts::new();
+	loop {
+		let indexed = match index_next_batch(&app, &mut failed_attempts).await {
+			Ok(indexed) => indexed,
+			Err(e) => {
+				log::warn!("Indexing messages for semantic search failed: {}", e);
+				0
+			}
+		};
+		if indexed == 0 {
+			tokio::time::sleep(INDEX_INTERVAL).await;
+		}
+	}
+}
+
+/// Embed the next batch of messages, returns the number of messages indexed.
+/// The settings are read for every batch so a change of the embedding model takes effect right away.
+/// If the batch is rejected its messages are embedded one by one, so a message the provider can't embed doesn't hold up the others.
+async fn index_next_batch(app: &AppHandle, failed_attempts: &mut FailedAttempts) -> Result<usize, String> {
+	let (pool, settings) = {
+		let data = app.state::<ArcData>();
+		let data = data.0.lock().await;
+		(data.db_pool.clone(), data.settings.clone())
+	};
+	let Some((provider_name, model_name)) = embedding_model(&settings) else {
+		return Ok(0);
+	};
+
+	let skip: Vec<&str> = failed_attempts
+		.iter()
+		.filter(|((provider, model, _), attempts)| provider == provider_name && model == model_name && **attempts >= MAX_EMBEDDING_ATTEMPTS)
+		.map(|((_, _, message_id), _)| message_id.as_str())
+		.collect();
+	let messages = find_unembedded_messages(provider_name, model_name, &skip, BATCH_SIZE, &pool).await?;
+	if messages.is_empty() {
+		return Ok(0);
+	}
+	let texts: Vec<String> = messages
+		.iter()
+		.map(|(_, content)| content.chars().take(MAX_EMBEDDING_CHARS).collect())
+		.collect();
+	let vectors: Vec<Option<Vec<f32>>> = match embed_texts(provider_name, model_name, &texts, &pool).await {
+		Ok(vectors) => vectors.into_iter().map(Some).collect(),
+		Err(e) if texts.len() > 1 => {
+			log::warn!("Embedding a batch of messages failed, embedding them one by one: {}", e);
+			let mut vectors = Vec::with_capacity(texts.len());
+			for text in &texts {
+				let vector = embed_texts(provider_name, model_name, std::slice::from_ref(text), &pool).await;
+				vectors.push(vector.ok().and_then(|mut vectors| vectors.pop()));
+			}
+			// Most likely the provider is down or misconfigured, only the oldest message counts as failed
+			// so a batch of messages the provider can't embed still drains without skipping everything during an outage
+			if vectors.iter().all(Option::is_none) {
+				*failed_attempts
+					.entry((provider_name.to_string(), model_name.to_string(), messages[0].0.clone()))
+					.or_default() += 1;
+				return Err(e);
+			}
+			vectors
+		}
+		Err(e) => {
+			log::warn!("Embedding a message failed: {}", e);
+			vec![None]
+		}
+	};
+
+	let mut indexed = 0;
+	for ((message_id, _), vector) in messages.iter().zip(vectors) {
+		let key = (provider_name.to_string(), model_name.to_string(), message_id.clone());
+		match vector {
+			Some(vector) => {
+				save_embedding(message_id, provider_name, model_name, &vector, &pool).await?;
+				failed_attempts.remove(&key);
+				indexed += 1;
+			}
+			None => {
+				let attempts = failed_attempts.entry(key).or_default();
+				*attempts += 1;
+				log::warn!("Embedding message {} failed ({}/{} attempts)", message_id, attempts, MAX_EMBEDDING_ATTEMPTS);
+			}
+		}
+	}
+	log::debug!("Embedded {} messages with {}/{}", indexed, provider_name, model_name);
+	Ok(indexed)
+}
@@ -0,0 +1,163 @@
+//! Knowledge bases: local documents are split into chunks and embedded when they are added to a collection.
+//! Before a chat with collections is answered, the chunks closest to the question are added to it as numbered sources.
+
+use std::collections::HashMap;
+use std::path::Path;
+
+use sqlx::SqlitePool;
+
+use crate::db::attachments::{extract_pdf_text, is_pdf, read_text_file, text_mime_type};
+use crate::db::embeddings::{blob_to_vector, cosine_similarity};
+use crate::db::{find_chat_collections, find_collection_chunks};
+use crate::indexer::embed_texts;
+
+/// Chunks are filled with whole paragraphs up to this size in bytes, longer paragraphs are split between words
+const CHUNK_SIZE: usize = 1500;
+
+/// Number of chunks embedded with one request
+const EMBEDDING_BATCH_SIZE: usize = 32;
+
+/// Number of chunks added to a question
+const RETRIEVED_CHUNKS: usize = 5;
+
+/// A chunk of a document retrieved for a question
+#[derive(Debug, Clone)]
+pub struct Source {
+	pub file_name: String,
+	/// Index of the chunk within its document
+	pub chunk_position: u32,
+	pub content: String,
+	pub similarity: f32,
+}
+
+/// A document read for a collection
+pub struct Document {
+	pub file_name: String,
+	pub mime_type: &'static str,
+	pub size_bytes: u64,
+	pub chunks: Vec<String>,
+}
+
+/// Read a markdown, code, text or PDF file and split its text into chunks
+pub fn read_document(path: &Path) -> Result<Document, String> {
+	let file_name = path
+		.file_name()
+		.map(|name| name.to_string_lossy().to_string())
+		.ok_or_else(|| "Not a file".to_string())?;
+	let size_bytes = std::fs::metadata(path).map_err(|e| format!("Could not read {}: {}", file_name, e))?.len();
+	let (mime_type, text) = match is_pdf(path) {
+		true => ("application/pdf", extract_pdf_text(path, &file_name)?),
+		false => (text_mime_type(path), read_text_file(path, &file_name)?),
+	};
+	let chunks = chunk_text(&text);
+	if chunks.is_empty() {
+		return Err(format!("{} contains no text", file_name));
+	}
+	Ok(Document {
+		file_name,
+		mime_type,
+		size_bytes,
+		chunks,
+	})
+}
+
+/// Split a text into chunks of whole paragraphs, so related sentences are embedded together
+pub fn chunk_text(text: &str) -> Vec<String> {
+	let text = text.replace("\r\n", "\n");
+	let mut chunks = Vec::new();
+	let mut chunk = String::new();
+	for paragraph in text.split("\n\n").map(str::trim).filter(|paragraph| !paragraph.is_empty()) {
+		for piece in split_paragraph(paragraph) {
+			if !chunk.is_empty() && chunk.len() + piece.len() + 2 > CHUNK_SIZE {
+				chunks.push(std::mem::take(&mut chunk));
+			}
+			if !chunk.is_empty() {
+				chunk.push_str("\n\n");
+			}
+			chunk.push_str(&piece);
+		}
+	}
+	if !chunk.is_empty() {
+		chunks.push(chunk);
+	}
+	chunks
+}
+
+/// Split a paragraph longer than a chunk between words
+fn split_paragraph(paragraph: &str) -> Vec<String> {
+	if paragraph.len() <= CHUNK_SIZE {
+		return vec![paragraph.to_string()];
+	}
+	let mut pieces = Vec::new();
+	let mut piece = String::new();
+	for word in paragraph.split_inclusive(char::is_whitespace) {
+		if !piece.is_empty() && piece.len() + word.len() > CHUNK_SIZE {
+			pieces.push(piece.trim_end().to_string());
+			piece.clear();
+		}
+		piece.push_str(word);
+	}
+	if !piece.trim().is_empty() {
+		pieces.push(piece.trim_end().to_string());
+	}
+	pieces
+}
+
+/// Embed the chunks of a document in batches
+pub async fn embed_chunks(provider_name: &str, model_name: &str, chunks: &[String], pool: &SqlitePool) -> Result<Vec<Vec<f32>>, String> {
+	let mut vectors = Vec::with_capacity(chunks.len());
+	for batch in chunks.chunks(EMBEDDING_BATCH_SIZE) {
+		vectors.extend(embed_texts(provider_name, model_name, batch, pool).await?);
+	}
+	Ok(vectors)
+}
+
+/// Find the chunks of the chat's collections closest to a question, most similar first.
+/// The question is embedded once for every embedding model the collections use.
+pub async fn retrieve_sources(chat_id: &str, question: &str, pool: &SqlitePool) -> Result<Vec<Source>, String> {
+	let collections = find_chat_collections(chat_id, pool).await?;
+	if collections.is_empty() || question.trim().is_empty() {
+		return Ok(Vec::new());
+	}
+
+	let mut question_vectors: HashMap<(String, String), Vec<f32>> = HashMap::new();
+	let mut sources = Vec::new();
+	for collection in collections {
+		let model = (collection.embedding_provider.clone(), collection.embedding_model.clone());
+		if !question_vectors.contains_key(&model) {
+			let vector = embed_texts(&model.0, &model.1, &[question.to_string()], pool).await?.remove(0);
+			question_vectors.insert(model.clone(), vector);
+		}
+		let question_vector = &question_vectors[&model];
+		for chunk in find_collection_chunks(&collection.id, pool).await? {
+			if let Some(similarity) = cosine_similarity(question_vector, &blob_to_vector(&chunk.vector)) {
+				sources.push(Source {
+					file_name: chunk.file_name,
+					chunk_position: chunk.position,
+					content: chunk.content,
+					similarity,
+				});
+			}
+		}
+	}
+
+	sources.sort_by(|a, b| b.similarity.total_cmp(&a.similarity));
+	sources.truncate(RETRIEVED_CHUNKS);
+	Ok(sources)
+}
+
+/// Question sent to the model with the retrieved sources, numbered in the order they are shown with the answer
+pub fn sources_prompt(sources: &[Source], question: &str) -> String {
+	let excerpts: Vec<String> = sources
+		.iter()
+		.enumerate()
+		.map(|(index, source)| format!("[{}] {}\n{}", index + 1, source.file_name, source.content))
+		.collect();
+	format!(
+		"Excerpts of the user's documents that may help with the question below. \
+		Use them where they are relevant and cite them by their number in square brackets, e.g. [1].\n\n\
+		{}\n\n---\n\n{}",
+		excerpts.join("\n\n"),
+		question
+	)
+}
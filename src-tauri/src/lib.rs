@@ -7,18 +7,25 @@ use tauri::menu::{MenuBuilder, MenuItem, PredefinedMenuItem, SubmenuBuilder};
 use tauri::{command, Emitter, Manager};
 use tauri_plugin_dialog::DialogExt;
 
+mod context;
 mod data;
 mod db;
 mod errors;
+mod export;
+mod import;
+mod indexer;
+mod knowledge;
 mod llm_providers;
+mod mcp;
 mod providers;
 mod settings;
+mod tools;
 mod types;
 mod utils;
 
 pub use errors::{AppError, AppResult, ConfigError, DatabaseError, DbResult, ProviderError, ProviderResult};
 
-use crate::data::{AppPaths, AppSettings, ArcData, Data, DbPool, MainWindow, PathsState};
+use crate::data::{AppPaths, AppSettings, ArcData, Data, DbPool, Generations, MainWindow, McpClients, PathsState, ToolApprovals};
 use std::sync::Arc;
 
 fn error_popup_main_thread(msg: impl AsRef<str>) {
@@ -54,30 +61,83 @@ pub async fn run() {
 	dotenv().ok();
 	env_logger::init();
 
+	// With `--mcp` the chat history is served to other applications over stdio instead of opening the window
+	let serve_mcp = std::env::args().any(|arg| arg == "--mcp");
+
+	// Bindings are not exported in MCP mode, stdout belongs to the protocol
 	#[cfg(debug_assertions)]
-	{
-		let specta_builder = tauri_specta::Builder::<tauri::Wry>::new().commands(tauri_specta::collect_commands![
-			error_popup,
-			providers::get_message,
-			db::chats::get_chats,
-			db::messages::load_chat,
-			db::providers_db::load_providers,
-			db::providers_db::set_api_key,
-			db::providers_db::add_provider,
-			db::providers_db::update_provider,
-			db::providers_db::delete_provider,
-			db::models::get_models,
-			db::models::get_all_models,
-			db::models::add_model,
-			db::models::update_model,
-			db::models::delete_model,
-			db::providers_db::read_api_keys_from_env,
-			db::chats::rename_chat,
-			db::chats::archive_chat,
-			db::chats::delete_chat,
-			settings::get_settings,
-			settings::apply_and_save_settings
-		]);
+	if !serve_mcp {
+		let specta_builder = tauri_specta::Builder::<tauri::Wry>::new()
+			.commands(tauri_specta::collect_commands![
+				error_popup,
+				providers::get_message,
+				providers::cancel_generation,
+				providers::retry_message,
+				providers::regenerate_message,
+				providers::edit_message,
+				providers::compare_models,
+				db::chats::get_chats,
+				db::search::search_chats,
+				db::embeddings::search_similar_chats,
+				db::embeddings::get_similar_chats,
+				db::embeddings::get_embedding_status,
+				db::collections::get_collections,
+				db::collections::create_collection,
+				db::collections::rename_collection,
+				db::collections::delete_collection,
+				db::collections::get_collection_documents,
+				db::collections::add_collection_documents,
+				db::collections::remove_collection_document,
+				db::collections::get_chat_collections,
+				db::collections::set_chat_collections,
+				db::chats::fork_chat,
+				export::export_chat,
+				export::export_chats,
+				import::import_chats,
+				db::usage::get_chat_usage,
+				db::usage::get_usage_by_provider,
+				db::usage::get_usage_by_day,
+				db::messages::load_chat,
+				db::messages::get_message_versions,
+				db::messages::switch_message_version,
+				db::attachments::add_attachment,
+				db::attachments::remove_attachment,
+				db::providers_db::load_providers,
+				db::providers_db::set_api_key,
+				db::providers_db::add_provider,
+				db::providers_db::update_provider,
+				db::providers_db::delete_provider,
+				db::models::get_models,
+				db::models::get_all_models,
+				db::models::add_model,
+				db::models::update_model,
+				db::models::refresh_ollama_models,
+				db::models::list_provider_models,
+				db::models::import_models,
+				db::models::delete_model,
+				db::providers_db::read_api_keys_from_env,
+				db::chats::rename_chat,
+				db::chats::archive_chat,
+				db::chats::delete_chat,
+				db::chats::get_chat_parameters,
+				db::chats::update_chat_parameters,
+				db::chats::get_chat_system_prompt,
+				db::chats::set_chat_system_prompt,
+				db::mcp_servers::get_mcp_servers,
+				db::mcp_servers::save_mcp_server,
+				db::mcp_servers::delete_mcp_server,
+				mcp::list_mcp_capabilities,
+				mcp::answer_tool_approval,
+				settings::get_settings,
+				settings::apply_and_save_settings
+			])
+			.typ::<types::MessageDelta>()
+			.typ::<types::ContextTruncated>()
+			.typ::<types::ProviderRetry>()
+			.typ::<types::ModelDeprecated>()
+			.typ::<types::ToolActivity>()
+			.typ::<types::ToolApprovalRequest>()
+			.typ::<types::ImportProgress>();
 		specta_builder
 			.export(specta_typescript::Typescript::default(), "../bindings.ts")
 			.expect("Failed to export typescript bindings");
@@ -101,14 +161,53 @@ pub async fn run() {
 		}
 	};
 
+	if serve_mcp {
+		let settings = settings::Settings::load(&app_paths.settings_file);
+		mcp::serve(pool, settings).await;
+		return;
+	}
+
+	// Attached images are loaded by the webview through the attachment protocol
+	let attachments_dir = app_paths.app_dir.join(db::ATTACHMENTS_DIR);
+
 	let app = tauri::Builder::default()
 		.plugin(tauri_plugin_dialog::init())
 		.plugin(tauri_plugin_opener::init())
+		.register_uri_scheme_protocol("attachment", move |_ctx, request| db::serve_attachment(&attachments_dir, request.uri().path()))
 		.invoke_handler(tauri::generate_handler![
 			error_popup,
 			providers::get_message,
+			providers::cancel_generation,
+			providers::retry_message,
+			providers::regenerate_message,
+			providers::edit_message,
+			providers::compare_models,
 			db::chats::get_chats,
+			db::search::search_chats,
+			db::embeddings::search_similar_chats,
+			db::embeddings::get_similar_chats,
+			db::embeddings::get_embedding_status,
+			db::collections::get_collections,
+			db::collections::create_collection,
+			db::collections::rename_collection,
+			db::collections::delete_collection,
+			db::collections::get_collection_documents,
+			db::collections::add_collection_documents,
+			db::collections::remove_collection_document,
+			db::collections::get_chat_collections,
+			db::collections::set_chat_collections,
+			db::chats::fork_chat,
+			export::export_chat,
+			export::export_chats,
+			import::import_chats,
+			db::usage::get_chat_usage,
+			db::usage::get_usage_by_provider,
+			db::usage::get_usage_by_day,
 			db::messages::load_chat,
+			db::messages::get_message_versions,
+			db::messages::switch_message_version,
+			db::attachments::add_attachment,
+			db::attachments::remove_attachment,
 			db::providers_db::load_providers,
 			db::providers_db::set_api_key,
 			db::providers_db::add_provider,
@@ -118,11 +217,23 @@ pub async fn run() {
 			db::models::get_all_models,
 			db::models::add_model,
 			db::models::update_model,
+			db::models::refresh_ollama_models,
+			db::models::list_provider_models,
+			db::models::import_models,
 			db::models::delete_model,
 			db::providers_db::read_api_keys_from_env,
 			db::chats::rename_chat,
 			db::chats::archive_chat,
 			db::chats::delete_chat,
+			db::chats::get_chat_parameters,
+			db::chats::update_chat_parameters,
+			db::chats::get_chat_system_prompt,
+			db::chats::set_chat_system_prompt,
+			db::mcp_servers::get_mcp_servers,
+			db::mcp_servers::save_mcp_server,
+			db::mcp_servers::delete_mcp_server,
+			mcp::list_mcp_capabilities,
+			mcp::answer_tool_approval,
 			settings::get_settings,
 			settings::apply_and_save_settings
 		])
@@ -158,6 +269,9 @@ pub async fn run() {
 			app.manage(AppSettings::new(loaded_settings.clone()));
 			app.manage(PathsState(app_paths.clone()));
 			app.manage(MainWindow(Arc::new(win.clone())));
+			app.manage(Generations::default());
+			app.manage(McpClients::default());
+			app.manage(ToolApprovals::default());
 
 			// Also register combined state for backwards compatibility during migration
 			let data: Data = Data {
@@ -168,6 +282,9 @@ pub async fn run() {
 			};
 			app.manage(ArcData::new(data));
 
+			// Embed messages for semantic search in the background, including those written before it was configured
+			tauri::async_runtime::spawn(indexer::run(app.handle().clone()));
+
 			Ok(())
 		})
 		.menu(|app| {
@@ -264,6 +381,9 @@ pub async fn run() {
 			"preferences" | "options" => {
 				let _ = app.emit("menuOpenSettings", ());
 			}
+			"find" => {
+				let _ = app.emit("menuFind", ());
+			}
 			"learn_more" => {
 				let url = "https://github.com/friediisch/GenHub";
 				let _ = tauri_plugin_opener::open_url(url, None::<&str>);
@@ -0,0 +1,274 @@
+//! Listing the models offered by a provider
+
+use reqwest::Client;
+use serde::Deserialize;
+use serde_json::{Map, Value};
+
+use crate::errors::{ProviderError, ProviderResult};
+
+use super::{ApiScheme, RequestOptions};
+
+/// Address of a local Ollama server with the default configuration
+pub const OLLAMA_DEFAULT_URL: &str = "http://localhost:11434";
+
+/// Context size Ollama runs models with whose Modelfile sets no `num_ctx`
+pub const OLLAMA_DEFAULT_CONTEXT: u32 = 2048;
+
+/// API version sent with requests to Anthropic
+const ANTHROPIC_VERSION: &str = "2023-06-01";
+
+/// A model listed by a provider's model endpoint
+#[derive(Debug, Clone)]
+pub struct ListedModel {
+	pub id: String,
+	pub display_name: Option<String>,
+	/// Context length, only reported by some providers
+	pub context_window: Option<u32>,
+}
+
+#[derive(Deserialize)]
+struct ModelList {
+	data: Vec<ModelListEntry>,
+}
+
+/// Entry of a model list, the fields besides the id depend on the provider
+#[derive(Deserialize)]
+struct ModelListEntry {
+	id: String,
+	/// Anthropic
+	#[serde(default)]
+	display_name: Option<String>,
+	/// Groq, Mistral reports it as `max_context_length`
+	#[serde(default, alias = "max_context_length")]
+	context_window: Option<u32>,
+}
+
+/// A model installed on an Ollama server
+#[derive(Debug, Clone)]
+pub struct OllamaModel {
+	pub name: String,
+	/// Context size the server runs the model with, `None` if the server did not report it
+	pub context_window: Option<u32>,
+}
+
+#[derive(Deserialize)]
+struct GoogleModelList {
+	#[serde(default)]
+	models: Vec<GoogleModel>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct GoogleModel {
+	name: String,
+	#[serde(default)]
+	display_name: Option<String>,
+	#[serde(default)]
+	input_token_limit: Option<u32>,
+	#[serde(default)]
+	supported_generation_methods: Vec<String>,
+}
+
+#[derive(Deserialize)]
+struct OllamaTags {
+	models: Vec<OllamaTag>,
+}
+
+#[derive(Deserialize)]
+struct OllamaTag {
+	name: String,
+}
+
+#[derive(Deserialize)]
+struct OllamaModelInfo {
+	/// Modelfile parameters, one `name value` pair per line
+	#[serde(default)]
+	parameters: Option<String>,
+	/// Model metadata, the context length is stored under `<architecture>.context_length`
+	#[serde(default)]
+	model_info: Option<Map<String, Value>>,
+}
+
+/// List the models a provider offers using its model endpoint
+pub async fn list_models(
+	provider_name: &str,
+	api_scheme: &ApiScheme,
+	api_key: &str,
+	base_url: Option<&str>,
+	options: &RequestOptions,
+) -> ProviderResult<Vec<ListedModel>> {
+	let base_url = base_url
+		.filter(|url| !url.is_empty())
+		.or(api_scheme.default_base_url())
+		.ok_or_else(|| ProviderError::ModelListFailed {
+			provider: provider_name.to_string(),
+			details: "no base URL configured".to_string(),
+		})?
+		.trim_end_matches('/');
+
+	if let ApiScheme::Azure = api_scheme {
+		return Err(ProviderError::ModelListFailed {
+			provider: provider_name.to_string(),
+			details: "Azure OpenAI deployments cannot be listed, add them as models using the deployment name".to_string(),
+		});
+	}
+
+	if let ApiScheme::Ollama = api_scheme {
+		let models = list_ollama_models(base_url).await?;
+		return Ok(models
+			.into_iter()
+			.map(|model| ListedModel {
+				id: model.name,
+				display_name: None,
+				context_window: model.context_window,
+			})
+			.collect());
+	}
+
+	if let ApiScheme::Google = api_scheme {
+		return list_google_models(provider_name, api_key, base_url).await;
+	}
+
+	let mut request = Client::new().get(format!("{}/models", base_url)).query(&options.query);
+	for (name, value) in &options.headers {
+		request = request.header(name, value);
+	}
+	let request = match api_scheme {
+		ApiScheme::Anthropic => request
+			.header("x-api-key", api_key)
+			.header("anthropic-version", ANTHROPIC_VERSION)
+			.query(&[("limit", "1000")]),
+		_ => request.bearer_auth(api_key),
+	};
+	let response = request.send().await.map_err(|e| list_failed(provider_name, e))?;
+	if matches!(response.status().as_u16(), 401 | 403) {
+		return Err(ProviderError::InvalidApiKey {
+			provider: provider_name.to_string(),
+			reason: format!("the model endpoint answered with {}", response.status()),
+		});
+	}
+	let list: ModelList = response
+		.error_for_status()
+		.map_err(|e| list_failed(provider_name, e))?
+		.json()
+		.await
+		.map_err(|e| list_failed(provider_name, e))?;
+
+	Ok(list
+		.data
+		.into_iter()
+		.map(|entry| ListedModel {
+			id: entry.id,
+			display_name: entry.display_name,
+			context_window: entry.context_window,
+		})
+		.collect())
+}
+
+/// List the Gemini models that can generate content
+async fn list_google_models(provider_name: &str, api_key: &str, base_url: &str) -> ProviderResult<Vec<ListedModel>> {
+	let response = Client::new()
+		.get(format!("{}/models", base_url))
+		.query(&[("key", api_key), ("pageSize", "1000")])
+		.send()
+		.await
+		.map_err(|e| list_failed(provider_name, e))?;
+	if matches!(response.status().as_u16(), 400 | 401 | 403) {
+		return Err(ProviderError::InvalidApiKey {
+			provider: provider_name.to_string(),
+			reason: format!("the model endpoint answered with {}", response.status()),
+		});
+	}
+	let list: GoogleModelList = response
+		.error_for_status()
+		.map_err(|e| list_failed(provider_name, e))?
+		.json()
+		.await
+		.map_err(|e| list_failed(provider_name, e))?;
+
+	Ok(list
+		.models
+		.into_iter()
+		.filter(|model| model.supported_generation_methods.iter().any(|method| method == "generateContent"))
+		.map(|model| ListedModel {
+			// Names are returned as `models/<id>`, requests only take the id
+			id: model.name.trim_start_matches("models/").to_string(),
+			display_name: model.display_name,
+			context_window: model.input_token_limit,
+		})
+		.collect())
+}
+
+/// List the models installed on an Ollama server using its tags endpoint
+pub async fn list_ollama_models(base_url: &str) -> ProviderResult<Vec<OllamaModel>> {
+	let client = Client::new();
+	let base_url = base_url.trim_end_matches('/');
+	let tags: OllamaTags = client
+		.get(format!("{}/api/tags", base_url))
+		.send()
+		.await
+		.and_then(|response| response.error_for_status())
+		.map_err(|e| list_failed("ollama", e))?
+		.json()
+		.await
+		.map_err(|e| list_failed("ollama", e))?;
+
+	let mut models = Vec::with_capacity(tags.models.len());
+	for tag in tags.models {
+		let context_window = match ollama_context_window(&client, base_url, &tag.name).await {
+			Ok(context_window) => context_window,
+			Err(e) => {
+				log::warn!("Could not get the context size of Ollama model {}: {}", tag.name, e);
+				None
+			}
+		};
+		models.push(OllamaModel {
+			name: tag.name,
+			context_window,
+		});
+	}
+	Ok(models)
+}
+
+/// Get the context size Ollama runs a model with.
+/// That is the `num_ctx` parameter of the Modelfile, otherwise the server default capped at the context length the model was trained with.
+/// Requests don't set `num_ctx`, so the trained context length is never used in full.
+async fn ollama_context_window(client: &Client, base_url: &str, model_name: &str) -> ProviderResult<Option<u32>> {
+	let info: OllamaModelInfo = client
+		.post(format!("{}/api/show", base_url))
+		.json(&serde_json::json!({ "model": model_name }))
+		.send()
+		.await
+		.and_then(|response| response.error_for_status())
+		.map_err(|e| list_failed("ollama", e))?
+		.json()
+		.await
+		.map_err(|e| list_failed("ollama", e))?;
+
+	let num_ctx = info.parameters.as_deref().and_then(|parameters| {
+		parameters
+			.lines()
+			.find_map(|line| match line.split_whitespace().collect::<Vec<_>>().as_slice() {
+				["num_ctx", value] => value.parse().ok(),
+				_ => None,
+			})
+	});
+	let context_length = info.model_info.as_ref().and_then(|model_info| {
+		model_info
+			.iter()
+			.find(|(key, _)| key.ends_with(".context_length"))
+			.and_then(|(_, value)| value.as_u64())
+			.map(|value| value.min(u32::MAX as u64) as u32)
+	});
+	Ok(Some(num_ctx.unwrap_or_else(|| {
+		context_length.map_or(OLLAMA_DEFAULT_CONTEXT, |length| length.min(OLLAMA_DEFAULT_CONTEXT))
+	})))
+}
+
+/// Error for a failed request to the model endpoints of a provider
+fn list_failed(provider: &str, error: reqwest::Error) -> ProviderError {
+	ProviderError::ModelListFailed {
+		provider: provider.to_string(),
+		details: error.to_string(),
+	}
+}
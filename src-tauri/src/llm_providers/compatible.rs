@@ -0,0 +1,434 @@
+//! Client for OpenAI-compatible chat completion APIs that need extra headers or query parameters, like gateways and proxies.
+//! The llm crate cannot add them, all other requests go through its backends.
+
+use std::collections::VecDeque;
+use std::time::Duration;
+
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use futures::{stream, Stream, StreamExt};
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue, AUTHORIZATION, RETRY_AFTER};
+use reqwest::{Client, Response};
+use serde_json::{json, Value};
+
+use crate::errors::{ProviderError, ProviderResult};
+use crate::types::{Message, MessageHistory};
+
+use super::enums::AnswerStream;
+use super::retry::parse_retry_after;
+use super::{Answer, ApiScheme, LLMConfig, RequestOptions, StreamChunk, TokenUsage, ToolAnswer, ToolCall, ToolRound, ToolSpec};
+
+/// API version used for Azure OpenAI when the provider does not set an `api-version` query parameter
+pub const AZURE_API_VERSION: &str = "2024-10-21";
+
+pub struct CompatibleClient {
+	provider_name: String,
+	api_scheme: ApiScheme,
+	model: String,
+	url: String,
+	headers: HeaderMap,
+	query: Vec<(String, String)>,
+}
+
+impl CompatibleClient {
+	pub fn new(provider_name: &str, api_scheme: &ApiScheme, api_key: &str, base_url: &str, model: &str, options: &RequestOptions) -> ProviderResult<Self> {
+		let configuration_error = |details: String| ProviderError::Configuration {
+			provider: provider_name.to_string(),
+			details,
+		};
+
+		let base_url = base_url.trim_end_matches('/');
+		let mut headers = HeaderMap::new();
+		let mut query: Vec<(String, String)> = options.query.iter().map(|(name, value)| (name.clone(), value.clone())).collect();
+		let url = match api_scheme {
+			// Azure addresses the model through the deployment in the URL and requires an API version
+			ApiScheme::Azure => {
+				if !query.iter().any(|(name, _)| name == "api-version") {
+					query.push(("api-version".to_string(), AZURE_API_VERSION.to_string()));
+				}
+				if !api_key.is_empty() {
+					let value = HeaderValue::from_str(api_key).map_err(|e| configuration_error(e.to_string()))?;
+					headers.insert(HeaderName::from_static("api-key"), value);
+				}
+				format!("{}/openai/deployments/{}/chat/completions", base_url, model)
+			}
+			_ => {
+				if !api_key.is_empty() {
+					let value = HeaderValue::from_str(&format!("Bearer {}", api_key)).map_err(|e| configuration_error(e.to_string()))?;
+					headers.insert(AUTHORIZATION, value);
+				}
+				format!("{}/chat/completions", base_url)
+			}
+		};
+
+		for (name, value) in &options.headers {
+			let name = HeaderName::from_bytes(name.as_bytes()).map_err(|e| configuration_error(format!("invalid header name {}: {}", name, e)))?;
+			let value = HeaderValue::from_str(value).map_err(|e| configuration_error(format!("invalid value for header {}: {}", name, e)))?;
+			headers.insert(name, value);
+		}
+
+		Ok(Self {
+			provider_name: provider_name.to_string(),
+			api_scheme: api_scheme.clone(),
+			model: model.to_string(),
+			url,
+			headers,
+			query,
+		})
+	}
+
+	/// Send a chat request and get the whole answer
+	pub async fn complete(&self, messages: &MessageHistory, config: &LLMConfig, system_prompt: Option<String>) -> ProviderResult<Answer> {
+		let response = self.send(self.body(messages, config, system_prompt, false)).await?;
+		let body: Value = response.json().await.map_err(|e| self.failed(e.to_string()))?;
+		let text = body["choices"][0]["message"]["content"]
+			.as_str()
+			.ok_or_else(|| self.failed("No response text from LLM".to_string()))?;
+		Ok(Answer {
+			text: text.to_string(),
+			usage: parse_usage(&body["usage"]),
+		})
+	}
+
+	/// Send a chat request and stream the answer from the server-sent events of the response
+	pub async fn stream(&self, messages: &MessageHistory, config: &LLMConfig, system_prompt: Option<String>) -> ProviderResult<AnswerStream> {
+		let response = self.send(self.body(messages, config, system_prompt, true)).await?;
+		Ok(Box::pin(event_stream(Box::pin(response.bytes_stream()), self.provider_name.clone())))
+	}
+
+	/// Send the history and the tool calls made so far, offering the tools again
+	pub async fn complete_with_tools(
+		&self,
+		messages: &MessageHistory,
+		rounds: &[ToolRound],
+		tools: &[ToolSpec],
+		config: &LLMConfig,
+		system_prompt: Option<String>,
+	) -> ProviderResult<ToolAnswer> {
+		let mut body = self.body(messages, config, system_prompt, false);
+		if let Some(chat_messages) = body["messages"].as_array_mut() {
+			chat_messages.extend(rounds.iter().flat_map(round_messages));
+		}
+		body["tools"] = tools
+			.iter()
+			.map(|tool| json!({ "type": "function", "function": { "name": tool.name, "description": tool.description, "parameters": tool.parameters } }))
+			.collect();
+
+		let response = self.send(body).await?;
+		let body: Value = response.json().await.map_err(|e| self.failed(e.to_string()))?;
+		let message = &body["choices"][0]["message"];
+		let tool_calls: Vec<ToolCall> = message["tool_calls"]
+			.as_array()
+			.map(|calls| {
+				calls
+					.iter()
+					.filter_map(|call| {
+						Some(ToolCall {
+							id: call["id"].as_str()?.to_string(),
+							name: call["function"]["name"].as_str()?.to_string(),
+							arguments: call["function"]["arguments"].as_str().unwrap_or("{}").to_string(),
+						})
+					})
+					.collect()
+			})
+			.unwrap_or_default();
+		let text = message["content"].as_str().unwrap_or_default().to_string();
+		if text.is_empty() && tool_calls.is_empty() {
+			return Err(self.failed("No response text from LLM".to_string()));
+		}
+		Ok(ToolAnswer {
+			text,
+			tool_calls,
+			usage: parse_usage(&body["usage"]),
+		})
+	}
+
+	/// Request body in the chat completions format
+	fn body(&self, messages: &MessageHistory, config: &LLMConfig, system_prompt: Option<String>, stream: bool) -> Value {
+		let mut chat_messages = Vec::new();
+		if let Some(system_prompt) = system_prompt {
+			chat_messages.push(json!({ "role": "system", "content": system_prompt }));
+		}
+		for msg in messages.iter().filter(|msg| msg.role != "system") {
+			let role = if msg.role == "assistant" { "assistant" } else { "user" };
+			chat_messages.push(json!({ "role": role, "content": message_content(msg) }));
+		}
+
+		let mut body = json!({
+			"messages": chat_messages,
+			"temperature": config.temperature,
+			"max_tokens": config.max_tokens,
+			"stream": stream,
+		});
+		// Azure takes the model from the deployment in the URL
+		if !matches!(self.api_scheme, ApiScheme::Azure) {
+			body["model"] = self.model.clone().into();
+		}
+		if let Some(top_p) = config.top_p {
+			body["top_p"] = top_p.into();
+		}
+		// Only OpenAI and Azure report usage in streams when asked, other APIs reject the field
+		if stream && matches!(self.api_scheme, ApiScheme::OpenAI | ApiScheme::Azure) {
+			body["stream_options"] = json!({ "include_usage": true });
+		}
+		if let (Some(Value::Object(extra)), Some(fields)) = (config.extra_body(&self.api_scheme), body.as_object_mut()) {
+			fields.extend(extra);
+		}
+		body
+	}
+
+	async fn send(&self, body: Value) -> ProviderResult<Response> {
+		let response = Client::new()
+			.post(&self.url)
+			.headers(self.headers.clone())
+			.query(&self.query)
+			.json(&body)
+			.send()
+			.await
+			.map_err(|e| self.failed(e.to_string()))?;
+		if response.status().is_success() {
+			Ok(response)
+		} else {
+			Err(error_from_response(&self.provider_name, response).await)
+		}
+	}
+
+	fn failed(&self, details: String) -> ProviderError {
+		ProviderError::MessageFailed {
+			provider: self.provider_name.clone(),
+			details,
+			status: None,
+		}
+	}
+}
+
+/// Content of a message including attached files, a list of parts with the attached images in front of the text if there are any
+fn message_content(msg: &Message) -> Value {
+	let images: Vec<Value> = msg
+		.attachments
+		.iter()
+		.filter(|attachment| attachment.is_image())
+		.filter_map(|attachment| {
+			let contents = attachment.data.as_ref()?;
+			let url = format!("data:{};base64,{}", attachment.mime_type, BASE64.encode(contents));
+			Some(json!({ "type": "image_url", "image_url": { "url": url } }))
+		})
+		.collect();
+	if images.is_empty() {
+		return msg.prompt_content().into();
+	}
+	let mut parts = images;
+	parts.push(json!({ "type": "text", "text": msg.prompt_content() }));
+	parts.into()
+}
+
+/// Messages of a tool round: the assistant message with the calls, followed by one tool message per output
+fn round_messages(round: &ToolRound) -> Vec<Value> {
+	let calls: Vec<Value> = round
+		.calls
+		.iter()
+		.map(|call| json!({ "id": call.id, "type": "function", "function": { "name": call.name, "arguments": call.arguments } }))
+		.collect();
+	let mut messages = vec![json!({ "role": "assistant", "content": null, "tool_calls": calls })];
+	messages.extend(
+		round
+			.outputs
+			.iter()
+			.map(|output| json!({ "role": "tool", "tool_call_id": output.call_id, "content": output.model_content() })),
+	);
+	messages
+}
+
+/// Classify an unsuccessful response by its status, keeping the error message the provider sent
+async fn error_from_response(provider: &str, response: Response) -> ProviderError {
+	let status = response.status().as_u16();
+	let retry_after = response
+		.headers()
+		.get(RETRY_AFTER)
+		.and_then(|value| value.to_str().ok())
+		.and_then(|value| value.trim().parse::<u64>().ok())
+		.map(Duration::from_secs);
+	let body = response.text().await.unwrap_or_default();
+	let retry_after = retry_after.or_else(|| parse_retry_after(&body));
+	let details = serde_json::from_str::<Value>(&body)
+		.ok()
+		.and_then(|body| body["error"]["message"].as_str().map(|message| message.to_string()))
+		.unwrap_or(body);
+
+	match status {
+		429 => ProviderError::RateLimited {
+			provider: provider.to_string(),
+			retry_after,
+		},
+		401 | 403 => ProviderError::InvalidApiKey {
+			provider: provider.to_string(),
+			reason: details,
+		},
+		_ => ProviderError::MessageFailed {
+			provider: provider.to_string(),
+			details,
+			status: Some(status),
+		},
+	}
+}
+
+fn parse_usage(usage: &Value) -> Option<TokenUsage> {
+	Some(TokenUsage {
+		prompt_tokens: usage["prompt_tokens"].as_u64()? as u32,
+		completion_tokens: usage["completion_tokens"].as_u64()? as u32,
+	})
+}
+
+/// State of a server-sent event stream while it is being parsed
+struct EventStream<S> {
+	bytes: S,
+	/// Received bytes that do not form a complete line yet
+	buffer: Vec<u8>,
+	/// Parsed chunks that have not been yielded yet
+	pending: VecDeque<ProviderResult<StreamChunk>>,
+	done: bool,
+	provider_name: String,
+}
+
+impl<S> EventStream<S> {
+	/// Parse all complete lines of the buffer
+	fn parse_lines(&mut self) {
+		while let Some(position) = self.buffer.iter().position(|byte| *byte == b'\n') {
+			let line: Vec<u8> = self.buffer.drain(..=position).collect();
+			let line = String::from_utf8_lossy(&line);
+			let Some(data) = line.trim().strip_prefix("data:") else {
+				continue;
+			};
+			let data = data.trim();
+			if data == "[DONE]" {
+				self.done = true;
+				return;
+			}
+			match serde_json::from_str::<Value>(data) {
+				Ok(event) => self.parse_event(&event),
+				Err(e) => log::warn!("Could not parse stream event from {}: {}", self.provider_name, e),
+			}
+		}
+	}
+
+	fn parse_event(&mut self, event: &Value) {
+		if let Some(message) = event["error"]["message"].as_str() {
+			self.pending.push_back(Err(ProviderError::MessageFailed {
+				provider: self.provider_name.clone(),
+				details: message.to_string(),
+				status: None,
+			}));
+			return;
+		}
+		if let Some(delta) = event["choices"][0]["delta"]["content"].as_str() {
+			if !delta.is_empty() {
+				self.pending.push_back(Ok(StreamChunk::Text(delta.to_string())));
+			}
+		}
+		if let Some(usage) = parse_usage(&event["usage"]) {
+			self.pending.push_back(Ok(StreamChunk::Usage(usage)));
+		}
+	}
+}
+
+/// Turn the body of a streamed response into text deltas and usage
+fn event_stream<S, B>(bytes: S, provider_name: String) -> impl Stream<Item = ProviderResult<StreamChunk>> + Send
+where
+	S: Stream<Item = reqwest::Result<B>> + Send + Unpin + 'static,
+	B: AsRef<[u8]> + Send,
+{
+	let state = EventStream {
+		bytes,
+		buffer: Vec::new(),
+		pending: VecDeque::new(),
+		done: false,
+		provider_name,
+	};
+	stream::unfold(state, |mut state| async move {
+		loop {
+			if let Some(chunk) = state.pending.pop_front() {
+				return Some((chunk, state));
+			}
+			if state.done {
+				return None;
+			}
+			match state.bytes.next().await {
+				Some(Ok(bytes)) => {
+					state.buffer.extend_from_slice(bytes.as_ref());
+					state.parse_lines();
+				}
+				Some(Err(e)) => {
+					state.done = true;
+					let error = ProviderError::MessageFailed {
+						provider: state.provider_name.clone(),
+						details: e.to_string(),
+						status: None,
+					};
+					return Some((Err(error), state));
+				}
+				None => state.done = true,
+			}
+		}
+	})
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use futures::executor::block_on;
+
+	/// Parse a response body arriving in the given pieces
+	fn parse(pieces: &[&str]) -> Vec<ProviderResult<StreamChunk>> {
+		let bytes = stream::iter(
+			pieces
+				.iter()
+				.map(|piece| Ok::<_, reqwest::Error>(piece.as_bytes().to_vec()))
+				.collect::<Vec<_>>(),
+		);
+		block_on(event_stream(bytes, "custom".to_string()).collect())
+	}
+
+	fn texts(chunks: &[ProviderResult<StreamChunk>]) -> Vec<&str> {
+		chunks
+			.iter()
+			.filter_map(|chunk| match chunk {
+				Ok(StreamChunk::Text(text)) => Some(text.as_str()),
+				_ => None,
+			})
+			.collect()
+	}
+
+	#[test]
+	fn joins_events_split_across_frames() {
+		let chunks = parse(&[
+			"data: {\"choices\":[{\"delta\":{\"content\":\"Hel\"}}]}\n\ndata: {\"choi",
+			"ces\":[{\"delta\":{\"content\":\"lo\"}}]}",
+			"\n\n",
+		]);
+		assert_eq!(texts(&chunks), ["Hel", "lo"]);
+	}
+
+	#[test]
+	fn stops_at_done() {
+		let chunks = parse(&[
+			"data: {\"choices\":[{\"delta\":{\"content\":\"Hi\"}}],\"usage\":{\"prompt_tokens\":5,\"completion_tokens\":1}}\n\n",
+			"data: [DONE]\n\ndata: {\"choices\":[{\"delta\":{\"content\":\"ignored\"}}]}\n\n",
+		]);
+		assert_eq!(chunks.len(), 2);
+		assert_eq!(texts(&chunks), ["Hi"]);
+		assert!(matches!(
+			chunks[1],
+			Ok(StreamChunk::Usage(TokenUsage {
+				prompt_tokens: 5,
+				completion_tokens: 1
+			}))
+		));
+	}
+
+	#[test]
+	fn reports_error_events() {
+		let chunks = parse(&["data: {\"error\":{\"message\":\"The server is overloaded\"}}\n\n"]);
+		assert_eq!(chunks.len(), 1);
+		assert!(matches!(&chunks[0], Err(ProviderError::MessageFailed { details, .. }) if details == "The server is overloaded"));
+	}
+}
@@ -0,0 +1,142 @@
+//! Embedding texts with the embedding endpoint of a provider
+
+use reqwest::{Client, RequestBuilder};
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use serde_json::json;
+
+use crate::errors::{ProviderError, ProviderResult};
+
+use super::compatible::AZURE_API_VERSION;
+use super::{ApiScheme, RequestOptions};
+
+#[derive(Deserialize)]
+struct EmbeddingList {
+	data: Vec<EmbeddingEntry>,
+}
+
+#[derive(Deserialize)]
+struct EmbeddingEntry {
+	index: usize,
+	embedding: Vec<f32>,
+}
+
+#[derive(Deserialize)]
+struct OllamaEmbeddings {
+	embeddings: Vec<Vec<f32>>,
+}
+
+#[derive(Deserialize)]
+struct GoogleEmbeddings {
+	#[serde(default)]
+	embeddings: Vec<GoogleEmbedding>,
+}
+
+#[derive(Deserialize)]
+struct GoogleEmbedding {
+	values: Vec<f32>,
+}
+
+/// Embed texts with an embedding model of a provider, returns one vector per text in the same order
+pub async fn embed(
+	provider_name: &str,
+	api_scheme: &ApiScheme,
+	api_key: &str,
+	base_url: Option<&str>,
+	options: &RequestOptions,
+	model: &str,
+	inputs: &[String],
+) -> ProviderResult<Vec<Vec<f32>>> {
+	if inputs.is_empty() {
+		return Ok(Vec::new());
+	}
+	let base_url = base_url
+		.filter(|url| !url.is_empty())
+		.or(api_scheme.default_base_url())
+		.ok_or_else(|| embedding_failed(provider_name, "no base URL configured"))?
+		.trim_end_matches('/');
+
+	let client = Client::new();
+	let vectors = match api_scheme {
+		ApiScheme::Anthropic | ApiScheme::Groq => {
+			return Err(ProviderError::Unsupported(format!("{} does not offer embedding models", provider_name)));
+		}
+		ApiScheme::Ollama => {
+			let request = client.post(format!("{}/api/embed", base_url)).json(&json!({ "model": model, "input": inputs }));
+			let embeddings: OllamaEmbeddings = send(provider_name, request).await?;
+			embeddings.embeddings
+		}
+		ApiScheme::Google => {
+			let requests: Vec<_> = inputs
+				.iter()
+				.map(|input| json!({ "model": format!("models/{}", model), "content": { "parts": [{ "text": input }] } }))
+				.collect();
+			let request = client
+				.post(format!("{}/models/{}:batchEmbedContents", base_url, model))
+				.query(&[("key", api_key)])
+				.json(&json!({ "requests": requests }));
+			let embeddings: GoogleEmbeddings = send(provider_name, request).await?;
+			embeddings.embeddings.into_iter().map(|embedding| embedding.values).collect()
+		}
+		_ => {
+			let mut request = match api_scheme {
+				// Azure addresses the model through the deployment in the URL and requires an API version
+				ApiScheme::Azure => {
+					let request = client
+						.post(format!("{}/openai/deployments/{}/embeddings", base_url, model))
+						.header("api-key", api_key);
+					match options.query.contains_key("api-version") {
+						true => request,
+						false => request.query(&[("api-version", AZURE_API_VERSION)]),
+					}
+				}
+				_ => client.post(format!("{}/embeddings", base_url)).bearer_auth(api_key),
+			}
+			.query(&options.query);
+			for (name, value) in &options.headers {
+				request = request.header(name, value);
+			}
+			let list: EmbeddingList = send(provider_name, request.json(&json!({ "model": model, "input": inputs }))).await?;
+			let mut entries = list.data;
+			entries.sort_by_key(|entry| entry.index);
+			entries.into_iter().map(|entry| entry.embedding).collect()
+		}
+	};
+
+	if vectors.len() != inputs.len() {
+		return Err(embedding_failed(
+			provider_name,
+			&format!("expected {} embeddings, got {}", inputs.len(), vectors.len()),
+		));
+	}
+	Ok(vectors)
+}
+
+/// Send an embedding request and parse the answer
+async fn send<T: DeserializeOwned>(provider_name: &str, request: RequestBuilder) -> ProviderResult<T> {
+	let response = request.send().await.map_err(|e| embedding_failed(provider_name, &e.to_string()))?;
+	match response.status().as_u16() {
+		401 | 403 => Err(ProviderError::InvalidApiKey {
+			provider: provider_name.to_string(),
+			reason: format!("the embedding endpoint answered with {}", response.status()),
+		}),
+		429 => Err(ProviderError::RateLimited {
+			provider: provider_name.to_string(),
+			retry_after: None,
+		}),
+		_ => response
+			.error_for_status()
+			.map_err(|e| embedding_failed(provider_name, &e.to_string()))?
+			.json()
+			.await
+			.map_err(|e| embedding_failed(provider_name, &e.to_string())),
+	}
+}
+
+/// Error for a failed request to the embedding endpoint of a provider
+fn embedding_failed(provider: &str, details: &str) -> ProviderError {
+	ProviderError::EmbeddingFailed {
+		provider: provider.to_string(),
+		details: details.to_string(),
+	}
+}
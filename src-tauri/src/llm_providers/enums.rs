@@ -1,13 +1,26 @@
+use std::pin::Pin;
+
 use anyhow::{anyhow, Result};
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use futures::{stream, Stream, StreamExt};
 use llm::{
 	builder::{LLMBackend, LLMBuilder},
-	chat::ChatMessage,
-	LLMProvider,
+	chat::{ChatMessage, ChatResponse, FunctionTool, ImageMime, StreamResponse, Tool},
+	error::LLMError,
+	FunctionCall, LLMProvider,
 };
 
+use crate::errors::{ProviderError, ProviderResult};
 use crate::types::MessageHistory;
 
-use super::LLMConfig;
+use super::catalog::OLLAMA_DEFAULT_URL;
+use super::compatible::{CompatibleClient, AZURE_API_VERSION};
+use super::retry::classify_error;
+use super::{Answer, LLMConfig, RequestOptions, StreamChunk, TokenUsage, ToolAnswer, ToolCall, ToolRound, ToolSpec};
+
+/// Stream of text deltas and usage returned by a provider while it generates an answer
+pub type AnswerStream = Pin<Box<dyn Stream<Item = ProviderResult<StreamChunk>> + Send>>;
 
 /// Supported API schemes for custom providers
 #[derive(Clone, Debug)]
@@ -17,6 +30,9 @@ pub enum ApiScheme {
 	Groq,
 	Mistral,
 	Ollama,
+	Google,
+	/// Azure OpenAI, the model name is the name of the deployment
+	Azure,
 }
 
 impl ApiScheme {
@@ -27,6 +43,8 @@ impl ApiScheme {
 			"groq" => Some(Self::Groq),
 			"mistral" => Some(Self::Mistral),
 			"ollama" => Some(Self::Ollama),
+			"google" | "gemini" => Some(Self::Google),
+			"azure" | "azure_openai" => Some(Self::Azure),
 			_ => None,
 		}
 	}
@@ -38,19 +56,77 @@ impl ApiScheme {
 			Self::Groq => LLMBackend::Groq,
 			Self::Mistral => LLMBackend::Mistral,
 			Self::Ollama => LLMBackend::Ollama,
+			Self::Google => LLMBackend::Google,
+			Self::Azure => LLMBackend::AzureOpenAI,
+		}
+	}
+
+	/// Whether the API follows the OpenAI chat completions format
+	pub fn is_openai_compatible(&self) -> bool {
+		matches!(self, Self::OpenAI | Self::Groq | Self::Mistral | Self::Azure)
+	}
+
+	/// How the llm crate's backend for the API streams answers
+	fn stream_support(&self) -> StreamSupport {
+		match self {
+			Self::OpenAI | Self::Groq | Self::Mistral | Self::Google => StreamSupport::WithUsage,
+			Self::Anthropic | Self::Ollama => StreamSupport::TextOnly,
+			Self::Azure => StreamSupport::None,
 		}
 	}
+
+	/// Default API address, used when no base URL is configured.
+	/// Azure has none since every resource has its own address.
+	pub fn default_base_url(&self) -> Option<&'static str> {
+		match self {
+			Self::OpenAI => Some("https://api.openai.com/v1"),
+			Self::Anthropic => Some("https://api.anthropic.com/v1"),
+			Self::Groq => Some("https://api.groq.com/openai/v1"),
+			Self::Mistral => Some("https://api.mistral.ai/v1"),
+			Self::Ollama => Some(OLLAMA_DEFAULT_URL),
+			Self::Google => Some("https://generativelanguage.googleapis.com/v1beta"),
+			Self::Azure => None,
+		}
+	}
+}
+
+/// Streaming implemented by a backend of the llm crate, the others fall back to errors that can't be told apart from provider errors
+enum StreamSupport {
+	/// Text deltas followed by the usage
+	WithUsage,
+	/// Text deltas only
+	TextOnly,
+	/// The whole answer arrives at once
+	None,
 }
 
 /// Supported LLM providers
 #[derive(Clone, Debug)]
 pub enum Provider {
-	OpenAI { api_key: String },
-	Anthropic { api_key: String },
-	Groq { api_key: String },
-	Mistral { api_key: String },
-	Ollama { base_url: Option<String> },
-	Custom { api_key: String, base_url: String, api_scheme: ApiScheme },
+	OpenAI {
+		api_key: String,
+	},
+	Anthropic {
+		api_key: String,
+	},
+	Groq {
+		api_key: String,
+	},
+	Mistral {
+		api_key: String,
+	},
+	Ollama {
+		base_url: Option<String>,
+	},
+	Google {
+		api_key: String,
+	},
+	Custom {
+		api_key: String,
+		base_url: String,
+		api_scheme: ApiScheme,
+		options: RequestOptions,
+	},
 }
 
 impl Provider {
@@ -63,6 +139,7 @@ impl Provider {
 			"groq" | "groqcloud" => Ok(Self::Groq { api_key: api_key.to_string() }),
 			"mistral" | "mistralai" => Ok(Self::Mistral { api_key: api_key.to_string() }),
 			"ollama" => Ok(Self::Ollama { base_url: None }),
+			"google" | "gemini" => Ok(Self::Google { api_key: api_key.to_string() }),
 			_ => Err(anyhow!("Unsupported provider: {}", provider_name)),
 		}
 	}
@@ -74,11 +151,93 @@ impl Provider {
 			api_key: api_key.to_string(),
 			base_url: base_url.to_string(),
 			api_scheme: scheme,
+			options: RequestOptions::default(),
 		})
 	}
 
+	/// Whether requests to this provider can carry extra headers and query parameters.
+	/// Only OpenAI-compatible APIs can, since the compatible client is the only one that adds them.
+	pub fn supports_request_options(&self) -> bool {
+		self.api_scheme().is_openai_compatible()
+	}
+
+	/// Whether stop sequences, seed and penalties reach the API.
+	/// The llm crate only sends them as extra request fields, which its Anthropic, Ollama, Google and Azure backends ignore,
+	/// so Azure deployments only support them when requests go through the compatible client.
+	pub fn supports_extra_parameters(&self) -> bool {
+		match self.api_scheme() {
+			ApiScheme::Azure => self.needs_compatible_client(),
+			api_scheme => api_scheme.is_openai_compatible(),
+		}
+	}
+
+	/// Send requests with extra headers and query parameters.
+	/// Built-in providers become custom ones using their default address, since only the compatible client can add them.
+	/// Providers that don't support them keep sending plain requests, saving a provider rejects such options beforehand.
+	pub fn with_request_options(self, options: RequestOptions) -> Self {
+		if options.is_empty() {
+			return self;
+		}
+		if !self.supports_request_options() {
+			log::warn!("Ignoring extra headers and query parameters for {}", self.provider_name());
+			return self;
+		}
+		let api_scheme = self.api_scheme();
+		match self {
+			Provider::Custom {
+				api_key, base_url, api_scheme, ..
+			} => Self::Custom {
+				api_key,
+				base_url,
+				api_scheme,
+				options,
+			},
+			Provider::OpenAI { api_key } | Provider::Groq { api_key } | Provider::Mistral { api_key } => Self::Custom {
+				api_key,
+				base_url: api_scheme.default_base_url().unwrap_or_default().to_string(),
+				api_scheme,
+				options,
+			},
+			provider => provider,
+		}
+	}
+
+	/// Whether requests carry extra headers or query parameters, which the llm crate cannot add.
+	/// Azure's `api-version` parameter doesn't count, the llm crate's Azure backend sends it itself.
+	fn needs_compatible_client(&self) -> bool {
+		match self {
+			Provider::Custom { api_scheme, options, .. } => {
+				!options.headers.is_empty()
+					|| options
+						.query
+						.keys()
+						.any(|name| !(matches!(api_scheme, ApiScheme::Azure) && name == "api-version"))
+			}
+			_ => false,
+		}
+	}
+
+	/// Client for requests with extra headers or query parameters, `None` for all others
+	fn compatible_client(&self, model: &str) -> ProviderResult<Option<CompatibleClient>> {
+		match self {
+			Provider::Custom {
+				api_key,
+				base_url,
+				api_scheme,
+				options,
+			} if self.needs_compatible_client() => CompatibleClient::new(self.provider_name(), api_scheme, api_key, base_url, model, options).map(Some),
+			_ => Ok(None),
+		}
+	}
+
 	/// Create a provider from ProviderData, supporting both built-in and custom providers
 	pub fn from_provider_data(provider_name: &str, api_key: &str, base_url: Option<&str>, api_scheme: Option<&str>) -> Result<Self> {
+		// Ollama needs no API key, only the address of the server
+		if provider_name == "ollama" {
+			return Ok(Self::Ollama {
+				base_url: base_url.filter(|url| !url.is_empty()).map(|url| url.to_string()),
+			});
+		}
 		// If base_url is provided, treat as custom provider
 		if let Some(url) = base_url {
 			if !url.is_empty() {
@@ -98,36 +257,170 @@ impl Provider {
 			Provider::Groq { .. } => "groq",
 			Provider::Mistral { .. } => "mistral",
 			Provider::Ollama { .. } => "ollama",
+			Provider::Google { .. } => "google",
 			Provider::Custom { .. } => "custom",
 		}
 	}
 
+	/// Get the API scheme used to talk to this provider
+	pub fn api_scheme(&self) -> ApiScheme {
+		match self {
+			Provider::OpenAI { .. } => ApiScheme::OpenAI,
+			Provider::Anthropic { .. } => ApiScheme::Anthropic,
+			Provider::Groq { .. } => ApiScheme::Groq,
+			Provider::Mistral { .. } => ApiScheme::Mistral,
+			Provider::Ollama { .. } => ApiScheme::Ollama,
+			Provider::Google { .. } => ApiScheme::Google,
+			Provider::Custom { api_scheme, .. } => api_scheme.clone(),
+		}
+	}
+
 	/// Send a message to the LLM provider and get a response
-	pub async fn send_message(&self, messages: &MessageHistory, model: &str, config: &LLMConfig) -> Result<String> {
+	pub async fn send_message(&self, messages: &MessageHistory, model: &str, config: &LLMConfig) -> ProviderResult<String> {
+		self.complete(messages, model, config).await.map(|answer| answer.text)
+	}
+
+	/// Send a message to the LLM provider and get the response together with its token usage
+	pub async fn complete(&self, messages: &MessageHistory, model: &str, config: &LLMConfig) -> ProviderResult<Answer> {
+		if let Some(client) = self.compatible_client(model)? {
+			return client.complete(messages, config, system_prompt(messages, config)).await;
+		}
+
 		// Build the LLM client based on provider type
-		let llm = self.build_llm(model, config)?;
+		let llm = self.build_llm(model, config, system_prompt(messages, config))?;
+		let chat_messages = to_chat_messages(messages, &self.api_scheme());
+
+		// Send the chat request
+		let response = llm.chat(&chat_messages).await.map_err(|e| classify_error(self.provider_name(), &e))?;
+		self.to_answer(response.as_ref())
+	}
+
+	/// Send a message to the LLM provider and stream the response as text deltas, followed by the usage if reported.
+	/// Backends without usage in their stream only yield text, backends without streaming support yield the whole answer at once.
+	pub async fn stream_message(&self, messages: &MessageHistory, model: &str, config: &LLMConfig) -> ProviderResult<AnswerStream> {
+		if let Some(client) = self.compatible_client(model)? {
+			return client.stream(messages, config, system_prompt(messages, config)).await;
+		}
+
+		let llm = self.build_llm(model, config, system_prompt(messages, config))?;
+		let chat_messages = to_chat_messages(messages, &self.api_scheme());
+		let provider_name = self.provider_name().to_string();
+
+		match self.api_scheme().stream_support() {
+			StreamSupport::WithUsage => {
+				let responses = llm.chat_stream_struct(&chat_messages).await.map_err(|e| classify_error(&provider_name, &e))?;
+				return Ok(Box::pin(
+					responses.flat_map(move |response| stream::iter(to_stream_chunks(response, &provider_name))),
+				));
+			}
+			StreamSupport::TextOnly => {
+				let deltas = llm.chat_stream(&chat_messages).await.map_err(|e| classify_error(&provider_name, &e))?;
+				return Ok(Box::pin(
+					deltas.map(move |delta| delta.map(StreamChunk::Text).map_err(|e| classify_error(&provider_name, &e))),
+				));
+			}
+			StreamSupport::None => log::debug!("Streaming not supported by {}, falling back to a single response", provider_name),
+		}
 
-		// Convert MessageHistory to ChatMessage format
-		let chat_messages: Vec<ChatMessage> = messages
+		let response = llm.chat(&chat_messages).await.map_err(|e| classify_error(&provider_name, &e))?;
+		let answer = self.to_answer(response.as_ref())?;
+		let mut chunks = vec![Ok(StreamChunk::Text(answer.text))];
+		if let Some(usage) = answer.usage {
+			chunks.push(Ok(StreamChunk::Usage(usage)));
+		}
+		Ok(Box::pin(stream::iter(chunks)))
+	}
+
+	/// Whether the provider's API supports tool calling
+	pub fn supports_tools(&self) -> bool {
+		matches!(self.api_scheme(), ApiScheme::OpenAI | ApiScheme::Anthropic | ApiScheme::Azure)
+	}
+
+	/// Send the history and the tool calls made so far, offering the tools again.
+	/// The answer either contains the final text or further tool calls.
+	pub async fn complete_with_tools(
+		&self,
+		messages: &MessageHistory,
+		rounds: &[ToolRound],
+		tools: &[ToolSpec],
+		model: &str,
+		config: &LLMConfig,
+	) -> ProviderResult<ToolAnswer> {
+		if let Some(client) = self.compatible_client(model)? {
+			return client
+				.complete_with_tools(messages, rounds, tools, config, system_prompt(messages, config))
+				.await;
+		}
+
+		let llm = self.build_llm(model, config, system_prompt(messages, config))?;
+		let mut chat_messages = to_chat_messages(messages, &self.api_scheme());
+		for round in rounds {
+			let calls = round.calls.iter().map(|call| to_llm_tool_call(&call.id, &call.name, &call.arguments)).collect();
+			let outputs = round
+				.outputs
+				.iter()
+				.map(|output| to_llm_tool_call(&output.call_id, &output.name, &output.model_content()))
+				.collect();
+			chat_messages.push(ChatMessage::assistant().tool_use(calls).build());
+			chat_messages.push(ChatMessage::user().tool_result(outputs).build());
+		}
+		let tools: Vec<Tool> = tools
 			.iter()
-			.map(|msg| match msg.role.as_str() {
-				"user" => ChatMessage::user().content(&msg.content).build(),
-				"assistant" => ChatMessage::assistant().content(&msg.content).build(),
-				// For system messages, we'll use user with a prefix since system() may not exist
-				"system" => ChatMessage::user().content(&format!("[System]: {}", &msg.content)).build(),
-				_ => ChatMessage::user().content(&msg.content).build(),
+			.map(|tool| Tool {
+				tool_type: "function".to_string(),
+				function: FunctionTool {
+					name: tool.name.clone(),
+					description: tool.description.clone(),
+					parameters: tool.parameters.clone(),
+				},
 			})
 			.collect();
 
-		// Send the chat request
-		let response = llm.chat(&chat_messages).await.map_err(|e| anyhow!("{}", e))?;
+		let response = llm
+			.chat_with_tools(&chat_messages, Some(&tools))
+			.await
+			.map_err(|e| classify_error(self.provider_name(), &e))?;
+		let tool_calls: Vec<ToolCall> = response
+			.tool_calls()
+			.unwrap_or_default()
+			.into_iter()
+			.map(|call| ToolCall {
+				id: call.id,
+				name: call.function.name,
+				arguments: call.function.arguments,
+			})
+			.collect();
+		let text = response.text().unwrap_or_default();
+		if text.is_empty() && tool_calls.is_empty() {
+			return Err(self.empty_response());
+		}
+		Ok(ToolAnswer {
+			text,
+			tool_calls,
+			usage: response.usage().map(TokenUsage::from),
+		})
+	}
 
-		// Extract the text from the response
-		response.text().map(|s| s.to_string()).ok_or_else(|| anyhow!("No response text from LLM"))
+	/// Extract the text and usage from a chat response
+	fn to_answer(&self, response: &dyn ChatResponse) -> ProviderResult<Answer> {
+		let text = response.text().map(|s| s.to_string()).ok_or_else(|| self.empty_response())?;
+		Ok(Answer {
+			text,
+			usage: response.usage().map(TokenUsage::from),
+		})
+	}
+
+	/// Error for a response that contains no text
+	fn empty_response(&self) -> ProviderError {
+		ProviderError::MessageFailed {
+			provider: self.provider_name().to_string(),
+			details: "No response text from LLM".to_string(),
+			status: None,
+		}
 	}
 
 	/// Build the LLM client with the appropriate backend and configuration
-	fn build_llm(&self, model: &str, config: &LLMConfig) -> Result<Box<dyn LLMProvider>> {
+	fn build_llm(&self, model: &str, config: &LLMConfig, system_prompt: Option<String>) -> ProviderResult<Box<dyn LLMProvider>> {
 		let mut builder = LLMBuilder::new();
 
 		// Configure backend and API key
@@ -144,7 +437,22 @@ impl Provider {
 					b
 				}
 			}
-			Provider::Custom { api_key, base_url, api_scheme } => builder.backend(api_scheme.to_backend()).api_key(api_key).base_url(base_url),
+			Provider::Google { api_key } => builder.backend(LLMBackend::Google).api_key(api_key),
+			// Azure addresses the model through its deployment and requires an API version
+			Provider::Custom {
+				api_key,
+				base_url,
+				api_scheme: ApiScheme::Azure,
+				options,
+			} => builder
+				.backend(LLMBackend::AzureOpenAI)
+				.api_key(api_key)
+				.base_url(base_url.trim_end_matches('/'))
+				.api_version(options.query.get("api-version").map_or(AZURE_API_VERSION, String::as_str))
+				.deployment_id(model),
+			Provider::Custom {
+				api_key, base_url, api_scheme, ..
+			} => builder.backend(api_scheme.to_backend()).api_key(api_key).base_url(base_url),
 		};
 
 		// Apply common configuration
@@ -154,6 +462,153 @@ impl Provider {
 			builder = builder.top_p(top_p);
 		}
 
-		builder.build().map_err(|e| anyhow!("{}", e))
+		if let Some(system_prompt) = system_prompt {
+			builder = builder.system(system_prompt);
+		}
+
+		// Stop sequences, seed and penalties are passed as raw request fields
+		if let Some(extra_body) = config.extra_body(&self.api_scheme()) {
+			builder = builder.extra_body(extra_body);
+		}
+
+		builder.build().map_err(|e| ProviderError::Configuration {
+			provider: self.provider_name().to_string(),
+			details: e.to_string(),
+		})
+	}
+}
+
+/// Split a structured stream response into its text delta and the usage, if it contains any
+fn to_stream_chunks(response: Result<StreamResponse, LLMError>, provider_name: &str) -> Vec<ProviderResult<StreamChunk>> {
+	match response {
+		Ok(response) => {
+			let text: String = response.choices.into_iter().filter_map(|choice| choice.delta.content).collect();
+			let mut chunks = Vec::new();
+			if !text.is_empty() {
+				chunks.push(Ok(StreamChunk::Text(text)));
+			}
+			if let Some(usage) = response.usage {
+				chunks.push(Ok(StreamChunk::Usage(usage.into())));
+			}
+			chunks
+		}
+		Err(e) => vec![Err(classify_error(provider_name, &e))],
+	}
+}
+
+/// Combine the configured system prompt with the system messages of the history
+pub(super) fn system_prompt(messages: &MessageHistory, config: &LLMConfig) -> Option<String> {
+	let prompts: Vec<&str> = config
+		.system_prompt
+		.as_deref()
+		.into_iter()
+		.chain(messages.iter().filter(|msg| msg.role == "system").map(|msg| msg.content.as_str()))
+		.filter(|prompt| !prompt.trim().is_empty())
+		.collect();
+	if prompts.is_empty() {
+		None
+	} else {
+		Some(prompts.join("\n\n"))
+	}
+}
+
+/// Convert MessageHistory to ChatMessage format.
+/// System messages are skipped, they are sent through the builder's system field.
+/// A message holds a single part, so attached images are sent as messages of their own in front of the text.
+/// OpenAI-compatible backends only accept images by URL, so they get data URLs instead of raw bytes.
+fn to_chat_messages(messages: &MessageHistory, api_scheme: &ApiScheme) -> Vec<ChatMessage> {
+	let mut chat_messages = Vec::new();
+	for msg in messages.iter().filter(|msg| msg.role != "system") {
+		match msg.role.as_str() {
+			"assistant" => chat_messages.push(ChatMessage::assistant().content(&msg.content).build()),
+			_ => {
+				for attachment in msg.attachments.iter().filter(|attachment| attachment.is_image()) {
+					if let (Some(mime), Some(contents)) = (image_mime(&attachment.mime_type), &attachment.data) {
+						let image = if api_scheme.is_openai_compatible() {
+							ChatMessage::user().image_url(format!("data:{};base64,{}", mime.mime_type(), BASE64.encode(contents)))
+						} else {
+							ChatMessage::user().image(mime, contents.clone())
+						};
+						chat_messages.push(image.build());
+					}
+				}
+				chat_messages.push(ChatMessage::user().content(msg.prompt_content()).build());
+			}
+		}
+	}
+	chat_messages
+}
+
+/// Tool call in the llm crate's format, which also carries tool outputs in place of the arguments
+fn to_llm_tool_call(id: &str, name: &str, arguments: &str) -> llm::ToolCall {
+	llm::ToolCall {
+		id: id.to_string(),
+		call_type: "function".to_string(),
+		function: FunctionCall {
+			name: name.to_string(),
+			arguments: arguments.to_string(),
+		},
+	}
+}
+
+/// Image format of a mime type, `None` for formats the providers do not accept
+fn image_mime(mime_type: &str) -> Option<ImageMime> {
+	match mime_type {
+		"image/png" => Some(ImageMime::PNG),
+		"image/jpeg" => Some(ImageMime::JPEG),
+		"image/gif" => Some(ImageMime::GIF),
+		"image/webp" => Some(ImageMime::WEBP),
+		_ => None,
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::types::{Attachment, Message};
+	use llm::providers::openai_compatible::chat_message_to_openai_message;
+
+	fn prompt_with_image() -> MessageHistory {
+		MessageHistory(vec![Message {
+			id: "1".to_string(),
+			role: "user".to_string(),
+			content: "What is in this picture?".to_string(),
+			model_name: "gpt-4o".to_string(),
+			parent_id: None,
+			provider_name: Some("openai".to_string()),
+			status: "ok".to_string(),
+			latency_ms: None,
+			prompt_tokens: None,
+			completion_tokens: None,
+			blocks: None,
+			attachments: vec![Attachment {
+				id: "a".to_string(),
+				kind: "image".to_string(),
+				file_name: "pixel.png".to_string(),
+				mime_type: "image/png".to_string(),
+				size_bytes: 3,
+				path: "attachments/a.png".to_string(),
+				content: None,
+				data: Some(vec![1, 2, 3]),
+			}],
+		}])
+	}
+
+	#[test]
+	fn openai_requests_carry_images_as_data_urls() {
+		let messages: Vec<serde_json::Value> = to_chat_messages(&prompt_with_image(), &ApiScheme::OpenAI)
+			.into_iter()
+			.map(|message| serde_json::to_value(chat_message_to_openai_message(message)).unwrap())
+			.collect();
+		assert_eq!(messages.len(), 2);
+		assert_eq!(messages[0]["content"][0]["type"], "image_url");
+		assert_eq!(messages[0]["content"][0]["image_url"]["url"], "data:image/png;base64,AQID");
+		assert_eq!(messages[1]["content"], "What is in this picture?");
+	}
+
+	#[test]
+	fn other_schemes_send_image_bytes() {
+		let messages = to_chat_messages(&prompt_with_image(), &ApiScheme::Anthropic);
+		assert!(matches!(messages[0].message_type, llm::chat::MessageType::Image((ImageMime::PNG, ref bytes)) if bytes == &[1, 2, 3]));
 	}
 }
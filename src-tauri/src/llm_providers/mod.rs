@@ -1,5 +1,13 @@
+mod catalog;
+mod compatible;
+mod embeddings;
 mod enums;
+mod retry;
+mod tokens;
 mod types;
 
-pub use enums::Provider;
-pub use types::LLMConfig;
+pub use catalog::{list_models, list_ollama_models, OLLAMA_DEFAULT_CONTEXT, OLLAMA_DEFAULT_URL};
+pub use embeddings::embed;
+pub use enums::{ApiScheme, Provider};
+pub use retry::{with_retries, RetryAttempt, MAX_ATTEMPTS};
+pub use types::{Answer, LLMConfig, RequestOptions, StreamChunk, TokenUsage, ToolAnswer, ToolCall, ToolOutput, ToolRound, ToolSpec};
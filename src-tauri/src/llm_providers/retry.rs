@@ -0,0 +1,183 @@
+//! Classification of provider failures and automatic retries with exponential backoff
+
+use std::future::Future;
+use std::time::Duration;
+
+use lazy_static::lazy_static;
+use llm::error::LLMError;
+use regex::Regex;
+
+use crate::errors::{ProviderError, ProviderResult};
+
+/// Number of attempts, including the first request
+pub const MAX_ATTEMPTS: u32 = 4;
+
+/// Delay before the first retry, doubled for every following one
+const BASE_DELAY: Duration = Duration::from_secs(1);
+
+/// Upper bound for a single delay, also caps the provider's Retry-After
+const MAX_DELAY: Duration = Duration::from_secs(60);
+
+lazy_static! {
+	/// HTTP status as the llm crate reports it, "API returned error status: 429 Too Many Requests",
+	/// or as reqwest does for its backends, "HTTP status server error (529 <unknown status code>) for url"
+	static ref STATUS_REGEX: Regex = Regex::new(r"(?:returned error status: |HTTP status (?:client|server) error \()([1-5]\d{2})\b").unwrap();
+	/// "Retry-After: 20" or "retry after 20 seconds"
+	static ref RETRY_AFTER_REGEX: Regex = Regex::new(r"(?i)retry[- ]after\D{0,3}(\d+(?:\.\d+)?)").unwrap();
+	/// OpenAI style hint in the error body: "Please try again in 1.5s" or "in 350ms"
+	static ref TRY_AGAIN_REGEX: Regex = Regex::new(r"(?i)try again in (\d+(?:\.\d+)?)\s*(ms|s)\b").unwrap();
+	/// Google's RetryInfo in the error body: "retryDelay": "23s"
+	static ref RETRY_DELAY_REGEX: Regex = Regex::new(r#""retryDelay":\s*"(\d+(?:\.\d+)?)s""#).unwrap();
+}
+
+/// A failed attempt that is about to be retried
+pub struct RetryAttempt {
+	/// Number of the attempt that failed, starting at 1
+	pub attempt: u32,
+	/// Time until the next attempt
+	pub delay: Duration,
+	pub error: ProviderError,
+}
+
+/// Map an error of the llm crate to a provider error, using the HTTP status and body it contains
+pub fn classify_error(provider: &str, error: &LLMError) -> ProviderError {
+	let details = error.to_string();
+	let lowercase = details.to_lowercase();
+	// The body the provider sent is not searched for a status, it may quote any number
+	let status_message = match error {
+		LLMError::ResponseFormatError { message, .. } => message,
+		_ => &details,
+	};
+	let status = STATUS_REGEX.captures(status_message).and_then(|caps| caps[1].parse::<u16>().ok());
+
+	let auth_failed = matches!(error, LLMError::AuthError(_))
+		|| matches!(status, Some(401) | Some(403))
+		|| lowercase.contains("invalid api key")
+		|| lowercase.contains("invalid x-api-key")
+		|| lowercase.contains("incorrect api key");
+	if auth_failed {
+		return ProviderError::InvalidApiKey {
+			provider: provider.to_string(),
+			reason: details,
+		};
+	}
+
+	if status == Some(429) || lowercase.contains("rate limit") || lowercase.contains("too many requests") {
+		return ProviderError::RateLimited {
+			provider: provider.to_string(),
+			retry_after: parse_retry_after(&details),
+		};
+	}
+
+	// Anthropic reports overload without a usable status in some error paths
+	let status = match status {
+		None if lowercase.contains("overloaded") => Some(529),
+		status => status,
+	};
+	ProviderError::MessageFailed {
+		provider: provider.to_string(),
+		details,
+		status,
+	}
+}
+
+/// Extract the delay a provider asked for from an error message.
+/// The llm crate does not pass on response headers, so for its requests only hints in the error body are found.
+pub fn parse_retry_after(details: &str) -> Option<Duration> {
+	let seconds = if let Some(caps) = RETRY_AFTER_REGEX.captures(details).or_else(|| RETRY_DELAY_REGEX.captures(details)) {
+		caps[1].parse::<f64>().ok()?
+	} else {
+		let caps = TRY_AGAIN_REGEX.captures(details)?;
+		let value = caps[1].parse::<f64>().ok()?;
+		match &caps[2] {
+			"ms" => value / 1000.0,
+			_ => value,
+		}
+	};
+	Duration::try_from_secs_f64(seconds.min(MAX_DELAY.as_secs_f64())).ok()
+}
+
+/// Run a request, retrying rate limits and server errors with exponential backoff.
+/// `on_retry` is called before waiting for the next attempt.
+pub async fn with_retries<T, F, Fut>(mut request: F, mut on_retry: impl FnMut(&RetryAttempt)) -> ProviderResult<T>
+where
+	F: FnMut() -> Fut,
+	Fut: Future<Output = ProviderResult<T>>,
+{
+	let mut attempt = 1;
+	loop {
+		match request().await {
+			Ok(value) => return Ok(value),
+			Err(error) if error.is_retryable() && attempt < MAX_ATTEMPTS => {
+				let backoff = BASE_DELAY * 2u32.pow(attempt - 1);
+				let delay = error.retry_after().unwrap_or(backoff).min(MAX_DELAY);
+				log::warn!("Attempt {} of {} failed, retrying in {:?}: {}", attempt, MAX_ATTEMPTS, delay, error);
+
+				on_retry(&RetryAttempt { attempt, delay, error });
+				tokio::time::sleep(delay).await;
+				attempt += 1;
+			}
+			Err(error) => return Err(error),
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn status(error: LLMError) -> Option<u16> {
+		match classify_error("openai", &error) {
+			ProviderError::MessageFailed { status, .. } => status,
+			ProviderError::RateLimited { .. } => Some(429),
+			ProviderError::InvalidApiKey { .. } => Some(401),
+			_ => None,
+		}
+	}
+
+	#[test]
+	fn finds_status_of_llm_errors() {
+		let error = LLMError::ResponseFormatError {
+			message: "OpenAI API returned error status: 503 Service Unavailable".to_string(),
+			raw_response: "{}".to_string(),
+		};
+		assert_eq!(status(error), Some(503));
+		let error = LLMError::HttpError("HTTP status server error (529 <unknown status code>) for url (https://api.anthropic.com/v1/messages)".to_string());
+		assert_eq!(status(error), Some(529));
+		let error = LLMError::ResponseFormatError {
+			message: "OpenAI API returned error status: 429 Too Many Requests".to_string(),
+			raw_response: "Rate limit reached, please try again in 1.5s".to_string(),
+		};
+		assert!(matches!(
+			classify_error("openai", &error),
+			ProviderError::RateLimited { retry_after: Some(delay), .. } if delay == Duration::from_millis(1500)
+		));
+	}
+
+	#[test]
+	fn ignores_numbers_in_messages() {
+		let error = LLMError::ResponseFormatError {
+			message: "OpenAI API returned error status: 400 Bad Request".to_string(),
+			raw_response: "Invalid value (500 tokens at most)".to_string(),
+		};
+		assert_eq!(status(error), Some(400));
+		assert_eq!(status(LLMError::ProviderError("The answer was cut off (503 words)".to_string())), None);
+	}
+
+	#[test]
+	fn parses_retry_hints() {
+		assert_eq!(parse_retry_after("Retry-After: 20"), Some(Duration::from_secs(20)));
+		assert_eq!(parse_retry_after("please try again in 350ms"), Some(Duration::from_millis(350)));
+		assert_eq!(
+			parse_retry_after(r#"{"@type": "type.googleapis.com/google.rpc.RetryInfo", "retryDelay": "23s"}"#),
+			Some(Duration::from_secs(23))
+		);
+		assert_eq!(parse_retry_after("no hint"), None);
+	}
+
+	#[test]
+	fn caps_retry_hints() {
+		assert_eq!(parse_retry_after("retry after 99999999999999999999999"), Some(MAX_DELAY));
+		assert_eq!(parse_retry_after("try again in 3600s"), Some(MAX_DELAY));
+	}
+}
@@ -0,0 +1,44 @@
+//! Token estimation per API scheme, used to fit chat histories into context windows.
+//! Estimates are based on the average characters per token of each provider's tokenizer.
+
+use crate::types::Message;
+
+use super::enums::ApiScheme;
+
+/// Tokens an attached image takes up, providers bill images by size and this covers a large one
+const IMAGE_TOKENS: usize = 1600;
+
+impl ApiScheme {
+	/// Average number of characters per token for mostly English text
+	fn chars_per_token(&self) -> f32 {
+		match self {
+			ApiScheme::OpenAI | ApiScheme::Azure | ApiScheme::Google => 4.0,
+			ApiScheme::Anthropic => 3.5,
+			ApiScheme::Mistral => 3.6,
+			ApiScheme::Groq | ApiScheme::Ollama => 3.8,
+		}
+	}
+
+	/// Tokens the chat format adds around every message (role markers and separators)
+	fn tokens_per_message(&self) -> usize {
+		match self {
+			ApiScheme::OpenAI | ApiScheme::Azure | ApiScheme::Groq | ApiScheme::Google => 4,
+			ApiScheme::Anthropic => 5,
+			ApiScheme::Mistral | ApiScheme::Ollama => 6,
+		}
+	}
+
+	/// Estimate the number of tokens of a text
+	pub fn estimate_tokens(&self, text: &str) -> usize {
+		// Non-ASCII characters (accents, CJK, emoji) are counted as a full token each
+		let ascii = text.chars().filter(|c| c.is_ascii()).count();
+		let other = text.chars().count() - ascii;
+		(ascii as f32 / self.chars_per_token()).ceil() as usize + other
+	}
+
+	/// Estimate the number of tokens a message takes up in the prompt, including its attachments
+	pub fn estimate_message_tokens(&self, message: &Message) -> usize {
+		let images = message.attachments.iter().filter(|attachment| attachment.is_image()).count();
+		self.tokens_per_message() + self.estimate_tokens(&message.prompt_content()) + images * IMAGE_TOKENS
+	}
+}
@@ -1,10 +1,126 @@
+use std::collections::BTreeMap;
+
+use llm::chat::Usage;
 use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+use crate::types::{ChatParameters, Model};
+
+use super::enums::ApiScheme;
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct LLMConfig {
 	pub temperature: f32,
 	pub max_tokens: u32,
 	pub top_p: Option<f32>,
+	pub stop_sequences: Option<Vec<String>>,
+	pub seed: Option<u32>,
+	pub frequency_penalty: Option<f32>,
+	pub presence_penalty: Option<f32>,
+	/// Sent through the backend's native system field
+	pub system_prompt: Option<String>,
+}
+
+/// Extra headers and query parameters sent with every request to a provider
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RequestOptions {
+	pub headers: BTreeMap<String, String>,
+	pub query: BTreeMap<String, String>,
+}
+
+impl RequestOptions {
+	pub fn is_empty(&self) -> bool {
+		self.headers.is_empty() && self.query.is_empty()
+	}
+}
+
+/// Token counts reported by a provider for a single request
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TokenUsage {
+	pub prompt_tokens: u32,
+	pub completion_tokens: u32,
+}
+
+impl From<Usage> for TokenUsage {
+	fn from(usage: Usage) -> Self {
+		Self {
+			prompt_tokens: usage.prompt_tokens,
+			completion_tokens: usage.completion_tokens,
+		}
+	}
+}
+
+/// Complete answer of a provider
+#[derive(Debug, Clone)]
+pub struct Answer {
+	pub text: String,
+	/// `None` if the provider did not report its usage
+	pub usage: Option<TokenUsage>,
+}
+
+/// Item of a streamed answer
+#[derive(Debug, Clone)]
+pub enum StreamChunk {
+	/// Text received since the previous chunk
+	Text(String),
+	/// Usage of the whole request, usually sent after the last text chunk
+	Usage(TokenUsage),
+}
+
+/// A tool offered to the model
+#[derive(Debug, Clone)]
+pub struct ToolSpec {
+	pub name: String,
+	pub description: String,
+	/// JSON schema of the arguments
+	pub parameters: Value,
+}
+
+/// A tool call requested by the model
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ToolCall {
+	/// Id assigned by the provider, the output is sent back under it
+	pub id: String,
+	pub name: String,
+	/// Arguments as JSON object
+	pub arguments: String,
+}
+
+/// Result of running a tool call
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ToolOutput {
+	pub call_id: String,
+	pub name: String,
+	/// Output of the tool, or the error message if it failed
+	pub content: String,
+	pub is_error: bool,
+}
+
+impl ToolOutput {
+	/// Text the model receives, errors are marked so the model does not mistake them for results
+	pub fn model_content(&self) -> String {
+		if self.is_error {
+			format!("Error: {}", self.content)
+		} else {
+			self.content.clone()
+		}
+	}
+}
+
+/// Tool calls of one answer together with their outputs, sent back to the model after the history
+#[derive(Debug, Clone)]
+pub struct ToolRound {
+	pub calls: Vec<ToolCall>,
+	pub outputs: Vec<ToolOutput>,
+}
+
+/// Answer of a request that offered tools, either text or tool calls to run before asking again
+#[derive(Debug, Clone)]
+pub struct ToolAnswer {
+	pub text: String,
+	pub tool_calls: Vec<ToolCall>,
+	/// `None` if the provider did not report its usage
+	pub usage: Option<TokenUsage>,
 }
 
 impl Default for LLMConfig {
@@ -13,6 +129,74 @@ impl Default for LLMConfig {
 			temperature: 0.7,
 			max_tokens: 4096,
 			top_p: None,
+			stop_sequences: None,
+			seed: None,
+			frequency_penalty: None,
+			presence_penalty: None,
+			system_prompt: None,
+		}
+	}
+}
+
+impl LLMConfig {
+	/// Default configuration for a model, taking the output limit from its database row
+	pub fn for_model(model: &Model) -> Self {
+		Self {
+			max_tokens: model.max_tokens,
+			..Self::default()
+		}
+	}
+
+	/// Apply per-chat overrides on top of this configuration
+	pub fn with_overrides(mut self, parameters: &ChatParameters) -> Self {
+		if let Some(temperature) = parameters.temperature {
+			self.temperature = temperature;
+		}
+		if let Some(max_tokens) = parameters.max_tokens {
+			self.max_tokens = max_tokens;
+		}
+		if parameters.top_p.is_some() {
+			self.top_p = parameters.top_p;
+		}
+		if parameters.stop_sequences.is_some() {
+			self.stop_sequences = parameters.stop_sequences.clone();
+		}
+		if parameters.seed.is_some() {
+			self.seed = parameters.seed;
+		}
+		if parameters.frequency_penalty.is_some() {
+			self.frequency_penalty = parameters.frequency_penalty;
+		}
+		if parameters.presence_penalty.is_some() {
+			self.presence_penalty = parameters.presence_penalty;
+		}
+		self
+	}
+
+	/// Request body fields for the parameters `LLMBuilder` has no setter for.
+	/// Only OpenAI-compatible APIs receive them, the llm crate's other backends never send extra fields.
+	pub fn extra_body(&self, scheme: &ApiScheme) -> Option<Value> {
+		if !scheme.is_openai_compatible() {
+			return None;
+		}
+		let mut body = Map::new();
+		if let Some(stop_sequences) = &self.stop_sequences {
+			body.insert("stop".to_string(), stop_sequences.clone().into());
+		}
+		if let Some(seed) = self.seed {
+			let key = if matches!(scheme, ApiScheme::Mistral) { "random_seed" } else { "seed" };
+			body.insert(key.to_string(), seed.into());
+		}
+		if let Some(frequency_penalty) = self.frequency_penalty {
+			body.insert("frequency_penalty".to_string(), frequency_penalty.into());
+		}
+		if let Some(presence_penalty) = self.presence_penalty {
+			body.insert("presence_penalty".to_string(), presence_penalty.into());
+		}
+		if body.is_empty() {
+			None
+		} else {
+			Some(Value::Object(body))
 		}
 	}
 }
@@ -0,0 +1,311 @@
+//! Connection to an MCP server process.
+//! JSON-RPC messages are exchanged as single lines of JSON over the stdin and stdout of the process.
+
+use std::collections::HashMap;
+use std::process::Stdio;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use serde_json::{json, Value};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::process::{Child, ChildStderr, ChildStdin, ChildStdout, Command};
+use tokio::sync::{oneshot, Mutex};
+
+use super::{METHOD_NOT_FOUND, PROTOCOL_VERSION};
+use crate::types::{McpCapabilities, McpPrompt, McpPromptArgument, McpResource, McpServer, McpTool};
+
+/// Time a server gets to answer a request, tool calls may take a while
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// Requests waiting for their response, by request id
+type PendingRequests = Arc<Mutex<HashMap<u64, oneshot::Sender<Result<Value, String>>>>>;
+
+/// A tool as listed by the server, with the JSON schema of its arguments
+pub struct McpToolDefinition {
+	pub name: String,
+	pub description: Option<String>,
+	pub input_schema: Value,
+}
+
+/// A running MCP server. Dropping the client kills the process.
+pub struct McpClient {
+	pub server: McpServer,
+	stdin: Arc<Mutex<ChildStdin>>,
+	pending: PendingRequests,
+	next_id: AtomicU64,
+	/// Set once the server closed its stdout, e.g. because it exited
+	closed: Arc<AtomicBool>,
+	/// Capabilities the server announced during initialization
+	capabilities: Value,
+	_process: Mutex<Child>,
+}
+
+impl McpClient {
+	/// Start the server process and initialize the session
+	pub async fn start(server: &McpServer) -> Result<Self, String> {
+		let mut process = Command::new(&server.command)
+			.args(&server.args)
+			.envs(&server.env)
+			.stdin(Stdio::piped())
+			.stdout(Stdio::piped())
+			.stderr(Stdio::piped())
+			.kill_on_drop(true)
+			.spawn()
+			.map_err(|e| format!("Could not start MCP server {}: {}", server.name, e))?;
+		let missing_pipe = || format!("Could not connect to MCP server {}", server.name);
+		let stdin = Arc::new(Mutex::new(process.stdin.take().ok_or_else(missing_pipe)?));
+		let stdout = process.stdout.take().ok_or_else(missing_pipe)?;
+		let stderr = process.stderr.take().ok_or_else(missing_pipe)?;
+
+		let pending = PendingRequests::default();
+		let closed = Arc::new(AtomicBool::new(false));
+		tauri::async_runtime::spawn(read_messages(server.name.clone(), stdout, stdin.clone(), pending.clone(), closed.clone()));
+		tauri::async_runtime::spawn(log_stderr(server.name.clone(), stderr));
+
+		let mut client = Self {
+			server: server.clone(),
+			stdin,
+			pending,
+			next_id: AtomicU64::new(1),
+			closed,
+			capabilities: Value::Null,
+			_process: Mutex::new(process),
+		};
+		let params = json!({
+			"protocolVersion": PROTOCOL_VERSION,
+			"capabilities": {},
+			"clientInfo": { "name": "byok", "version": env!("CARGO_PKG_VERSION") }
+		});
+		let result = client.request("initialize", params).await?;
+		client.capabilities = result["capabilities"].clone();
+		write_message(&client.stdin, &json!({ "jsonrpc": "2.0", "method": "notifications/initialized" })).await?;
+		log::info!(
+			"Connected to MCP server {} ({})",
+			server.name,
+			result["serverInfo"]["name"].as_str().unwrap_or("unknown")
+		);
+		Ok(client)
+	}
+
+	/// Whether the server process is still connected
+	pub fn is_running(&self) -> bool {
+		!self.closed.load(Ordering::Relaxed)
+	}
+
+	/// Send a request and wait for its result
+	async fn request(&self, method: &str, params: Value) -> Result<Value, String> {
+		let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+		let (response_tx, response_rx) = oneshot::channel();
+		self.pending.lock().await.insert(id, response_tx);
+		let request = json!({ "jsonrpc": "2.0", "id": id, "method": method, "params": params });
+		if let Err(e) = write_message(&self.stdin, &request).await {
+			self.pending.lock().await.remove(&id);
+			return Err(e);
+		}
+		match tokio::time::timeout(REQUEST_TIMEOUT, response_rx).await {
+			Ok(Ok(result)) => result.map_err(|e| format!("MCP server {}: {}", self.server.name, e)),
+			Ok(Err(_)) => Err(format!("MCP server {} exited", self.server.name)),
+			Err(_) => {
+				self.pending.lock().await.remove(&id);
+				Err(format!("MCP server {} did not answer {} in time", self.server.name, method))
+			}
+		}
+	}
+
+	/// Items of a list request, following the pagination cursors.
+	/// Empty if the server did not announce the capability.
+	async fn list(&self, capability: &str, method: &str, key: &str) -> Result<Vec<Value>, String> {
+		if self.capabilities.get(capability).is_none() {
+			return Ok(Vec::new());
+		}
+		let mut items = Vec::new();
+		let mut cursor: Option<String> = None;
+		loop {
+			let params = match &cursor {
+				Some(cursor) => json!({ "cursor": cursor }),
+				None => json!({}),
+			};
+			let result = self.request(method, params).await?;
+			if let Some(page) = result[key].as_array() {
+				items.extend(page.iter().cloned());
+			}
+			cursor = result["nextCursor"].as_str().map(|cursor| cursor.to_string());
+			if cursor.is_none() {
+				return Ok(items);
+			}
+		}
+	}
+
+	pub async fn tools(&self) -> Result<Vec<McpToolDefinition>, String> {
+		let tools = self.list("tools", "tools/list", "tools").await?;
+		Ok(tools
+			.into_iter()
+			.filter_map(|tool| {
+				Some(McpToolDefinition {
+					name: tool["name"].as_str()?.to_string(),
+					description: optional_string(&tool["description"]),
+					input_schema: match &tool["inputSchema"] {
+						Value::Object(schema) => Value::Object(schema.clone()),
+						_ => json!({ "type": "object", "properties": {} }),
+					},
+				})
+			})
+			.collect())
+	}
+
+	/// Tools, resources and prompts of the server
+	pub async fn capabilities(&self) -> Result<McpCapabilities, String> {
+		let tools = self
+			.tools()
+			.await?
+			.into_iter()
+			.map(|tool| McpTool {
+				name: tool.name,
+				description: tool.description,
+			})
+			.collect();
+		let resources = self
+			.list("resources", "resources/list", "resources")
+			.await?
+			.into_iter()
+			.filter_map(|resource| {
+				Some(McpResource {
+					uri: resource["uri"].as_str()?.to_string(),
+					name: resource["name"].as_str().unwrap_or_default().to_string(),
+					description: optional_string(&resource["description"]),
+					mime_type: optional_string(&resource["mimeType"]),
+				})
+			})
+			.collect();
+		let prompts = self
+			.list("prompts", "prompts/list", "prompts")
+			.await?
+			.into_iter()
+			.filter_map(|prompt| {
+				let arguments = prompt["arguments"]
+					.as_array()
+					.map(|arguments| {
+						arguments
+							.iter()
+							.filter_map(|argument| {
+								Some(McpPromptArgument {
+									name: argument["name"].as_str()?.to_string(),
+									description: optional_string(&argument["description"]),
+									required: argument["required"].as_bool().unwrap_or(false),
+								})
+							})
+							.collect()
+					})
+					.unwrap_or_default();
+				Some(McpPrompt {
+					name: prompt["name"].as_str()?.to_string(),
+					description: optional_string(&prompt["description"]),
+					arguments,
+				})
+			})
+			.collect();
+		Ok(McpCapabilities { tools, resources, prompts })
+	}
+
+	/// Call a tool, the text parts of the result are joined. Results the server marks as error are returned as `Err`.
+	pub async fn call_tool(&self, name: &str, arguments: Value) -> Result<String, String> {
+		let result = self.request("tools/call", json!({ "name": name, "arguments": arguments })).await?;
+		let parts: Vec<String> = result["content"]
+			.as_array()
+			.map(|content| {
+				content
+					.iter()
+					.map(|part| match part["type"].as_str() {
+						Some("text") => part["text"].as_str().unwrap_or_default().to_string(),
+						Some("resource") => match part["resource"]["text"].as_str() {
+							Some(text) => text.to_string(),
+							None => format!("[resource {}]", part["resource"]["uri"].as_str().unwrap_or_default()),
+						},
+						Some("resource_link") => format!("[resource {}]", part["uri"].as_str().unwrap_or_default()),
+						Some(other) => format!("[{} content]", other),
+						None => String::new(),
+					})
+					.collect()
+			})
+			.unwrap_or_default();
+		let mut text = parts.join("\n");
+		// Servers may only return structured content
+		if text.is_empty() {
+			if let Some(structured) = result.get("structuredContent") {
+				text = structured.to_string();
+			}
+		}
+		if result["isError"].as_bool().unwrap_or(false) {
+			return Err(text);
+		}
+		Ok(text)
+	}
+}
+
+fn optional_string(value: &Value) -> Option<String> {
+	value.as_str().map(|value| value.to_string())
+}
+
+/// Write one JSON-RPC message to the server
+async fn write_message(stdin: &Mutex<ChildStdin>, message: &Value) -> Result<(), String> {
+	let mut line = message.to_string();
+	line.push('\n');
+	let mut stdin = stdin.lock().await;
+	stdin
+		.write_all(line.as_bytes())
+		.await
+		.map_err(|e| format!("Could not write to MCP server: {}", e))?;
+	stdin.flush().await.map_err(|e| format!("Could not write to MCP server: {}", e))
+}
+
+/// Route the responses of the server to the waiting requests and answer requests of the server
+async fn read_messages(server_name: String, stdout: ChildStdout, stdin: Arc<Mutex<ChildStdin>>, pending: PendingRequests, closed: Arc<AtomicBool>) {
+	let mut lines = BufReader::new(stdout).lines();
+	while let Ok(Some(line)) = lines.next_line().await {
+		let Ok(message) = serde_json::from_str::<Value>(&line) else {
+			log::debug!("Ignoring output of MCP server {}: {}", server_name, line);
+			continue;
+		};
+		match (message.get("id"), message.get("method").and_then(Value::as_str)) {
+			// Requests of the server, only pings are supported
+			(Some(id), Some(method)) => {
+				let response = if method == "ping" {
+					json!({ "jsonrpc": "2.0", "id": id, "result": {} })
+				} else {
+					json!({ "jsonrpc": "2.0", "id": id, "error": { "code": METHOD_NOT_FOUND, "message": format!("{} is not supported", method) } })
+				};
+				if let Err(e) = write_message(&stdin, &response).await {
+					log::warn!("{}", e);
+				}
+			}
+			(Some(id), None) => {
+				let Some(id) = id.as_u64() else {
+					continue;
+				};
+				let Some(response_tx) = pending.lock().await.remove(&id) else {
+					continue;
+				};
+				let result = match message.get("error") {
+					Some(error) => Err(error["message"].as_str().unwrap_or("Unknown error").to_string()),
+					None => Ok(message["result"].clone()),
+				};
+				let _ = response_tx.send(result);
+			}
+			// Notifications are not used
+			_ => {}
+		}
+	}
+	log::info!("MCP server {} closed the connection", server_name);
+	closed.store(true, Ordering::Relaxed);
+	// Dropping the senders fails the requests that are still waiting
+	pending.lock().await.clear();
+}
+
+/// Servers log to stderr
+async fn log_stderr(server_name: String, stderr: ChildStderr) {
+	let mut lines = BufReader::new(stderr).lines();
+	while let Ok(Some(line)) = lines.next_line().await {
+		log::debug!("MCP server {}: {}", server_name, line);
+	}
+}
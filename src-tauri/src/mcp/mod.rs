@@ -0,0 +1,119 @@
+//! Model Context Protocol support.
+//! As client, configured servers are started over stdio, their tools are offered to models and every call needs the user's approval.
+//! As server (`--mcp`), the chat history is offered to other applications, see `server`.
+
+mod client;
+mod server;
+
+use std::time::Duration;
+
+use serde_json::Value;
+use tauri::{command, Emitter, Manager};
+
+use crate::data::{DataState, McpClients, McpClientsState, ToolApprovals, ToolApprovalsState};
+use crate::db::find_mcp_server;
+use crate::tools::Tool;
+use crate::types::{McpCapabilities, McpServer, ToolApprovalRequest};
+
+pub use client::McpClient;
+pub use server::serve;
+
+/// Protocol revision requested by the client and answered by the server
+const PROTOCOL_VERSION: &str = "2025-06-18";
+
+/// JSON-RPC error code for methods that are not implemented
+const METHOD_NOT_FOUND: i64 = -32601;
+
+/// Time the user has to approve a tool call before it is declined
+const APPROVAL_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// Longest tool name providers accept
+const MAX_TOOL_NAME_LENGTH: usize = 64;
+
+/// Start a server if needed and list its tools, resources and prompts
+#[command]
+#[specta::specta]
+pub async fn list_mcp_capabilities(server_name: String, data: DataState<'_>, clients: McpClientsState<'_>) -> Result<McpCapabilities, String> {
+	let server = find_mcp_server(&server_name, &data.0.lock().await.db_pool)
+		.await?
+		.ok_or_else(|| format!("MCP server {} not found", server_name))?;
+	let client = clients.connect(&server).await?;
+	client.capabilities().await.map_err(|e| {
+		log::error!("Error listing capabilities of MCP server {}: {}", server_name, e);
+		e
+	})
+}
+
+/// Approve or decline a tool call announced with the `toolApproval` event
+#[command]
+#[specta::specta]
+pub async fn answer_tool_approval(request_id: String, approved: bool, approvals: ToolApprovalsState<'_>) -> Result<(), String> {
+	if approvals.answer(&request_id, approved).await {
+		Ok(())
+	} else {
+		Err("The tool call is no longer waiting for approval".to_string())
+	}
+}
+
+/// Name a tool of a server is offered under, unique across servers and limited to the characters providers accept
+fn tool_name(server_name: &str, tool_name: &str) -> String {
+	format!("{}__{}", server_name, tool_name)
+		.chars()
+		.map(|c| if c.is_ascii_alphanumeric() || c == '_' || c == '-' { c } else { '_' })
+		.take(MAX_TOOL_NAME_LENGTH)
+		.collect()
+}
+
+/// Tools of the enabled servers. Servers that cannot be started are skipped.
+pub async fn mcp_tools(servers: &[McpServer], window: &tauri::WebviewWindow) -> Vec<Tool> {
+	let clients = window.state::<McpClients>();
+	let approvals = window.state::<ToolApprovals>().inner().clone();
+	let mut tools = Vec::new();
+	for server in servers.iter().filter(|server| server.enabled) {
+		let definitions = match clients.connect(server).await {
+			Ok(client) => client.tools().await.map(|definitions| (client, definitions)),
+			Err(e) => Err(e),
+		};
+		let (client, definitions) = match definitions {
+			Ok(definitions) => definitions,
+			Err(e) => {
+				log::error!("Could not get the tools of MCP server {}: {}", server.name, e);
+				continue;
+			}
+		};
+		for definition in definitions {
+			let description = format!("{} (MCP server {})", definition.description.as_deref().unwrap_or(&definition.name), server.name);
+			let client = client.clone();
+			let approvals = approvals.clone();
+			let window = window.clone();
+			let name = definition.name.clone();
+			tools.push(Tool::new(
+				&tool_name(&server.name, &definition.name),
+				&description,
+				definition.input_schema,
+				move |arguments: Value| {
+					let (client, approvals, window, name) = (client.clone(), approvals.clone(), window.clone(), name.clone());
+					async move {
+						let request = ToolApprovalRequest {
+							request_id: uuid::Uuid::new_v4().to_string(),
+							server_name: client.server.name.clone(),
+							tool_name: name.clone(),
+							arguments: arguments.to_string(),
+						};
+						let answer = approvals.request(&request.request_id).await;
+						let _ = window.emit("toolApproval", &request);
+						match tokio::time::timeout(APPROVAL_TIMEOUT, answer).await {
+							Ok(Ok(true)) => client.call_tool(&name, arguments).await,
+							Ok(Ok(false)) => Err("The user declined the tool call".to_string()),
+							_ => {
+								approvals.forget(&request.request_id).await;
+								Err("The tool call was not approved in time".to_string())
+							}
+						}
+					}
+				},
+			));
+		}
+	}
+	tools
+}
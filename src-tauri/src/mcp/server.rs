@@ -0,0 +1,325 @@
+//! MCP server offering the chat history to other applications, started with `byok --mcp`.
+//! Requests are read as lines of JSON from stdin and answered on stdout. The server works on the same database as the app,
+//! chats created with `send_message` appear in the app the next time it loads its chat list.
+
+use std::time::Instant;
+
+use serde_json::{json, Value};
+use sqlx::SqlitePool;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+
+use super::{METHOD_NOT_FOUND, PROTOCOL_VERSION};
+use crate::context::build_context;
+use crate::db::{find_model, find_provider, get_branch_messages, insert_message, insert_message_blocks};
+use crate::llm_providers::{LLMConfig, ToolCall};
+use crate::providers::fallback_chat_title;
+use crate::settings::Settings;
+use crate::tools::{search_chats_tool, string_argument, Tool, ToolRegistry};
+use crate::types::{Message, MessageHistory};
+use crate::utils::render_message;
+
+/// JSON-RPC error codes
+const PARSE_ERROR: i64 = -32700;
+const INVALID_PARAMS: i64 = -32602;
+
+/// Default and maximum number of chats list_recent_chats returns
+const DEFAULT_RECENT_CHATS: i64 = 20;
+const MAX_RECENT_CHATS: i64 = 100;
+
+/// Serve the history tools until stdin is closed
+pub async fn serve(pool: SqlitePool, settings: Settings) {
+	let tools = history_tools(pool, settings);
+	let mut lines = BufReader::new(tokio::io::stdin()).lines();
+	let mut stdout = tokio::io::stdout();
+	log::info!("Serving the chat history over MCP");
+
+	while let Ok(Some(line)) = lines.next_line().await {
+		if line.trim().is_empty() {
+			continue;
+		}
+		let response = match serde_json::from_str::<Value>(&line) {
+			Ok(request) => {
+				// Notifications are not answered
+				let Some(id) = request.get("id").cloned() else {
+					continue;
+				};
+				match handle_request(&request, &tools).await {
+					Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+					Err((code, message)) => json!({ "jsonrpc": "2.0", "id": id, "error": { "code": code, "message": message } }),
+				}
+			}
+			Err(e) => json!({ "jsonrpc": "2.0", "id": null, "error": { "code": PARSE_ERROR, "message": e.to_string() } }),
+		};
+		let mut response = response.to_string();
+		response.push('\n');
+		if let Err(e) = stdout.write_all(response.as_bytes()).await.and(stdout.flush().await) {
+			log::error!("Could not write MCP response: {}", e);
+			break;
+		}
+	}
+}
+
+/// Result of a request, or its JSON-RPC error code and message
+async fn handle_request(request: &Value, tools: &ToolRegistry) -> Result<Value, (i64, String)> {
+	let params = &request["params"];
+	match request["method"].as_str().unwrap_or_default() {
+		"initialize" => Ok(json!({
+			"protocolVersion": PROTOCOL_VERSION,
+			"capabilities": { "tools": {} },
+			"serverInfo": { "name": "byok", "version": env!("CARGO_PKG_VERSION") }
+		})),
+		"ping" => Ok(json!({})),
+		"tools/list" => {
+			let tools: Vec<Value> = tools
+				.specs()
+				.into_iter()
+				.map(|spec| json!({ "name": spec.name, "description": spec.description, "inputSchema": spec.parameters }))
+				.collect();
+			Ok(json!({ "tools": tools }))
+		}
+		"tools/call" => {
+			let name = params["name"].as_str().ok_or((INVALID_PARAMS, "Missing tool name".to_string()))?;
+			let call = ToolCall {
+				id: String::new(),
+				name: name.to_string(),
+				arguments: match &params["arguments"] {
+					Value::Null => String::new(),
+					arguments => arguments.to_string(),
+				},
+			};
+			let output = tools.run(&call).await;
+			Ok(json!({ "content": [{ "type": "text", "text": output.content }], "isError": output.is_error }))
+		}
+		method => Err((METHOD_NOT_FOUND, format!("{} is not supported", method))),
+	}
+}
+
+fn history_tools(pool: SqlitePool, settings: Settings) -> ToolRegistry {
+	let mut tools = ToolRegistry::default();
+	tools.register(search_chats_tool(pool.clone()));
+
+	let recent_pool = pool.clone();
+	tools.register(Tool::new(
+		"list_recent_chats",
+		"List the most recently updated chats with their ids and names.",
+		json!({
+			"type": "object",
+			"properties": {
+				"limit": { "type": "integer", "description": "Maximum number of chats, 20 by default" },
+				"include_archived": { "type": "boolean", "description": "Whether archived chats are listed too" }
+			}
+		}),
+		move |arguments| {
+			let pool = recent_pool.clone();
+			async move {
+				let limit = arguments["limit"].as_i64().unwrap_or(DEFAULT_RECENT_CHATS).clamp(1, MAX_RECENT_CHATS);
+				let include_archived = arguments["include_archived"].as_bool().unwrap_or(false);
+				list_recent_chats(limit, include_archived, &pool).await
+			}
+		},
+	));
+
+	let chat_pool = pool.clone();
+	tools.register(Tool::new(
+		"get_chat",
+		"Get the messages of a chat, from the first prompt to the latest answer.",
+		json!({
+			"type": "object",
+			"properties": { "chat_id": { "type": "string", "description": "Id of the chat" } },
+			"required": ["chat_id"]
+		}),
+		move |arguments| {
+			let pool = chat_pool.clone();
+			async move { get_chat(&string_argument(&arguments, "chat_id")?, &pool).await }
+		},
+	));
+
+	tools.register(Tool::new(
+		"send_message",
+		"Send a prompt to a model of the configured providers and save the exchange in Byok. \
+		Continues a chat if chat_id is given, otherwise a new chat is created. Returns the answer and the chat id.",
+		json!({
+			"type": "object",
+			"properties": {
+				"message": { "type": "string", "description": "Prompt to send" },
+				"chat_id": { "type": "string", "description": "Chat to continue" },
+				"provider_name": { "type": "string", "description": "Provider to use, by default the one of the chat or the default provider" },
+				"model_name": { "type": "string", "description": "Model to use, by default the one of the chat or the default model" }
+			},
+			"required": ["message"]
+		}),
+		move |arguments| {
+			let (pool, settings) = (pool.clone(), settings.clone());
+			async move { send_message(&arguments, &pool, &settings).await }
+		},
+	));
+	tools
+}
+
+async fn list_recent_chats(limit: i64, include_archived: bool, pool: &SqlitePool) -> Result<String, String> {
+	let query = "SELECT id, display_name, creation_date, last_updated, archived = 'true' FROM chats \
+		WHERE $1 OR archived = 'false' ORDER BY last_updated DESC LIMIT $2";
+	let chats = sqlx::query_as::<_, (String, String, Option<String>, Option<String>, bool)>(query)
+		.bind(include_archived)
+		.bind(limit)
+		.fetch_all(pool)
+		.await
+		.map_err(|e| format!("Error fetching chats: {}", e))?;
+	let chats: Vec<Value> = chats
+		.into_iter()
+		.map(|(id, name, created, updated, archived)| json!({ "chat_id": id, "name": name, "created": created, "last_updated": updated, "archived": archived }))
+		.collect();
+	serde_json::to_string_pretty(&chats).map_err(|e| e.to_string())
+}
+
+async fn get_chat(chat_id: &str, pool: &SqlitePool) -> Result<String, String> {
+	let chat = sqlx::query_as::<_, (String, Option<String>, Option<String>)>("SELECT display_name, creation_date, last_updated FROM chats WHERE id = $1")
+		.bind(chat_id)
+		.fetch_optional(pool)
+		.await
+		.map_err(|e| format!("Error fetching chat: {}", e))?;
+	let Some((name, created, updated)) = chat else {
+		return Err(format!("Chat {} not found", chat_id));
+	};
+	let messages: Vec<Value> = get_branch_messages(chat_id, pool)
+		.await?
+		.into_iter()
+		.map(|message| {
+			json!({
+				"message_id": message.id,
+				"role": message.role,
+				"model": message.model_name,
+				"status": message.status,
+				"content": message.content
+			})
+		})
+		.collect();
+	let chat = json!({ "chat_id": chat_id, "name": name, "created": created, "last_updated": updated, "messages": messages });
+	serde_json::to_string_pretty(&chat).map_err(|e| e.to_string())
+}
+
+/// Send a prompt with the history of the chat and save both messages
+async fn send_message(arguments: &Value, pool: &SqlitePool, settings: &Settings) -> Result<String, String> {
+	let prompt = string_argument(arguments, "message")?;
+	if prompt.trim().is_empty() {
+		return Err("The message must not be empty".to_string());
+	}
+	let chat_id = arguments["chat_id"].as_str().map(|chat_id| chat_id.to_string());
+	let (history, system_prompt) = match &chat_id {
+		Some(chat_id) => {
+			let chat = sqlx::query_as::<_, (Option<String>,)>("SELECT system_prompt FROM chats WHERE id = $1")
+				.bind(chat_id)
+				.fetch_optional(pool)
+				.await
+				.map_err(|e| format!("Error fetching chat: {}", e))?;
+			let Some((system_prompt,)) = chat else {
+				return Err(format!("Chat {} not found", chat_id));
+			};
+			(get_branch_messages(chat_id, pool).await?, system_prompt)
+		}
+		None => (Vec::new(), None),
+	};
+
+	// Continue with the model of the last answer unless another one is requested
+	let last_answer = history
+		.iter()
+		.rev()
+		.find(|message| message.role == "assistant" && message.provider_name.is_some());
+	let provider_name = arguments["provider_name"]
+		.as_str()
+		.map(|name| name.to_string())
+		.or_else(|| last_answer.and_then(|message| message.provider_name.clone()))
+		.unwrap_or_else(|| settings.default_provider.clone());
+	let model_name = arguments["model_name"]
+		.as_str()
+		.map(|name| name.to_string())
+		.or_else(|| last_answer.map(|message| message.model_name.clone()))
+		.unwrap_or_else(|| settings.default_model.clone());
+
+	let provider = find_provider(&provider_name, pool)
+		.await?
+		.ok_or_else(|| format!("Provider {} is not configured", provider_name))?
+		.to_provider()
+		.map_err(|e| format!("Failed to create provider: {}", e))?;
+	let model = find_model(&provider_name, &model_name, pool).await?;
+	let mut config = model.as_ref().map(LLMConfig::for_model).unwrap_or_default();
+	config.system_prompt = system_prompt.or_else(|| settings.system_prompt.clone());
+
+	let user_message = Message {
+		id: uuid::Uuid::new_v4().to_string(),
+		role: "user".to_string(),
+		content: prompt.clone(),
+		model_name: model_name.clone(),
+		parent_id: history.last().map(|message| message.id.clone()),
+		provider_name: Some(provider_name.clone()),
+		status: "ok".to_string(),
+		latency_ms: None,
+		prompt_tokens: None,
+		completion_tokens: None,
+		blocks: None,
+		attachments: Vec::new(),
+	};
+	let mut messages = MessageHistory(history.into_iter().filter(|message| message.status != "error").collect());
+	messages.0.push(user_message.clone());
+	if let Some(model) = &model {
+		messages = build_context(messages, model, &config, &provider.api_scheme()).messages;
+	}
+
+	let started_at = Instant::now();
+	let answer = provider.complete(&messages, &model_name, &config).await.map_err(|e| e.to_string())?;
+	let assistant_message = Message {
+		id: uuid::Uuid::new_v4().to_string(),
+		role: "assistant".to_string(),
+		content: answer.text.clone(),
+		model_name: model_name.clone(),
+		parent_id: Some(user_message.id.clone()),
+		provider_name: Some(provider_name.clone()),
+		status: "ok".to_string(),
+		latency_ms: Some(started_at.elapsed().as_millis() as u32),
+		prompt_tokens: answer.usage.map(|usage| usage.prompt_tokens),
+		completion_tokens: answer.usage.map(|usage| usage.completion_tokens),
+		blocks: None,
+		attachments: Vec::new(),
+	};
+
+	let chat_id = match chat_id {
+		Some(chat_id) => chat_id,
+		None => {
+			let chat_id = uuid::Uuid::new_v4().to_string();
+			sqlx::query(
+				"INSERT INTO chats (id, model, api_key_id, display_name, archived, last_updated) VALUES ($1, $2, 'NA', $3, 'false', CURRENT_TIMESTAMP)",
+			)
+			.bind(&chat_id)
+			.bind(&model_name)
+			.bind(fallback_chat_title(&prompt))
+			.execute(pool)
+			.await
+			.map_err(|e| format!("Error creating chat: {}", e))?;
+			chat_id
+		}
+	};
+	for message in [&user_message, &assistant_message] {
+		save_message(message, &chat_id, &settings.code_theme, pool).await?;
+	}
+	sqlx::query("UPDATE chats SET active_message_id = $1, last_updated = CURRENT_TIMESTAMP WHERE id = $2")
+		.bind(&assistant_message.id)
+		.bind(&chat_id)
+		.execute(pool)
+		.await
+		.map_err(|e| format!("Error updating chat: {}", e))?;
+
+	let result = json!({
+		"chat_id": chat_id,
+		"message_id": assistant_message.id,
+		"provider_name": provider_name,
+		"model_name": model_name,
+		"answer": answer.text
+	});
+	serde_json::to_string_pretty(&result).map_err(|e| e.to_string())
+}
+
+/// Insert a message with its rendered blocks
+async fn save_message(message: &Message, chat_id: &str, code_theme: &str, pool: &SqlitePool) -> Result<(), String> {
+	insert_message(message, chat_id, pool).await?;
+	insert_message_blocks(&message.id, &render_message(&message.content, code_theme).await, pool).await
+}
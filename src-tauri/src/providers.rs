@@ -1,18 +1,33 @@
+use std::collections::BTreeMap;
+use std::time::{Duration, Instant};
+
+use futures::stream::FuturesUnordered;
+use futures::StreamExt;
 use serde::{Deserialize, Serialize};
 use specta::Type;
-use sqlx::prelude::FromRow;
 use tauri::{command, Emitter};
+use tokio::sync::oneshot;
 
+use crate::context::build_context;
 use crate::db::get_api_key;
-use crate::llm_providers::{LLMConfig, Provider};
+use crate::errors::ProviderResult;
+use crate::knowledge::{retrieve_sources, sources_prompt, Source};
+use crate::llm_providers::{with_retries, LLMConfig, Provider, RequestOptions, RetryAttempt, StreamChunk, TokenUsage, ToolRound, MAX_ATTEMPTS};
+use crate::tools::{available_tools, ToolRegistry};
 use crate::{
-	data::DataState,
-	db::{get_chat_display_name, get_messages, insert_chat_display_name, insert_message, insert_message_blocks},
-	types::{Message, MessageBlocks, MessageHistory},
-	utils::render_message,
+	data::{DataState, GenerationsState},
+	db::{
+		delete_message, find_chat_parameters, find_chat_system_prompt, find_message, find_pending_attachments, get_active_message_id, get_attachments,
+		get_chat_display_name, get_messages, get_model, insert_chat_display_name, insert_message, insert_message_blocks, link_attachments,
+		set_active_message_id, update_message_content,
+	},
+	types::{
+		Attachment, ContextTruncated, Message, MessageBlocks, MessageDelta, MessageHistory, Model, ModelDeprecated, ModelTarget, ProviderRetry, ToolActivity,
+	},
+	utils::{render_attachments, render_message, render_sources, render_tool_rounds},
 };
 
-#[derive(Serialize, Deserialize, Clone, Debug, FromRow, Type)]
+#[derive(Serialize, Deserialize, Clone, Debug, Type)]
 pub struct ProviderData {
 	pub provider_name: String,
 	pub api_key: String,
@@ -21,24 +36,91 @@ pub struct ProviderData {
 	pub base_url: Option<String>,
 	pub api_scheme: Option<String>,
 	pub is_custom: bool,
+	/// Headers added to every request, e.g. for gateways or organization ids. Only supported for OpenAI-compatible APIs
+	#[serde(default)]
+	pub extra_headers: BTreeMap<String, String>,
+	/// Query parameters added to every request, e.g. `api-version` for Azure OpenAI. Only supported for OpenAI-compatible APIs
+	#[serde(default)]
+	pub query_params: BTreeMap<String, String>,
+}
+
+impl ProviderData {
+	/// Create the LLM provider for this configuration
+	pub fn to_provider(&self) -> anyhow::Result<Provider> {
+		Ok(
+			Provider::from_provider_data(&self.provider_name, &self.api_key, self.base_url.as_deref(), self.api_scheme.as_deref())?
+				.with_request_options(self.request_options()),
+		)
+	}
+
+	/// Check that the provider can send its extra headers and query parameters.
+	/// Only OpenAI-compatible APIs (OpenAI, Groq, Mistral, Azure and custom providers using their scheme) support them.
+	pub fn validate_request_options(&self) -> Result<(), String> {
+		if self.request_options().is_empty() {
+			return Ok(());
+		}
+		let provider = Provider::from_provider_data(&self.provider_name, &self.api_key, self.base_url.as_deref(), self.api_scheme.as_deref())
+			.map_err(|e| e.to_string())?;
+		match provider.supports_request_options() {
+			true => Ok(()),
+			false => Err("Extra headers and query parameters are only supported for OpenAI-compatible APIs".to_string()),
+		}
+	}
+
+	/// Extra headers and query parameters sent with every request
+	pub fn request_options(&self) -> RequestOptions {
+		RequestOptions {
+			headers: self.extra_headers.clone(),
+			query: self.query_params.clone(),
+		}
+	}
 }
 
 /// Maximum length for auto-generated chat titles
 const MAX_DISPLAY_NAME_LENGTH: u32 = 32;
 
-/// Save a user message to the database and emit event
-async fn save_user_message(msg: &str, chat_id: &str, model_name: &str, data: DataState<'_>) -> Result<String, String> {
-	let message_id = uuid::Uuid::new_v4().to_string();
-	insert_message(&message_id, "user", msg, chat_id, model_name, data.clone()).await;
+/// Maximum number of times a model may call tools before it has to answer
+const MAX_TOOL_ROUNDS: usize = 8;
 
-	let code_theme = data.0.lock().await.settings.code_theme.clone();
-	let rendered_blocks = render_message(msg, &code_theme).await;
-	insert_message_blocks(&message_id, &rendered_blocks, data.clone()).await;
+/// Minimum time between two re-renders of a partial answer while streaming
+const STREAM_RENDER_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Number of words of the user message used as title when no title could be generated
+const FALLBACK_TITLE_WORDS: usize = 6;
+
+/// Save a message with its attachments and rendered blocks to the database without changing the chat's active message
+async fn store_message(message: &Message, chat_id: &str, data: DataState<'_>) {
+	let (db_pool, code_theme) = {
+		let data = data.0.lock().await;
+		(data.db_pool.clone(), data.settings.code_theme.clone())
+	};
+	if let Err(e) = insert_message(message, chat_id, &db_pool).await {
+		log::error!("{}", e);
+	}
+	if let Err(e) = link_attachments(&message.id, &message.attachments, data.clone()).await {
+		log::error!("Error saving attachments of message {}: {}", message.id, e);
+	}
+
+	// Attached images and the pre-rendered blocks of the message, e.g. its tool calls, are shown above the text
+	let mut rendered_blocks = MessageBlocks(render_attachments(&message.attachments));
+	if let Some(blocks) = &message.blocks {
+		rendered_blocks.extend(blocks.iter().cloned());
+	}
+	rendered_blocks.extend(render_message(&message.content, &code_theme).await.0);
+	if let Err(e) = insert_message_blocks(&message.id, &rendered_blocks, &db_pool).await {
+		log::error!("{}", e);
+	}
+}
+
+/// Save a message with its rendered blocks to the database, make it the chat's active message and emit event
+async fn save_message(message: &Message, chat_id: &str, data: DataState<'_>) -> Result<(), String> {
+	store_message(message, chat_id, data.clone()).await;
+	set_active_message_id(chat_id, Some(&message.id), data.clone()).await?;
 
 	// Emit event that a new message is in the database
 	let _ = data.0.lock().await.window.emit("newMessage", chat_id);
 
-	Ok(message_id)
+	Ok(())
 }
 
 /// Ensure a chat exists in the database, creating it with a placeholder name if needed
@@ -68,7 +150,7 @@ async fn ensure_chat_exists(chat_id: &str, model_name: &str, data: DataState<'_>
 
 /// Get provider data for a specific provider
 async fn get_provider_data(provider_name: &str, data: DataState<'_>) -> Result<ProviderData, String> {
-	if provider_name == "local" || provider_name == "ollama" {
+	if provider_name == "local" {
 		return Ok(ProviderData {
 			provider_name: provider_name.to_string(),
 			api_key: String::new(),
@@ -77,9 +159,11 @@ async fn get_provider_data(provider_name: &str, data: DataState<'_>) -> Result<P
 			base_url: None,
 			api_scheme: Some("ollama".to_string()),
 			is_custom: false,
+			extra_headers: BTreeMap::new(),
+			query_params: BTreeMap::new(),
 		});
 	}
-	let query = "SELECT provider_name, api_key, display_name, api_key_valid, base_url, api_scheme, is_custom FROM providers WHERE provider_name = $1";
+	let query = "SELECT provider_name, api_key, display_name, api_key_valid, base_url, api_scheme, is_custom, extra_headers, query_params FROM providers WHERE provider_name = $1";
 	match sqlx::query_as::<_, ProviderData>(query)
 		.bind(provider_name)
 		.fetch_one(&data.0.lock().await.db_pool)
@@ -90,34 +174,362 @@ async fn get_provider_data(provider_name: &str, data: DataState<'_>) -> Result<P
 	}
 }
 
-/// Send message to LLM and get response
-async fn get_llm_response(llm: &Provider, messages: &MessageHistory, model_name: &str, config: &LLMConfig) -> String {
-	match llm.send_message(messages, model_name, config).await {
-		Ok(answer) => answer,
+/// Get the system prompt for a chat, falling back to the global one from the settings
+async fn resolve_system_prompt(chat_id: &str, data: DataState<'_>) -> Result<Option<String>, String> {
+	match find_chat_system_prompt(chat_id, data.clone()).await? {
+		Some(system_prompt) => Ok(Some(system_prompt)),
+		None => Ok(data.0.lock().await.settings.system_prompt.clone()),
+	}
+}
+
+/// Provider, model and parameters used to generate an answer
+struct Generation {
+	llm: Provider,
+	provider_name: String,
+	model_name: String,
+	/// Database row of the model, `None` for models that are not configured
+	model: Option<Model>,
+	config: LLMConfig,
+}
+
+/// Create the LLM provider and build the generation config from the model's defaults and the chat's overrides
+async fn prepare_generation(chat_id: &str, provider_name: &str, model_name: &str, data: DataState<'_>) -> Result<Generation, String> {
+	// Get provider data (including API key, base_url, api_scheme)
+	let provider_data = get_provider_data(provider_name, data.clone()).await?;
+	let llm = provider_data.to_provider().map_err(|e| format!("Failed to create provider: {}", e))?;
+
+	let model = get_model(provider_name, model_name, data.clone()).await?;
+	match &model {
+		None => log::warn!("Model {} of provider {} not found, using default parameters", model_name, provider_name),
+		Some(model) if model.deprecated => {
+			log::warn!("Model {} is no longer listed by provider {}", model_name, provider_name);
+			let payload = ModelDeprecated {
+				chat_id: chat_id.to_string(),
+				provider_name: provider_name.to_string(),
+				model_name: model_name.to_string(),
+			};
+			let _ = data.0.lock().await.window.emit("modelDeprecated", &payload);
+		}
+		Some(_) => {}
+	}
+
+	let mut config = model.as_ref().map(LLMConfig::for_model).unwrap_or_default();
+	config.system_prompt = resolve_system_prompt(chat_id, data.clone()).await?;
+	if let Some(parameters) = find_chat_parameters(chat_id, data).await? {
+		config = config.with_overrides(&parameters);
+	}
+
+	Ok(Generation {
+		llm,
+		provider_name: provider_name.to_string(),
+		model_name: model_name.to_string(),
+		model,
+		config,
+	})
+}
+
+/// Fit the history into the model's context window, notifying the UI if messages had to be excluded
+async fn fit_history(chat_id: &str, messages: MessageHistory, generation: &Generation, data: DataState<'_>) -> MessageHistory {
+	// Without a model row the context window is unknown, send the full history
+	let Some(model) = &generation.model else {
+		return messages;
+	};
+
+	let context = build_context(messages, model, &generation.config, &generation.llm.api_scheme());
+	if context.excluded > 0 {
+		log::info!(
+			"Excluded {} messages of chat {} that did not fit into the context window",
+			context.excluded,
+			chat_id
+		);
+		let payload = ContextTruncated {
+			chat_id: chat_id.to_string(),
+			excluded_messages: context.excluded as u32,
+			estimated_tokens: context.estimated_tokens as u32,
+		};
+		let _ = data.0.lock().await.window.emit("contextTruncated", &payload);
+	}
+	context.messages
+}
+
+/// Chunks of the chat's collections that match the question.
+/// Answers are still generated if retrieval fails, e.g. because the embedding provider is not reachable.
+async fn find_sources(chat_id: &str, question: &str, data: DataState<'_>) -> Vec<Source> {
+	let pool = data.0.lock().await.db_pool.clone();
+	match retrieve_sources(chat_id, question, &pool).await {
+		Ok(sources) => sources,
 		Err(e) => {
-			log::error!("Error sending message to LLM: {}", e);
-			e.to_string()
+			log::warn!("Could not retrieve sources for chat {}: {}", chat_id, e);
+			Vec::new()
 		}
 	}
 }
 
-/// Save assistant response to the database and emit event
-async fn save_assistant_message(answer: &str, chat_id: &str, model_name: &str, data: DataState<'_>) -> Result<String, String> {
-	let message_id = uuid::Uuid::new_v4().to_string();
-	insert_message(&message_id, "assistant", answer, chat_id, model_name, data.clone()).await;
+/// Whether any message of a history has images attached
+fn has_images(messages: &MessageHistory) -> bool {
+	messages.iter().any(|msg| msg.attachments.iter().any(Attachment::is_image))
+}
 
-	let code_theme = data.0.lock().await.settings.code_theme.clone();
-	let rendered_blocks: MessageBlocks = render_message(answer, &code_theme).await;
-	insert_message_blocks(&message_id, &rendered_blocks, data.clone()).await;
+/// Images can only be sent to models that are flagged as supporting them
+fn ensure_vision_support(model: Option<&Model>, model_name: &str) -> Result<(), String> {
+	match model {
+		Some(model) if model.supports_vision => Ok(()),
+		Some(model) => Err(format!(
+			"{} does not support images, select a model with vision support or enable it in the model settings",
+			model.model_display_name
+		)),
+		None => Err(format!("{} is not configured with vision support and cannot receive images", model_name)),
+	}
+}
 
-	// Emit event that a new message is in the database
-	let _ = data.0.lock().await.window.emit("newMessage", chat_id);
+/// Result of streaming an answer from the LLM
+struct StreamedAnswer {
+	/// The answer, or the error message if the request failed
+	text: String,
+	/// Message status: "ok", "error" or "interrupted"
+	status: &'static str,
+	/// Token usage, only known for completed answers of providers that report it
+	usage: Option<TokenUsage>,
+	/// Tool calls made before the answer, with their outputs
+	tool_rounds: Vec<ToolRound>,
+}
 
-	Ok(message_id)
+/// Payload of the `providerRetry` event for a failed attempt
+fn retry_payload(chat_id: &str, generation: &Generation, retry: &RetryAttempt) -> ProviderRetry {
+	ProviderRetry {
+		chat_id: chat_id.to_string(),
+		provider_name: generation.provider_name.clone(),
+		attempt: retry.attempt,
+		max_attempts: MAX_ATTEMPTS,
+		delay_ms: retry.delay.as_millis() as u32,
+		reason: retry.error.to_string(),
+	}
+}
+
+/// Stream the LLM response, emitting a `messageDelta` event for every received chunk.
+/// Dropping the stream when `cancel` fires aborts the provider request.
+async fn stream_llm_response(
+	generation: &Generation,
+	messages: &MessageHistory,
+	chat_id: &str,
+	message_id: &str,
+	mut cancel: oneshot::Receiver<()>,
+	data: DataState<'_>,
+) -> StreamedAnswer {
+	let failed = |text: String| StreamedAnswer {
+		text,
+		status: "error",
+		usage: None,
+		tool_rounds: Vec::new(),
+	};
+	let interrupted = |text: String| StreamedAnswer {
+		text,
+		status: "interrupted",
+		usage: None,
+		tool_rounds: Vec::new(),
+	};
+
+	let (window, code_theme) = {
+		let data = data.0.lock().await;
+		(data.window.clone(), data.settings.code_theme.clone())
+	};
+
+	// Rate limits and server errors are retried until the stream is established
+	let llm = &generation.llm;
+	let on_retry = |retry: &RetryAttempt| {
+		let _ = window.emit("providerRetry", &retry_payload(chat_id, generation, retry));
+	};
+	let request = with_retries(|| llm.stream_message(messages, &generation.model_name, &generation.config), on_retry);
+
+	let mut deltas = tokio::select! {
+		deltas = request => match deltas {
+			Ok(deltas) => deltas,
+			Err(e) => {
+				log::error!("Error sending message to LLM: {}", e);
+				return failed(e.to_string());
+			}
+		},
+		Ok(()) = &mut cancel => return interrupted(String::new()),
+	};
+
+	let mut answer = String::new();
+	let mut usage = None;
+	let mut last_render: Option<Instant> = None;
+	loop {
+		let chunk = tokio::select! {
+			chunk = deltas.next() => chunk,
+			Ok(()) = &mut cancel => return interrupted(answer),
+		};
+		let delta = match chunk {
+			Some(Ok(StreamChunk::Text(delta))) => delta,
+			Some(Ok(StreamChunk::Usage(reported))) => {
+				usage = Some(reported);
+				continue;
+			}
+			Some(Err(e)) => {
+				log::error!("Error while streaming LLM response: {}", e);
+				// Keep a partial answer like a cancelled one, only store the error if nothing arrived
+				if answer.is_empty() {
+					return failed(e.to_string());
+				}
+				return interrupted(answer);
+			}
+			None => break,
+		};
+		answer.push_str(&delta);
+
+		// Re-rendering runs syntax highlighting on the whole answer, so throttle it
+		let blocks = match last_render {
+			Some(rendered_at) if rendered_at.elapsed() < STREAM_RENDER_INTERVAL => None,
+			_ => {
+				last_render = Some(Instant::now());
+				Some(render_message(&answer, &code_theme).await)
+			}
+		};
+
+		let payload = MessageDelta {
+			chat_id: chat_id.to_string(),
+			message_id: message_id.to_string(),
+			delta,
+			blocks,
+		};
+		let _ = window.emit("messageDelta", &payload);
+	}
+
+	StreamedAnswer {
+		text: answer,
+		status: "ok",
+		usage,
+		tool_rounds: Vec::new(),
+	}
+}
+
+/// Answer with tools offered to the model, running the tools it calls and sending their outputs back until it answers with text.
+/// The requests are not streamed, a `toolActivity` event is emitted for every call and the final text is emitted as one `messageDelta`.
+async fn answer_with_tools(
+	generation: &Generation,
+	messages: &MessageHistory,
+	tools: &ToolRegistry,
+	chat_id: &str,
+	message_id: &str,
+	mut cancel: oneshot::Receiver<()>,
+	data: DataState<'_>,
+) -> StreamedAnswer {
+	let (window, code_theme) = {
+		let data = data.0.lock().await;
+		(data.window.clone(), data.settings.code_theme.clone())
+	};
+	let specs = tools.specs();
+	let mut tool_rounds: Vec<ToolRound> = Vec::new();
+	let mut usage: Option<TokenUsage> = None;
+
+	loop {
+		let on_retry = |retry: &RetryAttempt| {
+			let _ = window.emit("providerRetry", &retry_payload(chat_id, generation, retry));
+		};
+		let request = with_retries(
+			|| {
+				generation
+					.llm
+					.complete_with_tools(messages, &tool_rounds, &specs, &generation.model_name, &generation.config)
+			},
+			on_retry,
+		);
+		let answer = tokio::select! {
+			answer = request => Some(answer),
+			Ok(()) = &mut cancel => None,
+		};
+		let answer = match answer {
+			Some(Ok(answer)) => answer,
+			Some(Err(e)) => {
+				log::error!("Error sending message with tools to LLM: {}", e);
+				return StreamedAnswer {
+					text: e.to_string(),
+					status: "error",
+					usage,
+					tool_rounds,
+				};
+			}
+			None => {
+				return StreamedAnswer {
+					text: String::new(),
+					status: "interrupted",
+					usage,
+					tool_rounds,
+				}
+			}
+		};
+		usage = match (usage, answer.usage) {
+			(Some(total), Some(round)) => Some(TokenUsage {
+				prompt_tokens: total.prompt_tokens + round.prompt_tokens,
+				completion_tokens: total.completion_tokens + round.completion_tokens,
+			}),
+			(total, round) => total.or(round),
+		};
+
+		if answer.tool_calls.is_empty() {
+			let mut blocks = MessageBlocks(render_tool_rounds(&tool_rounds, &code_theme));
+			blocks.extend(render_message(&answer.text, &code_theme).await.0);
+			let payload = MessageDelta {
+				chat_id: chat_id.to_string(),
+				message_id: message_id.to_string(),
+				delta: answer.text.clone(),
+				blocks: Some(blocks),
+			};
+			let _ = window.emit("messageDelta", &payload);
+			return StreamedAnswer {
+				text: answer.text,
+				status: "ok",
+				usage,
+				tool_rounds,
+			};
+		}
+		if tool_rounds.len() >= MAX_TOOL_ROUNDS {
+			log::warn!("Stopped answer of chat {} after {} rounds of tool calls", chat_id, MAX_TOOL_ROUNDS);
+			return StreamedAnswer {
+				text: format!("The model was stopped after calling tools {} times without answering", MAX_TOOL_ROUNDS),
+				status: "error",
+				usage,
+				tool_rounds,
+			};
+		}
+
+		let mut outputs = Vec::new();
+		for call in &answer.tool_calls {
+			let mut activity = ToolActivity {
+				chat_id: chat_id.to_string(),
+				message_id: message_id.to_string(),
+				tool_name: call.name.clone(),
+				arguments: call.arguments.clone(),
+				output: None,
+				is_error: false,
+			};
+			let _ = window.emit("toolActivity", &activity);
+			let output = tokio::select! {
+				output = tools.run(call) => output,
+				Ok(()) = &mut cancel => {
+					return StreamedAnswer {
+						text: String::new(),
+						status: "interrupted",
+						usage,
+						tool_rounds,
+					}
+				}
+			};
+			activity.output = Some(output.content.clone());
+			activity.is_error = output.is_error;
+			let _ = window.emit("toolActivity", &activity);
+			outputs.push(output);
+		}
+		tool_rounds.push(ToolRound {
+			calls: answer.tool_calls,
+			outputs,
+		});
+	}
 }
 
 /// Generate a descriptive title for a chat using the LLM
-async fn generate_chat_title(llm: &Provider, user_msg: &str, assistant_msg: &str, model_name: &str) -> String {
+async fn generate_chat_title(generation: &Generation, user_msg: &str, assistant_msg: &str) -> ProviderResult<String> {
 	let prompt = format!(
 		"Based on the following conversation, create a short and descriptive title (3–6 words) \
 		that summarizes the main topic or purpose of the exchange:\n\
@@ -134,22 +546,33 @@ async fn generate_chat_title(llm: &Provider, user_msg: &str, assistant_msg: &str
 		id: String::new(),
 		role: "user".to_string(),
 		content: prompt,
-		model_name: model_name.to_string(),
+		model_name: generation.model_name.clone(),
+		parent_id: None,
+		provider_name: Some(generation.provider_name.clone()),
+		status: "ok".to_string(),
+		latency_ms: None,
+		prompt_tokens: None,
+		completion_tokens: None,
 		blocks: None,
+		attachments: Vec::new(),
 	}]);
 
 	let title_config = LLMConfig {
 		temperature: 0.0,
 		max_tokens: MAX_DISPLAY_NAME_LENGTH,
-		top_p: None,
+		..LLMConfig::default()
 	};
 
-	match llm.send_message(&title_messages, model_name, &title_config).await {
-		Ok(title) => title,
-		Err(e) => {
-			log::error!("Error generating chat title: {}", e);
-			e.to_string()
-		}
+	generation.llm.send_message(&title_messages, &generation.model_name, &title_config).await
+}
+
+/// Title made of the first words of the user message, used when the LLM could not generate one
+pub fn fallback_chat_title(user_msg: &str) -> String {
+	let words: Vec<&str> = user_msg.split_whitespace().take(FALLBACK_TITLE_WORDS).collect();
+	if words.is_empty() {
+		"New Chat".to_string()
+	} else {
+		words.join(" ")
 	}
 }
 
@@ -182,14 +605,20 @@ async fn update_chat_timestamp(chat_id: &str, data: DataState<'_>) -> Result<(),
 }
 
 /// Handle chat title generation or timestamp update after receiving a response
-async fn finalize_chat(llm: &Provider, chat_id: &str, user_msg: &str, assistant_msg: &str, model_name: &str, data: DataState<'_>) -> Result<(), String> {
+async fn finalize_chat(generation: &Generation, chat_id: &str, user_msg: &str, assistant_msg: &str, data: DataState<'_>) -> Result<(), String> {
 	let chat_result = get_chat_display_name(chat_id, data.clone()).await;
 
 	match chat_result {
 		Ok(Some((display_name,))) => {
 			if display_name.starts_with("unnamed_new_chat_") {
-				// Generate a new title for the chat
-				let new_title = generate_chat_title(llm, user_msg, assistant_msg, model_name).await;
+				// Generate a new title for the chat, errors must not end up as the chat's name
+				let new_title = match generate_chat_title(generation, user_msg, assistant_msg).await {
+					Ok(title) => title,
+					Err(e) => {
+						log::error!("Error generating chat title: {}", e);
+						fallback_chat_title(user_msg)
+					}
+				};
 				log::debug!("New chat display name: {}", new_title);
 				update_chat_display_name(chat_id, &new_title, data).await?;
 			} else {
@@ -210,35 +639,431 @@ async fn finalize_chat(llm: &Provider, chat_id: &str, user_msg: &str, assistant_
 
 #[command]
 #[specta::specta]
-pub async fn get_message(msg: String, chat_id: String, provider_name: String, model_name: String, data: DataState<'_>) -> Result<String, String> {
-	// 1. Save user message
-	save_user_message(&msg, &chat_id, &model_name, data.clone()).await?;
+pub async fn get_message(
+	msg: String,
+	chat_id: String,
+	provider_name: String,
+	model_name: String,
+	attachment_ids: Option<Vec<String>>,
+	data: DataState<'_>,
+	generations: GenerationsState<'_>,
+) -> Result<String, String> {
+	// Only one generation may run per chat at a time
+	let (generation_id, cancel) = generations.register(&chat_id).await?;
+	let result = send_user_message(&msg, &attachment_ids.unwrap_or_default(), &chat_id, &provider_name, &model_name, cancel, data).await;
+	generations.unregister(&chat_id, &generation_id).await;
+	result
+}
+
+/// Cancel the generation currently running for a chat.
+/// Returns false if no generation was running.
+#[command]
+#[specta::specta]
+pub async fn cancel_generation(chat_id: String, generations: GenerationsState<'_>) -> Result<bool, String> {
+	let cancelled = generations.cancel(&chat_id).await;
+	log::debug!("Cancel generation for chat {}: {}", chat_id, cancelled);
+	Ok(cancelled)
+}
+
+/// Re-send the turn of a failed or interrupted answer, optionally to a different model.
+/// The answer is replaced by the new one.
+#[command]
+#[specta::specta]
+pub async fn retry_message(
+	message_id: String,
+	provider_name: Option<String>,
+	model_name: Option<String>,
+	data: DataState<'_>,
+	generations: GenerationsState<'_>,
+) -> Result<String, String> {
+	let (chat_id, message) = find_message(&message_id, data.clone()).await?.ok_or_else(|| "Message not found".to_string())?;
+	if message.role != "assistant" || message.status == "ok" {
+		return Err("Only failed or interrupted answers can be retried".to_string());
+	}
+	if get_active_message_id(&chat_id, data.clone()).await?.as_deref() != Some(message_id.as_str()) {
+		return Err("Only the latest answer of a chat can be retried".to_string());
+	}
+
+	let provider_name = provider_name
+		.or(message.provider_name)
+		.ok_or_else(|| "The provider of this message is unknown, please select a model".to_string())?;
+	let model_name = model_name.unwrap_or(message.model_name);
+
+	let (generation_id, cancel) = generations.register(&chat_id).await?;
+	let result = match delete_message(&message_id, data.clone()).await {
+		Ok(()) => generate_reply(&chat_id, &provider_name, &model_name, cancel, data).await,
+		Err(e) => Err(e),
+	};
+	generations.unregister(&chat_id, &generation_id).await;
+	result
+}
+
+/// Generate a new version of an assistant reply, optionally with a different model.
+/// The previous versions are kept as alternative branches, the new one becomes active.
+#[command]
+#[specta::specta]
+pub async fn regenerate_message(
+	message_id: String,
+	provider_name: Option<String>,
+	model_name: Option<String>,
+	data: DataState<'_>,
+	generations: GenerationsState<'_>,
+) -> Result<String, String> {
+	let (chat_id, message) = find_message(&message_id, data.clone()).await?.ok_or_else(|| "Message not found".to_string())?;
+	if message.role != "assistant" {
+		return Err("Only answers can be regenerated".to_string());
+	}
+	let parent_id = message.parent_id.ok_or_else(|| "The answer has no prompt to regenerate it from".to_string())?;
+
+	let provider_name = provider_name
+		.or(message.provider_name)
+		.ok_or_else(|| "The provider of this message is unknown, please select a model".to_string())?;
+	let model_name = model_name.unwrap_or(message.model_name);
+
+	let (generation_id, cancel) = generations.register(&chat_id).await?;
+	let previous_active_id = get_active_message_id(&chat_id, data.clone()).await;
+	let result = match set_active_message_id(&chat_id, Some(&parent_id), data.clone()).await {
+		Ok(()) => generate_reply(&chat_id, &provider_name, &model_name, cancel, data.clone()).await,
+		Err(e) => Err(e),
+	};
+	// Nothing was saved if the generation was cancelled right away, go back to the branch shown before
+	if let (Ok(Some(previous_active_id)), Ok(Some(active_id))) = (previous_active_id, get_active_message_id(&chat_id, data.clone()).await) {
+		if active_id == parent_id {
+			let _ = set_active_message_id(&chat_id, Some(&previous_active_id), data).await;
+		}
+	}
+	generations.unregister(&chat_id, &generation_id).await;
+	result
+}
+
+/// Edit a message.
+/// An edited prompt is saved as a new version next to the original one and answered again, optionally with a different model.
+/// An edited answer is changed in place and rendered again. Returns the new answer or the edited content.
+#[command]
+#[specta::specta]
+pub async fn edit_message(
+	message_id: String,
+	content: String,
+	provider_name: Option<String>,
+	model_name: Option<String>,
+	data: DataState<'_>,
+	generations: GenerationsState<'_>,
+) -> Result<String, String> {
+	if content.trim().is_empty() {
+		return Err("Message must not be empty".to_string());
+	}
+	let (chat_id, message) = find_message(&message_id, data.clone()).await?.ok_or_else(|| "Message not found".to_string())?;
+
+	match message.role.as_str() {
+		"user" => {
+			let provider_name = provider_name
+				.or(message.provider_name)
+				.ok_or_else(|| "The provider of this message is unknown, please select a model".to_string())?;
+			let model_name = model_name.unwrap_or(message.model_name);
+			let edited_message = Message {
+				id: uuid::Uuid::new_v4().to_string(),
+				role: "user".to_string(),
+				content,
+				model_name: model_name.clone(),
+				parent_id: message.parent_id,
+				provider_name: Some(provider_name.clone()),
+				status: "ok".to_string(),
+				latency_ms: None,
+				prompt_tokens: None,
+				completion_tokens: None,
+				blocks: None,
+				attachments: get_attachments(&message_id, data.clone()).await?,
+			};
+
+			let (generation_id, cancel) = generations.register(&chat_id).await?;
+			let result = match save_message(&edited_message, &chat_id, data.clone()).await {
+				Ok(()) => generate_reply(&chat_id, &provider_name, &model_name, cancel, data).await,
+				Err(e) => Err(e),
+			};
+			generations.unregister(&chat_id, &generation_id).await;
+			result
+		}
+		"assistant" => {
+			let code_theme = data.0.lock().await.settings.code_theme.clone();
+			let rendered_blocks = render_message(&content, &code_theme).await;
+			update_message_content(&message_id, &content, &rendered_blocks, data.clone()).await?;
+			let _ = data.0.lock().await.window.emit("newMessage", &chat_id);
+			Ok(content)
+		}
+		_ => Err("Only prompts and answers can be edited".to_string()),
+	}
+}
+
+/// Send a prompt to several models at once.
+/// Every answer is stored as a version of the reply to the same prompt and the first successful one becomes active,
+/// `switch_message_version` continues the chat with another one. Returns the answers in the order of `targets`.
+#[command]
+#[specta::specta]
+pub async fn compare_models(
+	msg: String,
+	chat_id: String,
+	targets: Vec<ModelTarget>,
+	data: DataState<'_>,
+	generations: GenerationsState<'_>,
+) -> Result<Vec<Message>, String> {
+	if targets.is_empty() {
+		return Err("Select at least one model to compare".to_string());
+	}
+
+	let (generation_id, cancel) = generations.register(&chat_id).await?;
+	let result = send_comparison(&msg, &chat_id, &targets, cancel, data).await;
+	generations.unregister(&chat_id, &generation_id).await;
+	result
+}
+
+/// Save the prompt of a comparison and collect the answers of all models
+async fn send_comparison(
+	msg: &str,
+	chat_id: &str,
+	targets: &[ModelTarget],
+	mut cancel: oneshot::Receiver<()>,
+	data: DataState<'_>,
+) -> Result<Vec<Message>, String> {
+	// 1. Ensure chat exists
+	let first_target = &targets[0];
+	ensure_chat_exists(chat_id, &first_target.model_name, data.clone()).await?;
+
+	// 2. Save user message as reply to the active message
+	let user_message = Message {
+		id: uuid::Uuid::new_v4().to_string(),
+		role: "user".to_string(),
+		content: msg.to_string(),
+		model_name: first_target.model_name.clone(),
+		parent_id: get_active_message_id(chat_id, data.clone()).await?,
+		provider_name: Some(first_target.provider_name.clone()),
+		status: "ok".to_string(),
+		latency_ms: None,
+		prompt_tokens: None,
+		completion_tokens: None,
+		blocks: None,
+		attachments: Vec::new(),
+	};
+	save_message(&user_message, chat_id, data.clone()).await?;
+	let history = get_messages(chat_id, data.clone()).await.map_err(|e| e.to_string())?;
+
+	// 3. Ask all models concurrently, every answer is stored as soon as it arrives.
+	// Cancelling drops the requests that are still running.
+	let mut requests: FuturesUnordered<_> = targets
+		.iter()
+		.enumerate()
+		.map(|(index, target)| {
+			let data = data.clone();
+			let history = &history;
+			let parent_id = &user_message.id;
+			async move { (index, compare_answer(chat_id, parent_id, history, target, data).await) }
+		})
+		.collect();
+	let mut answers = Vec::with_capacity(targets.len());
+	loop {
+		tokio::select! {
+			answer = requests.next() => match answer {
+				Some(answer) => answers.push(answer),
+				None => break,
+			},
+			Ok(()) = &mut cancel => break,
+		}
+	}
+	drop(requests);
+	answers.sort_by_key(|(index, _)| *index);
+
+	// 4. Continue with the first successful answer, or show the failed ones
+	let active_answer = answers.iter().find(|(_, (message, _))| message.status == "ok").or(answers.first());
+	if let Some((_, (message, _))) = active_answer {
+		set_active_message_id(chat_id, Some(&message.id), data.clone()).await?;
+	}
+	let _ = data.0.lock().await.window.emit("newMessage", chat_id);
+
+	// 5. Finalize chat (generate title or update timestamp)
+	match answers
+		.iter()
+		.find_map(|(_, (message, generation))| generation.as_ref().map(|generation| (message, generation)))
+	{
+		Some((message, generation)) => finalize_chat(generation, chat_id, msg, &message.content, data).await?,
+		None => update_chat_timestamp(chat_id, data).await?,
+	}
+
+	Ok(answers.into_iter().map(|(_, (message, _))| message).collect())
+}
+
+/// Get the answer of a single model of a comparison and store it as reply to the prompt.
+/// The generation is only returned if the request succeeded.
+async fn compare_answer(chat_id: &str, parent_id: &str, history: &MessageHistory, target: &ModelTarget, data: DataState<'_>) -> (Message, Option<Generation>) {
+	let started_at = Instant::now();
+	let result = match prepare_generation(chat_id, &target.provider_name, &target.model_name, data.clone()).await {
+		Ok(generation) => {
+			let messages = fit_history(chat_id, MessageHistory(history.to_vec()), &generation, data.clone()).await;
+			let window = data.0.lock().await.window.clone();
+			let on_retry = |retry: &RetryAttempt| {
+				let _ = window.emit("providerRetry", &retry_payload(chat_id, &generation, retry));
+			};
+			let vision_check = if has_images(&messages) {
+				ensure_vision_support(generation.model.as_ref(), &target.model_name)
+			} else {
+				Ok(())
+			};
+			match vision_check {
+				Ok(()) => match with_retries(|| generation.llm.complete(&messages, &generation.model_name, &generation.config), on_retry).await {
+					Ok(answer) => Ok((answer, generation)),
+					Err(e) => Err(e.to_string()),
+				},
+				Err(e) => Err(e),
+			}
+		}
+		Err(e) => Err(e),
+	};
+	let latency_ms = started_at.elapsed().as_millis() as u32;
+
+	let (content, status, usage, generation) = match result {
+		Ok((answer, generation)) => (answer.text, "ok", answer.usage, Some(generation)),
+		Err(e) => {
+			log::error!("Error comparing model {} of provider {}: {}", target.model_name, target.provider_name, e);
+			(e, "error", None, None)
+		}
+	};
+	let message = Message {
+		id: uuid::Uuid::new_v4().to_string(),
+		role: "assistant".to_string(),
+		content,
+		model_name: target.model_name.clone(),
+		parent_id: Some(parent_id.to_string()),
+		provider_name: Some(target.provider_name.clone()),
+		status: status.to_string(),
+		latency_ms: Some(latency_ms),
+		prompt_tokens: usage.map(|usage| usage.prompt_tokens),
+		completion_tokens: usage.map(|usage| usage.completion_tokens),
+		blocks: None,
+		attachments: Vec::new(),
+	};
+	store_message(&message, chat_id, data).await;
+	(message, generation)
+}
+
+/// Save the user message with its attachments and generate the answer to it
+async fn send_user_message(
+	msg: &str,
+	attachment_ids: &[String],
+	chat_id: &str,
+	provider_name: &str,
+	model_name: &str,
+	cancel: oneshot::Receiver<()>,
+	data: DataState<'_>,
+) -> Result<String, String> {
+	// 1. Check the attachments before anything is saved
+	let attachments = find_pending_attachments(attachment_ids, data.clone()).await?;
+	if attachments.iter().any(Attachment::is_image) {
+		let model = get_model(provider_name, model_name, data.clone()).await?;
+		ensure_vision_support(model.as_ref(), model_name)?;
+	}
 
 	// 2. Ensure chat exists
-	ensure_chat_exists(&chat_id, &model_name, data.clone()).await?;
+	ensure_chat_exists(chat_id, model_name, data.clone()).await?;
 
-	// 3. Get provider data (including API key, base_url, api_scheme)
-	let provider_data = get_provider_data(&provider_name, data.clone()).await?;
+	// 3. Save user message as reply to the active message
+	let user_message = Message {
+		id: uuid::Uuid::new_v4().to_string(),
+		role: "user".to_string(),
+		content: msg.to_string(),
+		model_name: model_name.to_string(),
+		parent_id: get_active_message_id(chat_id, data.clone()).await?,
+		provider_name: Some(provider_name.to_string()),
+		status: "ok".to_string(),
+		latency_ms: None,
+		prompt_tokens: None,
+		completion_tokens: None,
+		blocks: None,
+		attachments,
+	};
+	save_message(&user_message, chat_id, data.clone()).await?;
 
-	// 4. Get chat history
-	let messages = get_messages(&chat_id, data.clone()).await.map_err(|e| e.to_string())?;
+	// 4. Generate and store the answer
+	generate_reply(chat_id, provider_name, model_name, cancel, data).await
+}
 
-	// 5. Create LLM provider and get response
-	let llm = Provider::from_provider_data(
-		&provider_data.provider_name,
-		&provider_data.api_key,
-		provider_data.base_url.as_deref(),
-		provider_data.api_scheme.as_deref(),
-	).map_err(|e| format!("Failed to create provider: {}", e))?;
+/// Generate the answer to the active message of a chat and store it as its reply.
+/// Failed requests are stored with status "error" and returned as error.
+async fn generate_reply(chat_id: &str, provider_name: &str, model_name: &str, cancel: oneshot::Receiver<()>, data: DataState<'_>) -> Result<String, String> {
+	// 1. Create LLM provider and resolve the generation parameters
+	let generation = prepare_generation(chat_id, provider_name, model_name, data.clone()).await?;
 
-	let llm_config = LLMConfig::default();
-	let answer = get_llm_response(&llm, &messages, &model_name, &llm_config).await;
+	// 2. Get chat history, add the sources found in the chat's collections to the question and fit it into the model's context window
+	let mut messages = get_messages(chat_id, data.clone()).await.map_err(|e| e.to_string())?;
+	let user_msg = messages
+		.iter()
+		.rev()
+		.find(|msg| msg.role == "user")
+		.map(|msg| msg.content.clone())
+		.unwrap_or_default();
+	let sources = find_sources(chat_id, &user_msg, data.clone()).await;
+	if !sources.is_empty() {
+		if let Some(question) = messages.0.iter_mut().rev().find(|msg| msg.role == "user") {
+			question.content = sources_prompt(&sources, &question.content);
+		}
+	}
+	let messages = fit_history(chat_id, messages, &generation, data.clone()).await;
+	if has_images(&messages) {
+		ensure_vision_support(generation.model.as_ref(), model_name)?;
+	}
 
-	// 6. Save assistant response
-	save_assistant_message(&answer, &chat_id, &model_name, data.clone()).await?;
+	// 3. Stream the response, answers with tools offered run the tool calls first
+	let tools = if generation.llm.supports_tools() {
+		available_tools(data.clone()).await
+	} else {
+		ToolRegistry::default()
+	};
+	let parent_id = get_active_message_id(chat_id, data.clone()).await?;
+	let message_id = uuid::Uuid::new_v4().to_string();
+	let started_at = Instant::now();
+	let answer = if tools.is_empty() {
+		stream_llm_response(&generation, &messages, chat_id, &message_id, cancel, data.clone()).await
+	} else {
+		answer_with_tools(&generation, &messages, &tools, chat_id, &message_id, cancel, data.clone()).await
+	};
+	let latency_ms = started_at.elapsed().as_millis() as u32;
 
-	// 7. Finalize chat (generate title or update timestamp)
-	finalize_chat(&llm, &chat_id, &msg, &answer, &model_name, data).await?;
+	// 4. Save the response once the stream has completed.
+	// Interrupted generations without any text are dropped, the chat stays usable for the next message.
+	if answer.status == "interrupted" && answer.text.is_empty() {
+		let _ = data.0.lock().await.window.emit("newMessage", chat_id);
+	} else {
+		let code_theme = data.0.lock().await.settings.code_theme.clone();
+		// Sources and tool calls are shown above the answer
+		let mut blocks = render_sources(&sources, &code_theme);
+		blocks.extend(render_tool_rounds(&answer.tool_rounds, &code_theme));
+		let assistant_message = Message {
+			id: message_id,
+			role: "assistant".to_string(),
+			content: answer.text.clone(),
+			model_name: model_name.to_string(),
+			parent_id,
+			provider_name: Some(provider_name.to_string()),
+			status: answer.status.to_string(),
+			latency_ms: Some(latency_ms),
+			prompt_tokens: answer.usage.map(|usage| usage.prompt_tokens),
+			completion_tokens: answer.usage.map(|usage| usage.completion_tokens),
+			blocks: (!blocks.is_empty()).then_some(MessageBlocks(blocks)),
+			attachments: Vec::new(),
+		};
+		save_message(&assistant_message, chat_id, data.clone()).await?;
+	}
 
-	Ok(answer)
+	// 5. Finalize chat (generate title or update timestamp)
+	match answer.status {
+		"ok" => {
+			finalize_chat(&generation, chat_id, &user_msg, &answer.text, data).await?;
+			Ok(answer.text)
+		}
+		"error" => {
+			update_chat_timestamp(chat_id, data).await?;
+			Err(answer.text)
+		}
+		_ => {
+			update_chat_timestamp(chat_id, data).await?;
+			Ok(answer.text)
+		}
+	}
 }
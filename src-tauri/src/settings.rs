@@ -14,6 +14,20 @@ pub struct Settings {
 	pub default_model: String,
 	pub default_provider: String,
 	pub code_theme: String,
+	/// Default system prompt for chats without their own
+	#[serde(default)]
+	pub system_prompt: Option<String>,
+	/// Whether models may call tools while answering
+	#[serde(default)]
+	pub tools_enabled: bool,
+	/// Files and folders the read_file tool may read
+	#[serde(default)]
+	pub approved_paths: Vec<String>,
+	/// Provider and model used to embed messages for semantic search, indexing is off while unset
+	#[serde(default)]
+	pub embedding_provider: Option<String>,
+	#[serde(default)]
+	pub embedding_model: Option<String>,
 }
 impl Settings {
 	pub fn load(settings_file: &PathBuf) -> Self {
@@ -24,6 +38,11 @@ impl Settings {
 					default_model: "claude-3-5-sonnet-latest".to_string(),
 					default_provider: "anthropic".to_string(),
 					code_theme: "base16-eighties.dark".to_string(),
+					system_prompt: None,
+					tools_enabled: false,
+					approved_paths: Vec::new(),
+					embedding_provider: None,
+					embedding_model: None,
 				};
 				let settings = serde_json::to_string(&default_settings).unwrap();
 				// Create directory if it doesn't exist
@@ -89,6 +108,12 @@ pub async fn apply_and_save_settings(new_settings: Settings, data: DataState<'_>
 		}
 	}
 
+	data.settings.system_prompt = new_settings.system_prompt.filter(|prompt| !prompt.trim().is_empty());
+	data.settings.tools_enabled = new_settings.tools_enabled;
+	data.settings.approved_paths = new_settings.approved_paths.into_iter().filter(|path| !path.trim().is_empty()).collect();
+	data.settings.embedding_provider = new_settings.embedding_provider.filter(|provider| !provider.trim().is_empty());
+	data.settings.embedding_model = new_settings.embedding_model.filter(|model| !model.trim().is_empty());
+
 	data.settings.save(&data.paths.settings_file);
 	Ok(())
 }
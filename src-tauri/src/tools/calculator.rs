@@ -0,0 +1,341 @@
+//! Evaluation of arithmetic expressions for the calculator tool.
+//! Supports + - * / % ^, parentheses, the constants pi and e and common functions like sqrt or ln.
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+	Number(f64),
+	Name(String),
+	Operator(char),
+	Open,
+	Close,
+	Comma,
+}
+
+fn tokenize(expression: &str) -> Result<Vec<Token>, String> {
+	let mut tokens = Vec::new();
+	let mut chars = expression.chars().peekable();
+	while let Some(&c) = chars.peek() {
+		match c {
+			c if c.is_whitespace() => {
+				chars.next();
+			}
+			'0'..='9' | '.' => {
+				let mut number = String::new();
+				while let Some(&c) = chars.peek() {
+					if c.is_ascii_digit() || c == '.' || c == '_' {
+						number.push(c);
+						chars.next();
+					} else if (c == 'e' || c == 'E') && !number.contains(['e', 'E']) {
+						// Exponent notation like 1.5e3, only if digits follow
+						let mut lookahead = chars.clone();
+						lookahead.next();
+						let sign = lookahead.peek().filter(|c| **c == '+' || **c == '-').copied();
+						if sign.is_some() {
+							lookahead.next();
+						}
+						if !lookahead.peek().is_some_and(|c| c.is_ascii_digit()) {
+							break;
+						}
+						number.push(c);
+						chars.next();
+						if let Some(sign) = sign {
+							number.push(sign);
+							chars.next();
+						}
+					} else {
+						break;
+					}
+				}
+				let value = number.replace('_', "").parse().map_err(|_| format!("Invalid number {}", number))?;
+				tokens.push(Token::Number(value));
+			}
+			c if c.is_alphabetic() => {
+				let mut name = String::new();
+				while let Some(&c) = chars.peek() {
+					if c.is_alphanumeric() || c == '_' {
+						name.push(c);
+						chars.next();
+					} else {
+						break;
+					}
+				}
+				tokens.push(Token::Name(name.to_lowercase()));
+			}
+			'+' | '-' | '*' | '/' | '%' | '^' => {
+				chars.next();
+				// ** is accepted as power operator
+				if c == '*' && chars.peek() == Some(&'*') {
+					chars.next();
+					tokens.push(Token::Operator('^'));
+				} else {
+					tokens.push(Token::Operator(c));
+				}
+			}
+			'(' => {
+				chars.next();
+				tokens.push(Token::Open);
+			}
+			')' => {
+				chars.next();
+				tokens.push(Token::Close);
+			}
+			',' => {
+				chars.next();
+				tokens.push(Token::Comma);
+			}
+			c => return Err(format!("Unexpected character {}", c)),
+		}
+	}
+	Ok(tokens)
+}
+
+/// Maximum nesting of parentheses, function calls, signs and exponents, keeps the recursion off the end of the stack
+const MAX_DEPTH: usize = 100;
+
+struct Parser {
+	tokens: Vec<Token>,
+	position: usize,
+	depth: usize,
+}
+
+impl Parser {
+	fn peek(&self) -> Option<&Token> {
+		self.tokens.get(self.position)
+	}
+
+	fn next(&mut self) -> Option<Token> {
+		let token = self.tokens.get(self.position).cloned();
+		self.position += 1;
+		token
+	}
+
+	fn expect(&mut self, expected: Token) -> Result<(), String> {
+		match self.next() {
+			Some(token) if token == expected => Ok(()),
+			Some(token) => Err(format!("Expected {:?}, found {:?}", expected, token)),
+			None => Err(format!("Expected {:?} at the end of the expression", expected)),
+		}
+	}
+
+	/// Parse a nested part of the expression, failing once it is nested too deeply
+	fn nested(&mut self, parse: fn(&mut Self) -> Result<f64, String>) -> Result<f64, String> {
+		if self.depth >= MAX_DEPTH {
+			return Err("Expression is nested too deeply".to_string());
+		}
+		self.depth += 1;
+		let value = parse(self);
+		self.depth -= 1;
+		value
+	}
+
+	/// Sums and differences
+	fn expression(&mut self) -> Result<f64, String> {
+		let mut value = self.term()?;
+		while let Some(Token::Operator(operator @ ('+' | '-'))) = self.peek().cloned() {
+			self.next();
+			let right = self.term()?;
+			value = if operator == '+' { value + right } else { value - right };
+		}
+		Ok(value)
+	}
+
+	/// Products, quotients and remainders
+	fn term(&mut self) -> Result<f64, String> {
+		let mut value = self.unary()?;
+		while let Some(Token::Operator(operator @ ('*' | '/' | '%'))) = self.peek().cloned() {
+			self.next();
+			let right = self.unary()?;
+			value = match operator {
+				'*' => value * right,
+				'/' if right == 0.0 => return Err("Division by zero".to_string()),
+				'/' => value / right,
+				_ if right == 0.0 => return Err("Division by zero".to_string()),
+				_ => value % right,
+			};
+		}
+		Ok(value)
+	}
+
+	/// Signs bind weaker than powers, -2^2 is -4
+	fn unary(&mut self) -> Result<f64, String> {
+		match self.peek() {
+			Some(Token::Operator('-')) => {
+				self.next();
+				Ok(-self.nested(Self::unary)?)
+			}
+			Some(Token::Operator('+')) => {
+				self.next();
+				self.nested(Self::unary)
+			}
+			_ => self.power(),
+		}
+	}
+
+	/// Powers are right associative, 2^3^2 is 2^9
+	fn power(&mut self) -> Result<f64, String> {
+		let base = self.primary()?;
+		if let Some(Token::Operator('^')) = self.peek() {
+			self.next();
+			let exponent = self.nested(Self::unary)?;
+			return Ok(base.powf(exponent));
+		}
+		Ok(base)
+	}
+
+	fn primary(&mut self) -> Result<f64, String> {
+		match self.next() {
+			Some(Token::Number(value)) => Ok(value),
+			Some(Token::Open) => {
+				let value = self.nested(Self::expression)?;
+				self.expect(Token::Close)?;
+				Ok(value)
+			}
+			Some(Token::Name(name)) => {
+				if self.peek() != Some(&Token::Open) {
+					return constant(&name);
+				}
+				self.next();
+				let mut arguments = vec![self.nested(Self::expression)?];
+				while self.peek() == Some(&Token::Comma) {
+					self.next();
+					arguments.push(self.nested(Self::expression)?);
+				}
+				self.expect(Token::Close)?;
+				function(&name, &arguments)
+			}
+			Some(token) => Err(format!("Unexpected {:?}", token)),
+			None => Err("Unexpected end of the expression".to_string()),
+		}
+	}
+}
+
+fn constant(name: &str) -> Result<f64, String> {
+	match name {
+		"pi" => Ok(std::f64::consts::PI),
+		"e" => Ok(std::f64::consts::E),
+		"tau" => Ok(std::f64::consts::TAU),
+		_ => Err(format!("Unknown constant {}", name)),
+	}
+}
+
+fn function(name: &str, arguments: &[f64]) -> Result<f64, String> {
+	let single = || match arguments {
+		[value] => Ok(*value),
+		_ => Err(format!("{} takes one argument", name)),
+	};
+	match name {
+		"sqrt" => Ok(single()?.sqrt()),
+		"abs" => Ok(single()?.abs()),
+		"ln" => Ok(single()?.ln()),
+		"log10" => Ok(single()?.log10()),
+		"log2" => Ok(single()?.log2()),
+		"log" => match arguments {
+			[value] => Ok(value.log10()),
+			[value, base] => Ok(value.log(*base)),
+			_ => Err("log takes a value and an optional base".to_string()),
+		},
+		"exp" => Ok(single()?.exp()),
+		"sin" => Ok(single()?.sin()),
+		"cos" => Ok(single()?.cos()),
+		"tan" => Ok(single()?.tan()),
+		"asin" => Ok(single()?.asin()),
+		"acos" => Ok(single()?.acos()),
+		"atan" => Ok(single()?.atan()),
+		"floor" => Ok(single()?.floor()),
+		"ceil" => Ok(single()?.ceil()),
+		"round" => Ok(single()?.round()),
+		"min" | "max" if !arguments.is_empty() => {
+			let values = arguments.iter().copied();
+			Ok(if name == "min" {
+				values.fold(f64::INFINITY, f64::min)
+			} else {
+				values.fold(f64::NEG_INFINITY, f64::max)
+			})
+		}
+		_ => Err(format!("Unknown function {}", name)),
+	}
+}
+
+/// Evaluate an arithmetic expression
+pub fn evaluate(expression: &str) -> Result<f64, String> {
+	let mut parser = Parser {
+		tokens: tokenize(expression)?,
+		position: 0,
+		depth: 0,
+	};
+	if parser.tokens.is_empty() {
+		return Err("The expression is empty".to_string());
+	}
+	let value = parser.expression()?;
+	if let Some(token) = parser.peek() {
+		return Err(format!("Unexpected {:?}", token));
+	}
+	if !value.is_finite() {
+		return Err("The result is not a finite number".to_string());
+	}
+	Ok(value)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn multiplies_before_adding() {
+		assert_eq!(evaluate("2 + 3 * 4"), Ok(14.0));
+		assert_eq!(evaluate("(2 + 3) * 4"), Ok(20.0));
+		assert_eq!(evaluate("10 - 4 - 3"), Ok(3.0));
+		assert_eq!(evaluate("7 % 4 * 2"), Ok(6.0));
+	}
+
+	#[test]
+	fn powers_are_right_associative() {
+		assert_eq!(evaluate("2 ^ 3 ^ 2"), Ok(512.0));
+		assert_eq!(evaluate("2 ** 3"), Ok(8.0));
+		assert_eq!(evaluate("2 * 3 ^ 2"), Ok(18.0));
+	}
+
+	#[test]
+	fn signs_bind_weaker_than_powers() {
+		assert_eq!(evaluate("-2 ^ 2"), Ok(-4.0));
+		assert_eq!(evaluate("(-2) ^ 2"), Ok(4.0));
+		assert_eq!(evaluate("2 ^ -1"), Ok(0.5));
+		assert_eq!(evaluate("--3"), Ok(3.0));
+		assert_eq!(evaluate("3 - -3"), Ok(6.0));
+	}
+
+	#[test]
+	fn rejects_division_by_zero() {
+		assert_eq!(evaluate("1 / 0"), Err("Division by zero".to_string()));
+		assert_eq!(evaluate("1 % (2 - 2)"), Err("Division by zero".to_string()));
+	}
+
+	#[test]
+	fn rejects_trailing_input() {
+		assert_eq!(evaluate("1 + 2 3"), Err("Unexpected Number(3.0)".to_string()));
+		assert_eq!(evaluate("(1 + 2))"), Err("Unexpected Close".to_string()));
+	}
+
+	#[test]
+	fn evaluates_constants_and_functions() {
+		assert_eq!(evaluate("sqrt(16) + max(1, 5, 3)"), Ok(9.0));
+		assert_eq!(evaluate("log(8, 2)"), Ok(3.0));
+		assert_eq!(evaluate("1.5e3"), Ok(1500.0));
+		assert_eq!(evaluate("PI"), Ok(std::f64::consts::PI));
+	}
+
+	#[test]
+	fn rejects_deeply_nested_expressions() {
+		let nested = format!("{}1{}", "(".repeat(10_000), ")".repeat(10_000));
+		assert_eq!(evaluate(&nested), Err("Expression is nested too deeply".to_string()));
+		assert_eq!(
+			evaluate(&format!("{}1", "-".repeat(10_000))),
+			Err("Expression is nested too deeply".to_string())
+		);
+		assert_eq!(
+			evaluate(&format!("{}1", "2^".repeat(10_000))),
+			Err("Expression is nested too deeply".to_string())
+		);
+		assert_eq!(evaluate(&format!("{}1{}", "(".repeat(50), ")".repeat(50))), Ok(1.0));
+	}
+}
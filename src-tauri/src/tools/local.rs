@@ -0,0 +1,140 @@
+//! Built-in tools that run locally: date and time, calculator, reading approved files and searching past chats
+
+use serde_json::{json, Value};
+use sqlx::SqlitePool;
+use tokio::fs;
+
+use super::calculator::evaluate;
+use super::{string_argument, Tool};
+use crate::db::{find_search_hits, MATCH_END, MATCH_START};
+use crate::types::SearchFilters;
+
+/// Largest file the read_file tool returns
+const MAX_READ_BYTES: u64 = 1024 * 1024;
+
+/// Default and maximum number of messages search_chats returns
+const DEFAULT_SEARCH_RESULTS: u32 = 10;
+const MAX_SEARCH_RESULTS: u32 = 50;
+
+/// Tools that need no connection to anything outside the app.
+/// `approved_paths` are the files and folders the user allowed models to read.
+pub fn local_tools(db_pool: SqlitePool, approved_paths: Vec<String>) -> Vec<Tool> {
+	vec![
+		Tool::new(
+			"current_datetime",
+			"Get the current local date, time and time zone of the user.",
+			json!({ "type": "object", "properties": {} }),
+			|_| async { Ok(current_datetime()) },
+		),
+		Tool::new(
+			"calculator",
+			"Evaluate an arithmetic expression. Supports + - * / % ^, parentheses, pi, e and the functions sqrt, abs, ln, log, exp, sin, cos, tan, floor, ceil, round, min and max.",
+			json!({
+				"type": "object",
+				"properties": { "expression": { "type": "string", "description": "Expression to evaluate, e.g. (3 + 4) * 2^10" } },
+				"required": ["expression"]
+			}),
+			|arguments| async move {
+				let expression = string_argument(&arguments, "expression")?;
+				evaluate(&expression).map(|value| value.to_string())
+			},
+		),
+		Tool::new(
+			"read_file",
+			"Read a text file from the user's computer. Only files the user approved in the settings can be read.",
+			json!({
+				"type": "object",
+				"properties": { "path": { "type": "string", "description": "Absolute path of the file" } },
+				"required": ["path"]
+			}),
+			move |arguments| {
+				let approved_paths = approved_paths.clone();
+				async move { read_file(&string_argument(&arguments, "path")?, &approved_paths).await }
+			},
+		),
+		search_chats_tool(db_pool),
+	]
+}
+
+/// Search the messages of all chats, also offered by the MCP server
+pub fn search_chats_tool(db_pool: SqlitePool) -> Tool {
+	Tool::new(
+		"search_chats",
+		"Search the user's past chats for messages and chat names containing all words of a query. Returns the best matches with the name of their chat.",
+		json!({
+			"type": "object",
+			"properties": {
+				"query": { "type": "string", "description": "Words to search for, case insensitive" },
+				"limit": { "type": "integer", "description": "Maximum number of results, 10 by default" }
+			},
+			"required": ["query"]
+		}),
+		move |arguments| {
+			let db_pool = db_pool.clone();
+			async move {
+				let query = string_argument(&arguments, "query")?;
+				let limit = arguments["limit"]
+					.as_u64()
+					.unwrap_or(DEFAULT_SEARCH_RESULTS as u64)
+					.clamp(1, MAX_SEARCH_RESULTS as u64) as u32;
+				search_chats(&query, limit, &db_pool).await
+			}
+		},
+	)
+}
+
+fn current_datetime() -> String {
+	let now = chrono::Local::now();
+	format!(
+		"{} ({})",
+		now.format("%A, %Y-%m-%d %H:%M:%S %:z"),
+		now.with_timezone(&chrono::Utc).format("%Y-%m-%d %H:%M:%S UTC")
+	)
+}
+
+/// Read a file if it is one of the approved files or inside an approved folder
+async fn read_file(path: &str, approved_paths: &[String]) -> Result<String, String> {
+	let path = fs::canonicalize(path).await.map_err(|e| format!("Could not open {}: {}", path, e))?;
+	let mut approved = false;
+	for approved_path in approved_paths {
+		if fs::canonicalize(approved_path).await.is_ok_and(|approved_path| path.starts_with(approved_path)) {
+			approved = true;
+			break;
+		}
+	}
+	if !approved {
+		return Err(format!("Reading {} was not approved by the user", path.display()));
+	}
+
+	let size = fs::metadata(&path).await.map_err(|e| e.to_string())?.len();
+	if size > MAX_READ_BYTES {
+		return Err(format!("{} is larger than {} KB", path.display(), MAX_READ_BYTES / 1024));
+	}
+	let bytes = fs::read(&path).await.map_err(|e| format!("Could not read {}: {}", path.display(), e))?;
+	String::from_utf8(bytes).map_err(|_| format!("{} is not a text file", path.display()))
+}
+
+/// Search the messages and chat names of all chats, best matches first
+async fn search_chats(query: &str, limit: u32, db_pool: &SqlitePool) -> Result<String, String> {
+	let filters = SearchFilters {
+		limit: Some(limit),
+		..SearchFilters::default()
+	};
+	let hits = find_search_hits(query, &filters, db_pool).await?;
+	if hits.is_empty() {
+		return Ok("No messages found".to_string());
+	}
+	let results: Vec<Value> = hits
+		.into_iter()
+		.map(|hit| {
+			json!({
+				"chat_id": hit.chat_id,
+				"chat": hit.chat_display_name,
+				"message_id": hit.message_id,
+				"role": hit.role,
+				"text": hit.snippet.replace([MATCH_START, MATCH_END], "")
+			})
+		})
+		.collect();
+	serde_json::to_string_pretty(&results).map_err(|e| e.to_string())
+}
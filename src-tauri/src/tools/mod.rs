@@ -0,0 +1,142 @@
+//! Tools models can call while answering.
+//! A tool has a name, a JSON schema of its arguments and an async handler that returns the output as text.
+//! Built-in tools are in `local`, tools of MCP servers are added by the `mcp` module.
+
+mod calculator;
+mod local;
+
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+
+use serde_json::Value;
+
+use crate::data::DataState;
+use crate::db::get_enabled_mcp_servers;
+use crate::llm_providers::{ToolCall, ToolOutput, ToolSpec};
+use crate::mcp::mcp_tools;
+
+pub use local::{local_tools, search_chats_tool};
+
+/// Future returned by a tool handler, resolving to the output or an error message
+pub type ToolFuture = Pin<Box<dyn Future<Output = Result<String, String>> + Send>>;
+
+/// Handler of a tool, called with the parsed arguments
+pub type ToolHandler = Arc<dyn Fn(Value) -> ToolFuture + Send + Sync>;
+
+#[derive(Clone)]
+pub struct Tool {
+	pub name: String,
+	pub description: String,
+	/// JSON schema of the arguments
+	pub parameters: Value,
+	handler: ToolHandler,
+}
+
+impl Tool {
+	pub fn new<F, Fut>(name: &str, description: &str, parameters: Value, handler: F) -> Self
+	where
+		F: Fn(Value) -> Fut + Send + Sync + 'static,
+		Fut: Future<Output = Result<String, String>> + Send + 'static,
+	{
+		Self {
+			name: name.to_string(),
+			description: description.to_string(),
+			parameters,
+			handler: Arc::new(move |arguments| Box::pin(handler(arguments))),
+		}
+	}
+
+	/// Definition sent to the provider
+	pub fn spec(&self) -> ToolSpec {
+		ToolSpec {
+			name: self.name.clone(),
+			description: self.description.clone(),
+			parameters: self.parameters.clone(),
+		}
+	}
+}
+
+/// Get a required string argument
+pub fn string_argument(arguments: &Value, name: &str) -> Result<String, String> {
+	arguments[name]
+		.as_str()
+		.map(|value| value.to_string())
+		.ok_or_else(|| format!("Missing argument {}", name))
+}
+
+/// Tools available to a generation, by name
+#[derive(Clone, Default)]
+pub struct ToolRegistry {
+	tools: BTreeMap<String, Tool>,
+}
+
+impl ToolRegistry {
+	/// Add a tool, replacing one with the same name
+	pub fn register(&mut self, tool: Tool) {
+		self.tools.insert(tool.name.clone(), tool);
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.tools.is_empty()
+	}
+
+	pub fn specs(&self) -> Vec<ToolSpec> {
+		self.tools.values().map(Tool::spec).collect()
+	}
+
+	/// Run a tool call. Failures are returned as error output so the model can react to them.
+	pub async fn run(&self, call: &ToolCall) -> ToolOutput {
+		// Some providers send no arguments at all for tools without parameters
+		let arguments = match call.arguments.trim() {
+			"" => "{}",
+			arguments => arguments,
+		};
+		let result = match self.tools.get(&call.name) {
+			Some(tool) => match serde_json::from_str::<Value>(arguments) {
+				Ok(arguments) => (tool.handler)(arguments).await,
+				Err(e) => Err(format!("Invalid arguments: {}", e)),
+			},
+			None => Err(format!("Unknown tool {}", call.name)),
+		};
+		if let Err(e) = &result {
+			log::warn!("Tool call {} failed: {}", call.name, e);
+		}
+		let (content, is_error) = match result {
+			Ok(content) => (content, false),
+			Err(e) => (e, true),
+		};
+		ToolOutput {
+			call_id: call.id.clone(),
+			name: call.name.clone(),
+			content,
+			is_error,
+		}
+	}
+}
+
+/// Tools the models may call: the local tools and the tools of the enabled MCP servers.
+/// Empty if tools are disabled in the settings.
+pub async fn available_tools(data: DataState<'_>) -> ToolRegistry {
+	let (db_pool, approved_paths, window) = {
+		let data = data.0.lock().await;
+		if !data.settings.tools_enabled {
+			return ToolRegistry::default();
+		}
+		(data.db_pool.clone(), data.settings.approved_paths.clone(), data.window.clone())
+	};
+	let mut registry = ToolRegistry::default();
+	for tool in local_tools(db_pool.clone(), approved_paths) {
+		registry.register(tool);
+	}
+	match get_enabled_mcp_servers(&db_pool).await {
+		Ok(servers) => {
+			for tool in mcp_tools(&servers, &window).await {
+				registry.register(tool);
+			}
+		}
+		Err(e) => log::error!("{}", e),
+	}
+	registry
+}
@@ -1,17 +1,80 @@
 use core::fmt;
+use std::collections::BTreeMap;
 
 use derive_more::{Deref, DerefMut};
 use serde::{Deserialize, Serialize};
 use specta::Type;
 use sqlx::FromRow;
 
+use crate::errors::ConfigError;
+
 #[derive(Serialize, Deserialize, Debug, Type, Clone)]
 pub struct Message {
 	pub id: String,
 	pub role: String,
 	pub content: String,
 	pub model_name: String,
+	/// Message this one follows in the chat's message tree, `None` for the first message
+	pub parent_id: Option<String>,
+	/// Provider the message was sent to, `None` for messages from before it was stored
+	pub provider_name: Option<String>,
+	/// "ok", "error" when the provider request failed, or "interrupted" when the generation was cancelled
+	pub status: String,
+	/// Milliseconds the provider took to answer, `None` for prompts
+	pub latency_ms: Option<u32>,
+	/// Tokens of the request that produced this answer, `None` if the provider did not report them
+	pub prompt_tokens: Option<u32>,
+	pub completion_tokens: Option<u32>,
 	pub blocks: Option<MessageBlocks>,
+	/// Files attached to a prompt
+	#[serde(default)]
+	pub attachments: Vec<Attachment>,
+}
+
+/// A file attached to a message
+#[derive(Serialize, Deserialize, Debug, Type, FromRow, Clone)]
+pub struct Attachment {
+	pub id: String,
+	/// "image", "text" for plain text and code, or "pdf"
+	pub kind: String,
+	pub file_name: String,
+	pub mime_type: String,
+	pub size_bytes: u32,
+	/// Path of the stored file, relative to the app data directory
+	#[serde(skip)]
+	pub path: String,
+	/// Text extracted from text, code and PDF files, only loaded while the message is sent to a model
+	#[serde(skip)]
+	#[sqlx(default)]
+	pub content: Option<String>,
+	/// Contents of the file, only loaded while the message is sent to a model
+	#[serde(skip)]
+	#[sqlx(skip)]
+	pub data: Option<Vec<u8>>,
+}
+
+impl Attachment {
+	pub fn is_image(&self) -> bool {
+		self.kind == "image"
+	}
+}
+
+impl Message {
+	/// Content sent to the model: the text of the attached files, each between file delimiters, followed by the message
+	pub fn prompt_content(&self) -> String {
+		let files: Vec<String> = self
+			.attachments
+			.iter()
+			.filter_map(|attachment| {
+				let content = attachment.content.as_ref()?;
+				Some(format!("<file name=\"{}\">\n{}\n</file>", attachment.file_name, content.trim_end()))
+			})
+			.collect();
+		if files.is_empty() {
+			return self.content.clone();
+		}
+		format!("{}\n\n{}", files.join("\n\n"), self.content)
+	}
 }
 
 #[derive(Deref, Serialize)]
@@ -43,6 +106,74 @@ impl fmt::Display for MessageBlock {
 #[derive(Serialize, Deserialize, Debug, Type, Clone, Deref, DerefMut)]
 pub struct MessageBlocks(pub Vec<MessageBlock>);
 
+/// Payload of the `messageDelta` event, emitted while an assistant answer is streamed
+#[derive(Serialize, Deserialize, Debug, Type, Clone)]
+pub struct MessageDelta {
+	pub chat_id: String,
+	/// Id the assistant message will be saved under once the stream completes
+	pub message_id: String,
+	/// Text received since the previous event
+	pub delta: String,
+	/// Rendered blocks of the partial answer, only set when it was re-rendered
+	pub blocks: Option<MessageBlocks>,
+}
+
+/// Payload of the `toolActivity` event, emitted when a model calls a tool and again when the tool has finished
+#[derive(Serialize, Deserialize, Debug, Type, Clone)]
+pub struct ToolActivity {
+	pub chat_id: String,
+	/// Id the assistant message will be saved under
+	pub message_id: String,
+	pub tool_name: String,
+	/// Arguments as JSON object
+	pub arguments: String,
+	/// Output of the tool, `None` while it is running
+	pub output: Option<String>,
+	pub is_error: bool,
+}
+
+/// Payload of the `toolApproval` event, emitted when a model wants to call a tool of an MCP server.
+/// The call runs once it is approved with `answer_tool_approval`.
+#[derive(Serialize, Deserialize, Debug, Type, Clone)]
+pub struct ToolApprovalRequest {
+	pub request_id: String,
+	pub server_name: String,
+	pub tool_name: String,
+	/// Arguments as JSON object
+	pub arguments: String,
+}
+
+/// Payload of the `contextTruncated` event, emitted when old messages did not fit into the context window
+#[derive(Serialize, Deserialize, Debug, Type, Clone)]
+pub struct ContextTruncated {
+	pub chat_id: String,
+	/// Number of messages that were not sent to the model
+	pub excluded_messages: u32,
+	/// Estimated number of prompt tokens that were sent
+	pub estimated_tokens: u32,
+}
+
+/// Payload of the `providerRetry` event, emitted when a failed request is about to be retried
+#[derive(Serialize, Deserialize, Debug, Type, Clone)]
+pub struct ProviderRetry {
+	pub chat_id: String,
+	pub provider_name: String,
+	/// Number of the attempt that failed, starting at 1
+	pub attempt: u32,
+	pub max_attempts: u32,
+	/// Milliseconds until the next attempt
+	pub delay_ms: u32,
+	pub reason: String,
+}
+
+/// Payload of the `modelDeprecated` event, emitted when a chat uses a model its provider no longer lists
+#[derive(Serialize, Deserialize, Debug, Type, Clone)]
+pub struct ModelDeprecated {
+	pub chat_id: String,
+	pub provider_name: String,
+	pub model_name: String,
+}
+
 #[derive(Serialize, Deserialize, Debug, Type, FromRow, Clone)]
 pub struct Model {
 	pub provider_name: String,
@@ -51,6 +182,16 @@ pub struct Model {
 	pub show: bool,
 	pub max_tokens: u32,
 	pub context_window: u32,
+	/// Price in USD per million prompt tokens
+	pub input_price: Option<f64>,
+	/// Price in USD per million completion tokens
+	pub output_price: Option<f64>,
+	/// Set when the provider no longer lists the model
+	#[serde(default)]
+	pub deprecated: bool,
+	/// Whether the model accepts images
+	#[serde(default)]
+	pub supports_vision: bool,
 }
 
 #[derive(Serialize, Deserialize, Debug, Type, FromRow, Clone, Deref)]
@@ -67,6 +208,39 @@ pub struct ModelUpdate {
 	pub model: Model,
 }
 
+/// A model offered by a provider, as listed by its model endpoint
+#[derive(Serialize, Deserialize, Debug, Type, Clone)]
+pub struct AvailableModel {
+	pub model_name: String,
+	pub display_name: Option<String>,
+	/// Context length, only reported by some providers
+	pub context_window: Option<u32>,
+	/// Whether the model is already in the models table
+	pub imported: bool,
+}
+
+/// A model of a provider to send a prompt to
+#[derive(Serialize, Deserialize, Debug, Type, Clone)]
+pub struct ModelTarget {
+	pub provider_name: String,
+	pub model_name: String,
+}
+
+/// Token usage and cost of a group of answers
+#[derive(Serialize, Deserialize, Debug, Type, FromRow, Clone)]
+pub struct UsageSummary {
+	/// Chat id, provider name or day (YYYY-MM-DD) the answers are grouped by
+	pub label: String,
+	/// Number of answers with reported usage
+	pub messages: u32,
+	pub prompt_tokens: u32,
+	pub completion_tokens: u32,
+	/// Cost in USD
+	pub cost: f64,
+	/// Answers whose model has no price set, they are not included in the cost
+	pub unpriced_messages: u32,
+}
+
 #[derive(Serialize, Deserialize, Type, Debug, FromRow, Clone)]
 pub struct Chat {
 	pub id: String,
@@ -77,3 +251,277 @@ pub struct Chat {
 
 #[derive(Serialize, Deserialize, Type, Debug, Deref)]
 pub struct Chats(pub Vec<Chat>);
+
+/// Per-chat generation parameters, `None` falls back to the defaults of the chat's model
+#[derive(Serialize, Deserialize, Type, Debug, Clone, Default)]
+pub struct ChatParameters {
+	pub temperature: Option<f32>,
+	pub top_p: Option<f32>,
+	pub max_tokens: Option<u32>,
+	pub stop_sequences: Option<Vec<String>>,
+	pub seed: Option<u32>,
+	pub frequency_penalty: Option<f32>,
+	pub presence_penalty: Option<f32>,
+}
+
+impl ChatParameters {
+	/// Check that all set parameters are within the ranges accepted by the providers
+	pub fn validate(&self) -> Result<(), ConfigError> {
+		let out_of_range = |setting: &str, value: f32, min: f32, max: f32| -> Result<(), ConfigError> {
+			if value < min || value > max {
+				return Err(ConfigError::InvalidValue {
+					setting: setting.to_string(),
+					reason: format!("{} is not between {} and {}", value, min, max),
+				});
+			}
+			Ok(())
+		};
+		if let Some(temperature) = self.temperature {
+			out_of_range("temperature", temperature, 0.0, 2.0)?;
+		}
+		if let Some(top_p) = self.top_p {
+			out_of_range("top_p", top_p, 0.0, 1.0)?;
+		}
+		if let Some(frequency_penalty) = self.frequency_penalty {
+			out_of_range("frequency_penalty", frequency_penalty, -2.0, 2.0)?;
+		}
+		if let Some(presence_penalty) = self.presence_penalty {
+			out_of_range("presence_penalty", presence_penalty, -2.0, 2.0)?;
+		}
+		if self.max_tokens == Some(0) {
+			return Err(ConfigError::InvalidValue {
+				setting: "max_tokens".to_string(),
+				reason: "must be greater than 0".to_string(),
+			});
+		}
+		Ok(())
+	}
+
+	/// Names of the set parameters that are sent as extra request fields, which only OpenAI-compatible APIs accept
+	pub fn extra_parameters(&self) -> Vec<&'static str> {
+		[
+			("stop_sequences", self.stop_sequences.is_some()),
+			("seed", self.seed.is_some()),
+			("frequency_penalty", self.frequency_penalty.is_some()),
+			("presence_penalty", self.presence_penalty.is_some()),
+		]
+		.into_iter()
+		.filter(|(_, set)| *set)
+		.map(|(name, _)| name)
+		.collect()
+	}
+}
+
+/// A Model Context Protocol server, started over stdio
+#[derive(Serialize, Deserialize, Debug, Type, Clone, PartialEq)]
+pub struct McpServer {
+	pub name: String,
+	pub command: String,
+	#[serde(default)]
+	pub args: Vec<String>,
+	/// Environment variables set for the server process
+	#[serde(default)]
+	pub env: BTreeMap<String, String>,
+	pub enabled: bool,
+}
+
+/// Tools, resources and prompts an MCP server offers
+#[derive(Serialize, Deserialize, Debug, Type, Clone, Default)]
+pub struct McpCapabilities {
+	pub tools: Vec<McpTool>,
+	pub resources: Vec<McpResource>,
+	pub prompts: Vec<McpPrompt>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Type, Clone)]
+pub struct McpTool {
+	pub name: String,
+	pub description: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Type, Clone)]
+pub struct McpResource {
+	pub uri: String,
+	pub name: String,
+	pub description: Option<String>,
+	pub mime_type: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Type, Clone)]
+pub struct McpPrompt {
+	pub name: String,
+	pub description: Option<String>,
+	pub arguments: Vec<McpPromptArgument>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Type, Clone)]
+pub struct McpPromptArgument {
+	pub name: String,
+	pub description: Option<String>,
+	pub required: bool,
+}
+
+/// Filters of a chat search, filters that are not set match everything
+#[derive(Serialize, Deserialize, Debug, Type, Clone, Default)]
+pub struct SearchFilters {
+	pub model_name: Option<String>,
+	/// First day to include, as YYYY-MM-DD
+	pub from_date: Option<String>,
+	/// Last day to include, as YYYY-MM-DD
+	pub to_date: Option<String>,
+	/// `Some(false)` only searches active chats, `Some(true)` only archived ones
+	pub archived: Option<bool>,
+	pub limit: Option<u32>,
+}
+
+/// A message or chat name matching a search, best matches first
+#[derive(Serialize, Deserialize, Debug, Type, Clone, FromRow)]
+pub struct SearchHit {
+	pub chat_id: String,
+	pub chat_display_name: String,
+	/// `None` if the name of the chat matched
+	pub message_id: Option<String>,
+	pub role: Option<String>,
+	pub model_name: Option<String>,
+	/// Part of the text around the match, matched words are wrapped in `<mark>`
+	pub snippet: String,
+	pub created_at: Option<String>,
+	pub archived: bool,
+}
+
+/// A chat similar to a query or another chat, most similar first
+#[derive(Serialize, Deserialize, Debug, Type, Clone)]
+pub struct SimilarChat {
+	pub chat_id: String,
+	pub chat_display_name: String,
+	/// The message of the chat closest to the query
+	pub message_id: String,
+	pub role: String,
+	/// Beginning of that message
+	pub snippet: String,
+	/// Cosine similarity between the message and the query, from -1 to 1
+	pub similarity: f32,
+	pub created_at: Option<String>,
+	pub archived: bool,
+}
+
+/// Progress of embedding the messages with the configured embedding model
+#[derive(Serialize, Deserialize, Debug, Type, Clone)]
+pub struct EmbeddingStatus {
+	pub provider_name: Option<String>,
+	pub model_name: Option<String>,
+	pub indexed_messages: u32,
+	pub total_messages: u32,
+}
+
+/// A knowledge base of local documents that can be attached to chats
+#[derive(Serialize, Deserialize, Debug, Type, Clone, FromRow)]
+pub struct Collection {
+	pub id: String,
+	pub name: String,
+	pub embedding_provider: String,
+	pub embedding_model: String,
+	pub document_count: u32,
+	pub chunk_count: u32,
+	pub created_at: Option<String>,
+}
+
+/// A document of a collection
+#[derive(Serialize, Deserialize, Debug, Type, Clone, FromRow)]
+pub struct CollectionDocument {
+	pub id: String,
+	pub collection_id: String,
+	/// Absolute path the document was read from
+	pub path: String,
+	pub file_name: String,
+	pub mime_type: String,
+	pub size_bytes: u32,
+	pub chunk_count: u32,
+	pub created_at: Option<String>,
+}
+
+/// File format of exported chats
+#[derive(Serialize, Deserialize, Debug, Type, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+	/// The active branch with the raw message contents
+	Markdown,
+	/// All messages with their blocks and metadata, can be imported again
+	Json,
+	/// The active branch as standalone page with the rendered blocks
+	Html,
+}
+
+/// Chats in Byok's JSON export format
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ChatArchive {
+	/// Always "byok", tells the importer the format apart from other exports
+	pub format: String,
+	pub version: u32,
+	pub exported_at: String,
+	pub chats: Vec<ExportedChat>,
+}
+
+/// A chat with all versions of its messages
+#[derive(Serialize, Deserialize, Debug, Clone, FromRow)]
+pub struct ExportedChat {
+	pub id: String,
+	pub display_name: String,
+	pub model: Option<String>,
+	pub archived: bool,
+	pub system_prompt: Option<String>,
+	pub creation_date: Option<String>,
+	pub last_updated: Option<String>,
+	pub active_message_id: Option<String>,
+	#[sqlx(skip)]
+	pub parameters: Option<ChatParameters>,
+	/// Messages in the order they were written, the tree is kept through `parent_id`
+	#[sqlx(skip)]
+	pub messages: Vec<ExportedMessage>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, FromRow)]
+pub struct ExportedMessage {
+	pub id: String,
+	pub parent_id: Option<String>,
+	pub role: String,
+	pub content: String,
+	pub status: String,
+	pub provider_name: Option<String>,
+	pub model_name: String,
+	pub latency_ms: Option<u32>,
+	pub prompt_tokens: Option<u32>,
+	pub completion_tokens: Option<u32>,
+	pub created_at: Option<String>,
+	#[sqlx(skip)]
+	pub blocks: Vec<MessageBlock>,
+	#[sqlx(skip)]
+	pub attachments: Vec<ExportedAttachment>,
+}
+
+/// An attached file, images are embedded so the export does not depend on the app data directory
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ExportedAttachment {
+	pub id: String,
+	pub kind: String,
+	pub file_name: String,
+	pub mime_type: String,
+	pub size_bytes: u32,
+	/// Text extracted from text, code and PDF files
+	pub content: Option<String>,
+	/// Base64 encoded contents of images
+	pub data: Option<String>,
+}
+
+/// Payload of the `importProgress` event, emitted after every chat of an import. The finished import returns the final counts.
+#[derive(Serialize, Deserialize, Debug, Type, Clone, Default)]
+pub struct ImportProgress {
+	/// "ChatGPT", "Claude" or "Byok"
+	pub source: String,
+	/// Number of chats in the file
+	pub total: u32,
+	pub imported: u32,
+	/// Chats that were imported before or contain no messages
+	pub skipped: u32,
+	pub failed: u32,
+}
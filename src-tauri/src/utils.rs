@@ -5,7 +5,9 @@ use syntect::html::{styled_line_to_highlighted_html, IncludeBackground};
 use syntect::parsing::SyntaxSet;
 use syntect::util::LinesWithEndings;
 
-use crate::types::{MessageBlock, MessageBlocks};
+use crate::knowledge::Source;
+use crate::llm_providers::ToolRound;
+use crate::types::{Attachment, MessageBlock, MessageBlocks};
 
 pub async fn render_message(message: &str, code_theme: &str) -> MessageBlocks {
 	let mut message_blocks: MessageBlocks = MessageBlocks(vec![]);
@@ -75,6 +77,90 @@ pub async fn render_message(message: &str, code_theme: &str) -> MessageBlocks {
 	return message_blocks;
 }
 
+/// Image blocks for the attached images of a message, the images are loaded through the `attachment` protocol
+pub fn render_attachments(attachments: &[Attachment]) -> Vec<MessageBlock> {
+	attachments
+		.iter()
+		.filter(|attachment| attachment.is_image())
+		.map(|attachment| {
+			let stored_name = attachment.path.rsplit('/').next().unwrap_or_default();
+			let file_name = attachment
+				.file_name
+				.replace('&', "&amp;")
+				.replace('"', "&quot;")
+				.replace('<', "&lt;")
+				.replace('>', "&gt;");
+			MessageBlock {
+				id: None,
+				type_: "image".to_string(),
+				language: None,
+				raw_content: attachment.id.clone(),
+				rendered_content: format!(
+					"<img src=\"{}\" alt=\"{}\" title=\"{}\" class=\"max-h-96 rounded-md\">",
+					attachment_url(stored_name),
+					file_name,
+					file_name
+				),
+				copied: Some(false),
+			}
+		})
+		.collect()
+}
+
+/// Blocks for the tool calls an answer made, each call is followed by its output.
+/// The language of the blocks is the name of the tool, arguments and JSON outputs are highlighted as JSON.
+pub fn render_tool_rounds(rounds: &[ToolRound], code_theme: &str) -> Vec<MessageBlock> {
+	let render = |type_: &str, name: &str, content: String| {
+		let (content, language) = match serde_json::from_str::<serde_json::Value>(&content) {
+			Ok(value) if value.is_object() || value.is_array() => (serde_json::to_string_pretty(&value).unwrap_or(content), "json"),
+			_ => (content, "txt"),
+		};
+		MessageBlock {
+			id: None,
+			type_: type_.to_string(),
+			language: Some(name.to_string()),
+			rendered_content: highlight_code(&content, language, code_theme).unwrap_or_else(|_| escape_html_tags(content.clone())),
+			raw_content: content,
+			copied: Some(false),
+		}
+	};
+	let mut blocks = Vec::new();
+	for round in rounds {
+		for call in &round.calls {
+			blocks.push(render("tool_call", &call.name, call.arguments.clone()));
+			if let Some(output) = round.outputs.iter().find(|output| output.call_id == call.id) {
+				blocks.push(render("tool_result", &output.name, output.model_content()));
+			}
+		}
+	}
+	blocks
+}
+
+/// Blocks for the document chunks an answer is based on, the language is the citation number, file name and part of the file
+pub fn render_sources(sources: &[Source], code_theme: &str) -> Vec<MessageBlock> {
+	sources
+		.iter()
+		.enumerate()
+		.map(|(index, source)| MessageBlock {
+			id: None,
+			type_: "source".to_string(),
+			language: Some(format!("[{}] {}, part {}", index + 1, source.file_name, source.chunk_position + 1)),
+			rendered_content: highlight_code(&source.content, "txt", code_theme).unwrap_or_else(|_| escape_html_tags(source.content.clone())),
+			raw_content: source.content.clone(),
+			copied: Some(false),
+		})
+		.collect()
+}
+
+/// Address the webview loads a stored attachment from, Windows only supports custom protocols as http subdomains
+fn attachment_url(stored_name: &str) -> String {
+	if cfg!(target_os = "windows") {
+		format!("http://attachment.localhost/{}", stored_name)
+	} else {
+		format!("attachment://localhost/{}", stored_name)
+	}
+}
+
 pub fn highlight_code(code: &str, language: &str, code_theme: &str) -> Result<String, String> {
 	let ps: SyntaxSet = SyntaxSet::load_defaults_newlines();
 	let ts: ThemeSet = ThemeSet::load_defaults();